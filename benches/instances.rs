@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: MIT
+// Instance benchmark: compiles an NPC behavior script once and instantiates it 1000 times,
+// reporting the memory the instances hold and the time taken to make and tick them. Run
+// with `cargo bench --bench instances`. Memory is counted by a global allocator wrapper.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use questicle::output::Capture;
+use questicle::value::Value;
+use questicle::{CompiledScript, Host, Interpreter};
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+const INSTANCES: usize = 1000;
+
+const BEHAVIOR: &str = r#"let hp: number = 20;
+let mood: string = "idle";
+let seen: list<string> = [];
+fn tick(dt: number) -> string {
+  if (hp < 10) {
+    mood = "flee";
+  } else {
+    if (len(seen) > 2) { mood = "chat"; } else { mood = "wander"; }
+  }
+  return mood;
+}
+fn notice(who: string) -> number {
+  seen = push(seen, who);
+  return len(seen);
+}
+fn hurt(amount: number) -> number {
+  hp = hp - amount;
+  if (hp < 0) { hp = 0; }
+  return hp;
+}
+"#;
+
+fn kib(bytes: usize) -> f64 {
+    bytes as f64 / 1024.0
+}
+
+fn main() {
+    let before = LIVE.load(Ordering::Relaxed);
+    let script = CompiledScript::compile(BEHAVIOR).expect("behavior compiles");
+    let compiled = LIVE.load(Ordering::Relaxed) - before;
+
+    let started = Instant::now();
+    let mut npcs = Vec::with_capacity(INSTANCES);
+    for _ in 0..INSTANCES {
+        let mut interp = Interpreter::with_host(Host::default());
+        interp.set_output(Capture::default());
+        npcs.push(script.instantiate_in(interp).expect("behavior runs"));
+    }
+    let made = started.elapsed();
+    let instances = LIVE.load(Ordering::Relaxed) - before - compiled;
+
+    let started = Instant::now();
+    for (i, npc) in npcs.iter_mut().enumerate() {
+        npc.call("notice", vec![Value::String(format!("npc{i}"))])
+            .expect("notice runs");
+        npc.call("hurt", vec![Value::Number((i % 15) as f64)])
+            .expect("hurt runs");
+        npc.call("tick", vec![Value::Number(0.016)])
+            .expect("tick runs");
+    }
+    let ticked = started.elapsed();
+
+    println!("compiled script   {:>10.1} KiB", kib(compiled));
+    println!(
+        "{} instances    {:>10.1} KiB ({:.2} KiB each)",
+        INSTANCES,
+        kib(instances),
+        kib(instances) / INSTANCES as f64
+    );
+    println!(
+        "instantiate       {:>10.2} ms ({:.1} us each)",
+        made.as_secs_f64() * 1000.0,
+        made.as_secs_f64() * 1e6 / INSTANCES as f64
+    );
+    println!(
+        "tick all          {:>10.2} ms",
+        ticked.as_secs_f64() * 1000.0
+    );
+}
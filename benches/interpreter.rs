@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: MIT
+// Interpreter benchmarks: loop-heavy scripts, run with `cargo bench --bench interpreter`.
+// Each script is parsed once and run several times; the best run is reported, which is
+// steadier than the mean on a busy machine. Scripts run both as parsed, with variables
+// resolved to slots, and unresolved, with every variable looked up by name, to show what
+// resolving gains. Pass a name to run only matching scripts.
+use std::time::{Duration, Instant};
+
+use questicle::ast::Program;
+use questicle::output::Capture;
+use questicle::{Host, Interpreter, Parser};
+
+const RUNS: usize = 5;
+
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "nested_loops",
+        r#"fn grid(n: number) -> number {
+  let total: number = 0;
+  let y: number = 0;
+  while (y < n) {
+    let x: number = 0;
+    while (x < n) {
+      total = total + x * y % 7;
+      x = x + 1;
+    }
+    y = y + 1;
+  }
+  return total;
+}
+print(grid(300));
+"#,
+    ),
+    (
+        "for_in_list",
+        r#"fn sum_squares(items: list<number>) -> number {
+  let total: number = 0;
+  for (i in items) {
+    for (j in [1, 2, 3, 4]) {
+      total = total + i * j;
+    }
+  }
+  return total;
+}
+let items: list<number> = [];
+let i: number = 0;
+while (i < 5000) {
+  items = push(items, i);
+  i = i + 1;
+}
+let rounds: number = 0;
+while (rounds < 5) {
+  sum_squares(items);
+  rounds = rounds + 1;
+}
+print(sum_squares(items));
+"#,
+    ),
+    (
+        "closures",
+        r#"fn counter() -> fn() -> number {
+  let n: number = 0;
+  return fn() -> number {
+    n = n + 1;
+    return n;
+  };
+}
+let next: fn() -> number = counter();
+let last: number = 0;
+while (last < 50000) {
+  last = next();
+}
+print(last);
+"#,
+    ),
+    (
+        "fib",
+        r#"fn fib(n: number) -> number {
+  if (n < 2) { return n; }
+  return fib(n - 1) + fib(n - 2);
+}
+print(fib(20));
+"#,
+    ),
+];
+
+// Best time of RUNS runs of `program`, each in a fresh interpreter
+fn best_of(program: &Program) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut interp = Interpreter::with_host(Host::default());
+        interp.set_output(Capture::default());
+        let started = Instant::now();
+        interp.run(program).expect("benchmark runs");
+        best = best.min(started.elapsed());
+    }
+    best
+}
+
+fn main() {
+    let only = std::env::args().skip(1).find(|a| !a.starts_with('-'));
+    println!(
+        "{:<14} {:>12} {:>12} {:>8}",
+        "script", "by name ms", "resolved ms", "speedup"
+    );
+    for (name, src) in SCRIPTS {
+        if only.as_deref().is_some_and(|o| !name.contains(o)) {
+            continue;
+        }
+        let resolved = Parser::new(src).parse_program().expect("benchmark parses");
+        let unresolved = Parser::new(src)
+            .parse_unresolved()
+            .expect("benchmark parses");
+        let by_name = best_of(&unresolved);
+        let slots = best_of(&resolved);
+        println!(
+            "{:<14} {:>12.2} {:>12.2} {:>7.2}x",
+            name,
+            by_name.as_secs_f64() * 1000.0,
+            slots.as_secs_f64() * 1000.0,
+            by_name.as_secs_f64() / slots.as_secs_f64()
+        );
+    }
+}
@@ -30,8 +30,17 @@ export async function activate(context: vscode.ExtensionContext) {
 
     const clientOptions: LanguageClientOptions = {
         documentSelector: [{ language: 'questicle', scheme: 'file' }],
+        initializationOptions: {
+            strict: config.get<boolean>('strict', false),
+            inlayHints: {
+                variableTypes: config.get<boolean>('inlayHints.variableTypes', true),
+                returnTypes: config.get<boolean>('inlayHints.returnTypes', true),
+                parameterNames: config.get<boolean>('inlayHints.parameterNames', true)
+            }
+        },
         synchronize: {
-            fileEvents: vscode.workspace.createFileSystemWatcher('**/*.qk')
+            configurationSection: 'questicle',
+            fileEvents: vscode.workspace.createFileSystemWatcher('**/{*.qk,*.qkd,qklint.json}')
         }
     };
 
@@ -85,6 +94,43 @@ export async function activate(context: vscode.ExtensionContext) {
         await vscode.commands.executeCommand('editor.action.formatDocument');
     });
     context.subscriptions.push(fmtCmd);
+
+    // Find the emit/on sites of the event named under the cursor
+    const eventSites = (method: string, what: string) => async () => {
+        const editor = vscode.window.activeTextEditor;
+        if (!editor || editor.document.languageId !== 'questicle') { return; }
+        const position = editor.selection.active;
+        const found = await client.sendRequest<any[] | null>(method, {
+            textDocument: { uri: editor.document.uri.toString() },
+            position: { line: position.line, character: position.character }
+        });
+        if (!found) {
+            vscode.window.showInformationMessage(`Place the cursor on an event name in an on(...) or emit(...) call to find its ${what}.`);
+            return;
+        }
+        const locations = found.map((l) => client.protocol2CodeConverter.asLocation(l));
+        await vscode.commands.executeCommand('editor.action.showReferences', editor.document.uri, position, locations);
+    };
+    // The debug adapter runs as a child process speaking DAP over stdio
+    context.subscriptions.push(vscode.debug.registerDebugAdapterDescriptorFactory('questicle', {
+        createDebugAdapterDescriptor: () => {
+            const dapName = process.platform === 'win32' ? 'qk-dap.exe' : 'qk-dap';
+            const dapCandidates: string[] = [];
+            const customDap = vscode.workspace.getConfiguration('questicle').get<string>('debugAdapterPath');
+            if (customDap && customDap.length > 0) {
+                dapCandidates.push(customDap);
+            }
+            dapCandidates.push(path.join(root, 'target', 'debug', dapName), path.join(root, 'target', 'release', dapName), dapName);
+            const dap = pickFirstExecutable(dapCandidates);
+            out.appendLine(`[Questicle] Using debug adapter: ${dap}`);
+            return new vscode.DebugAdapterExecutable(dap, [], { cwd: root });
+        }
+    }));
+
+    context.subscriptions.push(
+        vscode.commands.registerCommand('questicle.findEmitters', eventSites('questicle/emitters', 'emitters')),
+        vscode.commands.registerCommand('questicle.findHandlers', eventSites('questicle/handlers', 'handlers'))
+    );
 }
 
 export function deactivate(): Thenable<void> | undefined {
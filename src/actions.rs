@@ -0,0 +1,469 @@
+// SPDX-License-Identifier: MIT
+// Code actions for the language server. Quick fixes: annotate a function with its inferred
+// return type, change a `let` annotation to the inferred type, declare a missing variable
+// or function, and compare a non-bool condition explicitly. Refactoring: extract the
+// selected expression into a `let`.
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::complete::offset;
+use crate::formatter::FormatterOptions;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::stdlib::BUILTINS;
+use crate::symbols::{self, SymbolTable};
+use crate::token::{Token, TokenKind};
+use crate::typecheck::{self, CheckOptions, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    QuickFix,
+    Extract,
+}
+
+// Replace the text in `span` (end exclusive); an empty span inserts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub new_text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeAction {
+    pub title: String,
+    pub kind: ActionKind,
+    pub edits: Vec<Edit>,
+    // The fix for a reported problem, as opposed to a suggestion
+    pub preferred: bool,
+}
+
+// Actions for the 1-based range `range` (a cursor is an empty range); none when the source
+// does not parse
+pub fn code_actions(src: &str, range: Span, check: &CheckOptions) -> Vec<CodeAction> {
+    let Ok(program) = Parser::new(src).parse_program() else {
+        return Vec::new();
+    };
+    let tc = typecheck::check_program_with_options(&program, check);
+    let mut a = Actions {
+        src,
+        range,
+        check,
+        inferred: tc.inferred.into_iter().collect(),
+        errors: tc.errors.iter().map(|e| e.span).collect(),
+        tokens: Lexer::new(src).lex(),
+        table: symbols::analyze(&program),
+        out: Vec::new(),
+    };
+    for s in &program.statements {
+        a.stmt(s, s.span, s.span);
+    }
+    a.out
+}
+
+// Whether two ranges overlap or touch
+fn touches(a: Span, b: Span) -> bool {
+    (a.line, a.col) <= (b.end_line, b.end_col) && (b.line, b.col) <= (a.end_line, a.end_col)
+}
+
+fn insert_at(line: usize, col: usize, text: String) -> Edit {
+    Edit {
+        span: Span::new(line, col, line, col),
+        new_text: text,
+    }
+}
+
+struct Actions<'a> {
+    src: &'a str,
+    range: Span,
+    check: &'a CheckOptions,
+    inferred: HashMap<Span, Type>,
+    // Where the checker reported problems
+    errors: HashSet<Span>,
+    tokens: Vec<Token>,
+    table: SymbolTable,
+    out: Vec<CodeAction>,
+}
+
+impl Actions<'_> {
+    fn text(&self, span: Span) -> &str {
+        match (
+            offset(self.src, span.line, span.col),
+            offset(self.src, span.end_line, span.end_col),
+        ) {
+            (Some(start), Some(end)) if start <= end => &self.src[start..end],
+            _ => "",
+        }
+    }
+
+    // Leading whitespace of a line
+    fn indent(&self, line: usize) -> String {
+        let text = self.src.lines().nth(line - 1).unwrap_or("");
+        text.chars().take_while(|c| c.is_whitespace()).collect()
+    }
+
+    fn push(&mut self, title: String, kind: ActionKind, edits: Vec<Edit>, preferred: bool) {
+        self.out.push(CodeAction {
+            title,
+            kind,
+            edits,
+            preferred,
+        });
+    }
+
+    // `anchor` is the innermost statement of a statement list containing `s` (where new
+    // declarations go); `top` the top-level statement containing it
+    fn stmt(&mut self, s: &Stmt, anchor: Span, top: Span) {
+        match &s.kind {
+            StmtKind::Let {
+                name_span,
+                ty,
+                init,
+                ..
+            } => {
+                if let Some(ann) = ty {
+                    self.change_annotation(*name_span, ann, init);
+                }
+                if matches!(&init.kind, ExprKind::Fn(f) if f.ret.is_none()) {
+                    self.add_return_type(*name_span, init);
+                }
+                self.expr(init, anchor, top, true);
+            }
+            StmtKind::Expr(e) => self.expr(e, anchor, top, true),
+            StmtKind::Block(b, _) | StmtKind::Test { body: b, .. } => {
+                for inner in b {
+                    self.stmt(inner, inner.span, top);
+                }
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.compare_condition(cond);
+                self.expr(cond, anchor, top, true);
+                self.stmt(then_branch, then_branch.span, top);
+                if let Some(e) = else_branch {
+                    self.stmt(e, e.span, top);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.compare_condition(cond);
+                // Hoisting out of the condition would evaluate it only once
+                self.expr(cond, anchor, top, false);
+                self.stmt(body, body.span, top);
+            }
+            StmtKind::For { iter, body, .. } => {
+                self.expr(iter, anchor, top, true);
+                self.stmt(body, body.span, top);
+            }
+            StmtKind::Return(Some(e)) => self.expr(e, anchor, top, true),
+            StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    // `hoist`: whether the expression may be moved before `anchor` without changing when
+    // (or whether) it is evaluated
+    fn expr(&mut self, e: &Expr, anchor: Span, top: Span, hoist: bool) {
+        if hoist && e.span == self.range {
+            self.extract(e, anchor);
+        }
+        match &e.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Var(name, _) => self.create_variable(name, e.span, anchor),
+            ExprKind::Assign { name, value, .. } => {
+                self.expr(value, anchor, top, hoist);
+                let s = e.span;
+                let span = Span::new(s.line, s.col, s.line, s.col + name.chars().count());
+                self.create_variable(name, span, anchor);
+            }
+            ExprKind::Binary { left, op, right } => {
+                self.expr(left, anchor, top, hoist);
+                let short_circuit = matches!(op, BinOp::And | BinOp::Or);
+                self.expr(right, anchor, top, hoist && !short_circuit);
+            }
+            ExprKind::Unary { expr, .. } => self.expr(expr, anchor, top, hoist),
+            ExprKind::Call { callee, args } => {
+                match &callee.kind {
+                    ExprKind::Var(name, _) => self.create_function(name, callee.span, args, top),
+                    _ => self.expr(callee, anchor, top, hoist),
+                }
+                for a in args {
+                    self.expr(a, anchor, top, hoist);
+                }
+            }
+            ExprKind::Fn(f) => {
+                for s in &f.body {
+                    self.stmt(s, s.span, top);
+                }
+            }
+            ExprKind::List(items) => {
+                for i in items {
+                    self.expr(i, anchor, top, hoist);
+                }
+            }
+            ExprKind::Map(props) => {
+                for (_, v) in props {
+                    self.expr(v, anchor, top, hoist);
+                }
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target, anchor, top, hoist);
+                self.expr(index, anchor, top, hoist);
+            }
+            ExprKind::Field { target, .. } => self.expr(target, anchor, top, hoist),
+        }
+    }
+
+    // Index of the token starting at a 1-based position
+    fn token_at(&self, line: usize, col: usize) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|t| t.line == line && t.col == col)
+    }
+
+    // `let name: Type = init` where the annotation is `any` or does not match the initializer
+    fn change_annotation(&mut self, name_span: Span, ann: &TypeExpr, init: &Expr) {
+        let Some(t) = self.inferred.get(&name_span).cloned() else {
+            return;
+        };
+        let mismatch = self.errors.contains(&init.span);
+        if t == Type::Any || !(mismatch || matches!(ann, TypeExpr::Any)) {
+            return;
+        }
+        // The annotation runs from after the `:` to before the `=`
+        let Some(i) = self.token_at(name_span.line, name_span.col) else {
+            return;
+        };
+        let Some(eq) = self.tokens[i..]
+            .iter()
+            .position(|t| t.kind == TokenKind::Assign)
+            .map(|p| p + i)
+        else {
+            return;
+        };
+        if eq < i + 3 {
+            return;
+        }
+        let (first, last) = (&self.tokens[i + 2], &self.tokens[eq - 1]);
+        let span = Span::new(first.line, first.col, last.end_line, last.end_col);
+        let head = name_span.to(span);
+        let on_error = mismatch && touches(self.range, init.span);
+        if !touches(self.range, head) && !on_error {
+            return;
+        }
+        let edit = Edit {
+            span,
+            new_text: t.to_string(),
+        };
+        self.push(
+            format!("Change the annotation to `{}`", t),
+            ActionKind::QuickFix,
+            vec![edit],
+            mismatch,
+        );
+    }
+
+    // ` -> T` after the parameter list of a function without a return annotation
+    fn add_return_type(&mut self, name_span: Span, f: &Expr) {
+        let Some(Type::Func(_, ret)) = self.inferred.get(&f.span).cloned() else {
+            return;
+        };
+        if *ret == Type::Any {
+            return;
+        }
+        let Some(start) = self
+            .tokens
+            .iter()
+            .position(|t| (t.line, t.col) >= (f.span.line, f.span.col))
+        else {
+            return;
+        };
+        let mut depth = 0;
+        let mut close = None;
+        for t in &self.tokens[start..] {
+            match t.kind {
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some((t.end_line, t.end_col));
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some((line, col)) = close else {
+            return;
+        };
+        let head = Span::new(name_span.line, name_span.col, line, col);
+        if !touches(self.range, head) {
+            return;
+        }
+        let preferred = self.errors.contains(&name_span);
+        self.push(
+            format!("Add return type `-> {}`", ret),
+            ActionKind::QuickFix,
+            vec![insert_at(line, col, format!(" -> {}", ret))],
+            preferred,
+        );
+    }
+
+    // `if (count)` becomes `if (count != 0)`, and so on by type
+    fn compare_condition(&mut self, cond: &Expr) {
+        if !self.errors.contains(&cond.span) || !touches(self.range, cond.span) {
+            return;
+        }
+        let Some(t) = self.inferred.get(&cond.span) else {
+            return;
+        };
+        let text = self.text(cond.span);
+        let operand = match cond.kind {
+            ExprKind::Binary { .. } | ExprKind::Assign { .. } => format!("({})", text),
+            _ => text.to_string(),
+        };
+        let new_text = match t {
+            Type::Number => format!("{} != 0", operand),
+            Type::String => format!("{} != \"\"", operand),
+            Type::List(_) => format!("len({}) > 0", text),
+            _ => format!("{} != null", operand),
+        };
+        let edit = Edit {
+            span: cond.span,
+            new_text: new_text.clone(),
+        };
+        self.push(
+            format!("Compare explicitly: `{}`", new_text),
+            ActionKind::QuickFix,
+            vec![edit],
+            true,
+        );
+    }
+
+    // Names that resolve to nothing the script, the builtins or the host declare
+    fn undefined(&self, name: &str, span: Span) -> bool {
+        if BUILTINS.contains(&name) {
+            return false;
+        }
+        if self
+            .check
+            .decls
+            .as_ref()
+            .is_some_and(|d| d.natives.contains_key(name))
+        {
+            return false;
+        }
+        self.table
+            .reference_at(span.line, span.col)
+            .is_some_and(|r| r.symbol.is_none())
+    }
+
+    fn create_variable(&mut self, name: &str, span: Span, anchor: Span) {
+        if !touches(self.range, span) || !self.undefined(name, span) {
+            return;
+        }
+        let text = format!("{}let {}: any = null;\n", self.indent(anchor.line), name);
+        self.push(
+            format!("Create variable '{}'", name),
+            ActionKind::QuickFix,
+            vec![insert_at(anchor.line, 1, text)],
+            true,
+        );
+    }
+
+    // Declared before the top-level statement that calls it, one parameter per argument
+    fn create_function(&mut self, name: &str, span: Span, args: &[Expr], top: Span) {
+        if !touches(self.range, span) || !self.undefined(name, span) {
+            return;
+        }
+        let mut params: Vec<String> = Vec::new();
+        for (i, a) in args.iter().enumerate() {
+            let p = match &a.kind {
+                ExprKind::Var(v, _) if !params.contains(v) => v.clone(),
+                _ => format!("arg{}", i + 1),
+            };
+            params.push(format!("{}: any", p));
+        }
+        // Indented as `qk fmt` would
+        let indent = " ".repeat(FormatterOptions::default().indent_size);
+        let text = format!(
+            "fn {}({}) -> any {{\n{}return null;\n}}\n\n",
+            name,
+            params.join(", "),
+            indent
+        );
+        self.push(
+            format!("Create function '{}'", name),
+            ActionKind::QuickFix,
+            vec![insert_at(top.line, 1, text)],
+            true,
+        );
+    }
+
+    // Move the selected expression into `let value: T = ...;` before its statement; the
+    // type is what the checker infers for the new variable
+    fn extract(&mut self, e: &Expr, anchor: Span) {
+        if matches!(e.kind, ExprKind::Var(_, _) | ExprKind::Assign { .. }) {
+            return;
+        }
+        let taken: HashSet<&str> = self.table.symbols.iter().map(|s| s.name.as_str()).collect();
+        let mut name = "value".to_string();
+        let mut n = 1;
+        while taken.contains(name.as_str()) {
+            n += 1;
+            name = format!("value{}", n);
+        }
+        let indent = self.indent(anchor.line);
+        let text = self.text(e.span).to_string();
+        let replace = Edit {
+            span: e.span,
+            new_text: name.clone(),
+        };
+        let declare = |ty: &str| {
+            insert_at(
+                anchor.line,
+                1,
+                format!("{}let {}: {} = {};\n", indent, name, ty, text),
+            )
+        };
+        // Check the edited source to learn the new variable's type
+        let ty = apply(self.src, &[declare("any"), replace.clone()])
+            .and_then(|edited| {
+                let program = Parser::new(&edited).parse_program().ok()?;
+                let tc = typecheck::check_program_with_options(&program, self.check);
+                let col = indent.chars().count() + 5;
+                let span = Span::new(anchor.line, col, anchor.line, col + name.chars().count());
+                tc.inferred
+                    .into_iter()
+                    .find(|(s, _)| *s == span)
+                    .map(|(_, t)| t)
+            })
+            .unwrap_or(Type::Any);
+        self.push(
+            format!("Extract into `let {}`", name),
+            ActionKind::Extract,
+            vec![declare(&ty.to_string()), replace],
+            false,
+        );
+    }
+}
+
+// The source with non-overlapping edits applied
+pub fn apply(src: &str, edits: &[Edit]) -> Option<String> {
+    let mut sorted: Vec<&Edit> = edits.iter().collect();
+    sorted.sort_by_key(|e| (e.span.line, e.span.col));
+    let mut out = String::new();
+    let mut at = 0;
+    for e in sorted {
+        let start = offset(src, e.span.line, e.span.col)?;
+        let end = offset(src, e.span.end_line, e.span.end_col)?;
+        if start < at || end < start {
+            return None;
+        }
+        out.push_str(&src[at..start]);
+        out.push_str(&e.new_text);
+        at = end;
+    }
+    out.push_str(&src[at..]);
+    Some(out)
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -5,15 +7,95 @@ pub struct Program {
     pub statements: Vec<Stmt>,
 }
 
+// Source range of a node: 1-based line/col of its first character and the position just past its end
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, col: usize, end_line: usize, end_col: usize) -> Self {
+        Self {
+            line,
+            col,
+            end_line,
+            end_col,
+        }
+    }
+
+    // Span from the start of self to the end of other
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            line: self.line,
+            col: self.col,
+            end_line: other.end_line,
+            end_col: other.end_col,
+        }
+    }
+}
+
+// Where a variable lives, as worked out by the resolver (resolve.rs). A `Local` is `depth`
+// scopes out from its use, at `index` in that scope's frame; a `Global` is looked up by name
+// in the global scope. `Dynamic` uses, which the resolver leaves to be found at runtime or
+// never saw, are looked up by name from the innermost scope out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slot {
+    #[default]
+    Dynamic,
+    Global,
+    Local {
+        depth: usize,
+        index: usize,
+    },
+}
+
+// Names of a scope's slots in slot order, as the resolver numbered them. Every frame made
+// for the scope at runtime shares them and is sized from them; empty when unresolved.
+pub type SlotNames = Arc<[Arc<str>]>;
+
+// Whether a block runs in a scope of its own, as worked out by the resolver: a block that
+// declares nothing runs in the enclosing scope's frame. Unresolved blocks get their own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frame {
+    Own(SlotNames),
+    Enclosing,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::Own(SlotNames::default())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Stmt {
+pub enum StmtKind {
     Let {
         name: String,
+        name_span: Span,
         ty: Option<TypeExpr>,
+        // The annotation; for `fn name(...)` the signature its type comes from
+        ty_span: Span,
         init: Expr,
+        // Where the binding goes (see Slot)
+        slot: Slot,
     },
     Expr(Expr),
-    Block(Vec<Stmt>),
+    Block(Vec<Stmt>, Frame),
     If {
         cond: Expr,
         then_branch: Box<Stmt>,
@@ -25,20 +107,41 @@ pub enum Stmt {
     },
     For {
         name: String,
+        name_span: Span,
         iter: Expr,
         body: Box<Stmt>,
+        // Each iteration's frame: the loop variable, then the body's lets
+        slots: SlotNames,
     },
     Return(Option<Expr>),
     Break,
     Continue,
+    // `test "name" { ... }` at the top level: skipped when a script runs, run by `qk test`
+    Test {
+        name: String,
+        body: Vec<Stmt>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Expr {
+pub enum ExprKind {
     Literal(Lit),
-    Var(String),
+    Var(String, Slot),
     Assign {
         name: String,
+        slot: Slot,
         value: Box<Expr>,
     },
     Binary {
@@ -54,11 +157,8 @@ pub enum Expr {
         callee: Box<Expr>,
         args: Vec<Expr>,
     },
-    Fn {
-        params: Vec<(String, Option<TypeExpr>)>,
-        ret: Option<TypeExpr>,
-        body: Vec<Stmt>,
-    },
+    // Shared with every closure made from the literal, which keeps creating one cheap
+    Fn(Arc<FnLiteral>),
     List(Vec<Expr>),
     Map(Vec<(String, Expr)>),
     Index {
@@ -71,6 +171,42 @@ pub enum Expr {
     },
 }
 
+// A function literal: `fn (params) -> ret { body }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FnLiteral {
+    pub params: Vec<Param>,
+    pub ret: Option<TypeExpr>,
+    pub ret_span: Span,
+    pub body: Vec<Stmt>,
+    // Each call's frame: the parameters, then the body's lets
+    pub slots: SlotNames,
+}
+
+// Declaration from a .qkd file: a host op reached through host(op, payload) or a native function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decl {
+    pub kind: DeclKind,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret: TypeExpr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeclKind {
+    HostOp,
+    Native,
+}
+
+// Function parameter; span covers the name and ty_span the annotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Param {
+    pub name: String,
+    pub ty: Option<TypeExpr>,
+    pub span: Span,
+    pub ty_span: Span,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Lit {
     Number(f64),
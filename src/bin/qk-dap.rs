@@ -0,0 +1,516 @@
+// SPDX-License-Identifier: MIT
+// Debug adapter for Questicle scripts, speaking the Debug Adapter Protocol over stdio. The
+// script runs on the main thread with a debug session installed; a reader thread forwards
+// requests, which are answered between statements while the script runs and in a loop
+// while it is paused.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+
+use serde_json::{json, Value as Json};
+
+use questicle::ast::Span;
+use questicle::debug::{self, Breakpoint, Frontend, Paused, Resume, Session, StopReason};
+use questicle::value::Value;
+use questicle::{Host, Interpreter, Parser};
+
+// The only thread a script runs on
+const THREAD_ID: i64 = 1;
+
+struct Client {
+    requests: Receiver<Json>,
+    seq: Cell<i64>,
+    // Breakpoints by script, as last set by the client
+    breakpoints: RefCell<HashMap<PathBuf, BTreeMap<usize, Breakpoint>>>,
+    stop_on_error: Cell<bool>,
+    disconnected: Cell<bool>,
+}
+
+impl Client {
+    fn send(&self, mut msg: Json) {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+        msg["seq"] = json!(seq);
+        let body = msg.to_string();
+        let mut out = io::stdout().lock();
+        let _ = write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = out.flush();
+    }
+
+    fn respond(&self, req: &Json, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "success": true,
+            "command": req["command"],
+            "body": body,
+        }));
+    }
+
+    fn fail(&self, req: &Json, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "success": false,
+            "command": req["command"],
+            "message": message,
+        }));
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn output(&self, category: &str, text: String) {
+        self.event("output", json!({ "category": category, "output": text }));
+    }
+
+    // Requests that are answered the same way whether or not a script runs; false for the
+    // others
+    fn common(&self, req: &Json) -> bool {
+        match req["command"].as_str().unwrap_or_default() {
+            "threads" => self.respond(
+                req,
+                json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+            ),
+            "setBreakpoints" => self.set_breakpoints(req),
+            "setExceptionBreakpoints" => {
+                let filters = req["arguments"]["filters"].as_array().cloned();
+                let on = filters.unwrap_or_default().iter().any(|f| f == "error");
+                self.stop_on_error.set(on);
+                self.respond(req, json!({}));
+            }
+            "disconnect" | "terminate" => {
+                self.disconnected.set(true);
+                self.respond(req, json!({}));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Breakpoints move to the first line with a statement at or after the requested line
+    fn set_breakpoints(&self, req: &Json) {
+        let args = &req["arguments"];
+        let path = script_path(args["source"]["path"].as_str().unwrap_or_default());
+        let lines = fs::read_to_string(&path)
+            .ok()
+            .and_then(|src| Parser::new(&src).parse_program().ok())
+            .map(|p| debug::breakable_lines(&p))
+            .unwrap_or_default();
+        let mut set = BTreeMap::new();
+        let mut reply = Vec::new();
+        for bp in args["breakpoints"].as_array().cloned().unwrap_or_default() {
+            let requested = bp["line"].as_u64().unwrap_or(0) as usize;
+            match debug::resolve_line(&lines, requested) {
+                Some(line) => {
+                    let text = |key: &str| bp[key].as_str().map(str::to_string);
+                    set.insert(
+                        line,
+                        Breakpoint {
+                            line,
+                            condition: text("condition").filter(|c| !c.trim().is_empty()),
+                            log_message: text("logMessage"),
+                        },
+                    );
+                    reply.push(json!({ "verified": true, "line": line }));
+                }
+                None => reply.push(json!({
+                    "verified": false,
+                    "line": requested,
+                    "message": "No statement at or after this line",
+                })),
+            }
+        }
+        self.breakpoints.borrow_mut().insert(path, set);
+        self.respond(req, json!({ "breakpoints": reply }));
+    }
+}
+
+// Scripts are told apart by their canonical path
+fn script_path(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+// What a variablesReference points at while paused
+enum Handle {
+    Locals(usize),
+    Globals(usize),
+    Value(Value),
+}
+
+struct DapFrontend {
+    client: Rc<Client>,
+    program: PathBuf,
+}
+
+impl DapFrontend {
+    fn source(&self) -> Json {
+        let name = self.program.file_name().map(|n| n.to_string_lossy());
+        json!({ "name": name, "path": self.program })
+    }
+
+    // Breakpoints the client set for the running script since the last look
+    fn refresh(&self, breakpoints: &mut BTreeMap<usize, Breakpoint>) {
+        if let Some(set) = self.client.breakpoints.borrow().get(&self.program) {
+            *breakpoints = set.clone();
+        }
+    }
+
+    fn stack_trace(&self, req: &Json, paused: &Paused<'_>) {
+        let frames: Vec<Json> = paused
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, f)| {
+                json!({
+                    "id": id,
+                    "name": f.name,
+                    "line": f.span.line,
+                    "column": f.span.col,
+                    "endLine": f.span.end_line,
+                    "endColumn": f.span.end_col,
+                    "source": self.source(),
+                })
+            })
+            .collect();
+        let total = frames.len();
+        self.client
+            .respond(req, json!({ "stackFrames": frames, "totalFrames": total }));
+    }
+}
+
+impl Frontend for DapFrontend {
+    fn poll(&mut self, breakpoints: &mut BTreeMap<usize, Breakpoint>) -> bool {
+        let mut pause = false;
+        let mut any = false;
+        while let Ok(req) = self.client.requests.try_recv() {
+            any = true;
+            if self.client.common(&req) {
+                if self.client.disconnected.get() {
+                    // The script runs in this process, so it ends with the session
+                    std::process::exit(0);
+                }
+                continue;
+            }
+            match req["command"].as_str().unwrap_or_default() {
+                "pause" => {
+                    self.client.respond(&req, json!({}));
+                    pause = true;
+                }
+                _ => self.client.fail(&req, "The script is running"),
+            }
+        }
+        if any {
+            self.refresh(breakpoints);
+        }
+        pause
+    }
+
+    fn stopped(&mut self, reason: &StopReason, mut paused: Paused<'_>) -> Resume {
+        let (name, text) = match reason {
+            StopReason::Entry => ("entry", None),
+            StopReason::Breakpoint => ("breakpoint", None),
+            StopReason::Step => ("step", None),
+            StopReason::Pause => ("pause", None),
+            StopReason::Error(message) => ("exception", Some(message.clone())),
+        };
+        self.client.event(
+            "stopped",
+            json!({
+                "reason": name,
+                "description": text.as_deref().map(|_| "Runtime error"),
+                "text": text,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+        // variablesReference n is handles[n - 1]; valid until the script resumes
+        let mut handles: Vec<Handle> = Vec::new();
+        while let Ok(req) = self.client.requests.recv() {
+            if self.client.common(&req) {
+                if self.client.disconnected.get() {
+                    return Resume::Abort;
+                }
+                self.refresh(paused.breakpoints);
+                continue;
+            }
+            let args = &req["arguments"];
+            let frame = args["frameId"].as_u64().map(|f| f as usize);
+            match req["command"].as_str().unwrap_or_default() {
+                "stackTrace" => self.stack_trace(&req, &paused),
+                "scopes" => {
+                    let frame = frame.unwrap_or(0);
+                    let locals = push_handle(&mut handles, Handle::Locals(frame));
+                    let globals = push_handle(&mut handles, Handle::Globals(frame));
+                    self.client.respond(
+                        &req,
+                        json!({ "scopes": [
+                            { "name": "Locals", "variablesReference": locals, "expensive": false },
+                            { "name": "Globals", "variablesReference": globals, "expensive": false },
+                        ] }),
+                    );
+                }
+                "variables" => {
+                    let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+                    let children = match handles.get(reference.wrapping_sub(1)) {
+                        Some(Handle::Locals(f)) => debug::locals(&paused.env(*f)),
+                        Some(Handle::Globals(f)) => debug::globals(&paused.env(*f)),
+                        Some(Handle::Value(v)) => children(v),
+                        None => Vec::new(),
+                    };
+                    let variables: Vec<Json> = children
+                        .into_iter()
+                        .map(|(name, v)| {
+                            let reference = value_handle(&mut handles, &v);
+                            json!({
+                                "name": name,
+                                "value": v.to_string(),
+                                "type": v.type_name(),
+                                "variablesReference": reference,
+                            })
+                        })
+                        .collect();
+                    self.client.respond(&req, json!({ "variables": variables }));
+                }
+                "evaluate" => {
+                    let frame = frame.unwrap_or(paused.frames.len() - 1);
+                    let expression = args["expression"].as_str().unwrap_or_default();
+                    match paused.evaluate(frame, expression) {
+                        Ok(v) => {
+                            let reference = value_handle(&mut handles, &v);
+                            self.client.respond(
+                                &req,
+                                json!({
+                                    "result": v.to_string(),
+                                    "type": v.type_name(),
+                                    "variablesReference": reference,
+                                }),
+                            );
+                        }
+                        Err(e) => self.client.fail(&req, &e),
+                    }
+                }
+                "continue" => {
+                    self.client
+                        .respond(&req, json!({ "allThreadsContinued": true }));
+                    return Resume::Continue;
+                }
+                "next" => {
+                    self.client.respond(&req, json!({}));
+                    return Resume::StepOver;
+                }
+                "stepIn" => {
+                    self.client.respond(&req, json!({}));
+                    return Resume::StepIn;
+                }
+                "stepOut" => {
+                    self.client.respond(&req, json!({}));
+                    return Resume::StepOut;
+                }
+                "pause" => self.client.respond(&req, json!({})),
+                _ => self.client.fail(&req, "Unsupported request"),
+            }
+        }
+        // The client went away
+        Resume::Abort
+    }
+
+    fn log(&mut self, message: &str, span: Span) {
+        self.client.event(
+            "output",
+            json!({
+                "category": "console",
+                "output": format!("{}\n", message),
+                "source": self.source(),
+                "line": span.line,
+            }),
+        );
+    }
+}
+
+// The variablesReference for a new handle
+fn push_handle(handles: &mut Vec<Handle>, h: Handle) -> usize {
+    handles.push(h);
+    handles.len()
+}
+
+// Non-empty lists and maps can be expanded; other values get reference 0
+fn value_handle(handles: &mut Vec<Handle>, v: &Value) -> usize {
+    match v {
+        Value::List(items) if !items.is_empty() => push_handle(handles, Handle::Value(v.clone())),
+        Value::Map(m) if !m.is_empty() => push_handle(handles, Handle::Value(v.clone())),
+        _ => 0,
+    }
+}
+
+fn children(v: &Value) -> Vec<(String, Value)> {
+    match v {
+        Value::List(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("[{}]", i), v.clone()))
+            .collect(),
+        Value::Map(m) => m.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// DAP messages from stdin, until it closes
+fn read_requests(tx: mpsc::Sender<Json>) {
+    let mut input = io::BufReader::new(io::stdin().lock());
+    loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(n) = line.strip_prefix("Content-Length:") {
+                length = n.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+        let mut body = vec![0; length];
+        if input.read_exact(&mut body).is_err() {
+            return;
+        }
+        if let Ok(msg) = serde_json::from_slice::<Json>(&body) {
+            if tx.send(msg).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+struct Launch {
+    program: PathBuf,
+    stop_on_entry: bool,
+    no_debug: bool,
+}
+
+// Run the script to the end; returns the exit code
+fn run(client: &Rc<Client>, launch: &Launch) -> i32 {
+    let src = match fs::read_to_string(&launch.program) {
+        Ok(src) => src,
+        Err(e) => {
+            client.output("stderr", format!("{}: {}\n", launch.program.display(), e));
+            return 66;
+        }
+    };
+    let program = match Parser::new(&src).parse_program() {
+        Ok(p) => p,
+        Err(e) => {
+            client.output("stderr", format!("Parse error: {}\n", e));
+            return 65;
+        }
+    };
+    let mut interp = Interpreter::with_host(Host::default());
+    // Script output goes to the debug console rather than over the protocol stream
+    let out = client.clone();
+    interp.set_output(move |line: &str| out.output("stdout", format!("{}\n", line)));
+    if !launch.no_debug {
+        let mut session = Session::new(DapFrontend {
+            client: client.clone(),
+            program: launch.program.clone(),
+        });
+        session.stop_on_entry = launch.stop_on_entry;
+        session.stop_on_error = client.stop_on_error.get();
+        if let Some(set) = client.breakpoints.borrow().get(&launch.program) {
+            session.breakpoints = set.clone();
+        }
+        interp.hooks = Some(Box::new(session));
+    }
+    match interp.eval(program) {
+        Ok(_) => 0,
+        Err(_) if client.disconnected.get() => 0,
+        Err(e) => {
+            client.output("stderr", format!("Runtime error: {}\n", e));
+            70
+        }
+    }
+}
+
+fn main() {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || read_requests(tx));
+    let client = Rc::new(Client {
+        requests: rx,
+        seq: Cell::new(0),
+        breakpoints: RefCell::new(HashMap::new()),
+        stop_on_error: Cell::new(true),
+        disconnected: Cell::new(false),
+    });
+
+    // The script starts once it was launched and the client is done configuring
+    let mut launch: Option<Launch> = None;
+    let mut configured = false;
+    let mut finished = false;
+    while let Ok(req) = client.requests.recv() {
+        if client.common(&req) {
+            if client.disconnected.get() {
+                break;
+            }
+            continue;
+        }
+        let args = &req["arguments"];
+        match req["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                client.respond(
+                    &req,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsConditionalBreakpoints": true,
+                        "supportsLogPoints": true,
+                        "supportsEvaluateForHovers": true,
+                        "supportsTerminateRequest": true,
+                        "exceptionBreakpointFilters": [{
+                            "filter": "error",
+                            "label": "Runtime errors",
+                            "default": true,
+                        }],
+                    }),
+                );
+                client.event("initialized", json!({}));
+            }
+            "launch" => {
+                let Some(program) = args["program"].as_str() else {
+                    client.fail(&req, "launch needs a `program` to run");
+                    continue;
+                };
+                launch = Some(Launch {
+                    program: script_path(program),
+                    stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false),
+                    no_debug: args["noDebug"].as_bool().unwrap_or(false),
+                });
+                client.respond(&req, json!({}));
+            }
+            "configurationDone" => {
+                configured = true;
+                client.respond(&req, json!({}));
+            }
+            _ => client.fail(&req, "No script is paused"),
+        }
+        if let (Some(l), true, false) = (&launch, configured, finished) {
+            finished = true;
+            let code = run(&client, l);
+            if client.disconnected.get() {
+                break;
+            }
+            client.event("exited", json!({ "exitCode": code }));
+            client.event("terminated", json!({}));
+        }
+    }
+}
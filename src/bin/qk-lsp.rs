@@ -1,20 +1,75 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tower_lsp::jsonrpc;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
-use questicle::{typecheck, Parser};
+use questicle::actions::{self, ActionKind};
+use questicle::ast::{DeclKind, Span};
+use questicle::check::Severity;
+use questicle::complete::{self, CompletionKind as Kind, Completions};
+use questicle::decl::{Declarations, FnDecl};
+use questicle::events::SiteKind;
+use questicle::highlight;
+use questicle::hints::{self, HintKind, HintOptions};
+use questicle::lint::{self, LintConfig};
+use questicle::symbols::{self, RefKind, SymbolTable};
+use questicle::typecheck;
+use questicle::workspace::WorkspaceIndex;
+
+// Result id and data of a semantic tokens response
+type SentTokens = (String, Vec<SemanticToken>);
 
 struct Backend {
     client: Client,
     docs: Arc<RwLock<HashMap<Url, String>>>,
+    settings: Arc<RwLock<Settings>>,
+    // Workspace folders scanned for .qkd declaration files
+    roots: Arc<RwLock<Vec<PathBuf>>>,
+    decls: Arc<RwLock<Arc<Declarations>>>,
+    // Rule levels from qklint.json in the first workspace folder
+    lint: Arc<RwLock<LintConfig>>,
+    // Last semantic tokens sent per document, for delta requests
+    semantic: Arc<RwLock<HashMap<Url, SentTokens>>>,
+    next_result_id: Arc<AtomicU64>,
+    // Every script under the workspace roots; open documents with their unsaved text
+    index: Arc<RwLock<WorkspaceIndex>>,
+}
+
+// Client-provided settings (initializationOptions / workspace configuration)
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+struct Settings {
+    // Check every document in strict mode, not only those with a `// qk: strict` pragma
+    strict: bool,
+    // Which inlay hints to show
+    #[serde(rename = "inlayHints")]
+    inlay_hints: HintOptions,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+        if let Some(opts) = params.initialization_options {
+            if let Ok(settings) = serde_json::from_value::<Settings>(opts) {
+                *self.settings.write().await = settings;
+            }
+        }
+        let mut roots: Vec<PathBuf> = params
+            .workspace_folders
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|f| f.uri.to_file_path().ok())
+            .collect();
+        if roots.is_empty() {
+            if let Some(path) = params.root_uri.and_then(|u| u.to_file_path().ok()) {
+                roots.push(path);
+            }
+        }
+        *self.roots.write().await = roots;
         let caps = ServerCapabilities {
             text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
             completion_provider: Some(CompletionOptions {
@@ -36,6 +91,40 @@ impl LanguageServer for Backend {
                 work_done_progress_options: Default::default(),
             }),
             definition_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            document_highlight_provider: Some(OneOf::Left(true)),
+            semantic_tokens_provider: Some(
+                SemanticTokensOptions {
+                    legend: SemanticTokensLegend {
+                        token_types: highlight::TOKEN_TYPES
+                            .iter()
+                            .map(|t| SemanticTokenType::new(t))
+                            .collect(),
+                        token_modifiers: highlight::TOKEN_MODIFIERS
+                            .iter()
+                            .map(|m| SemanticTokenModifier::new(m))
+                            .collect(),
+                    },
+                    full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                    range: None,
+                    work_done_progress_options: Default::default(),
+                }
+                .into(),
+            ),
+            inlay_hint_provider: Some(OneOf::Left(true)),
+            workspace_symbol_provider: Some(OneOf::Left(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::REFACTOR_EXTRACT,
+                ]),
+                ..Default::default()
+            })),
+            call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+            rename_provider: Some(OneOf::Right(RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: Default::default(),
+            })),
             ..Default::default()
         };
         Ok(InitializeResult {
@@ -49,37 +138,540 @@ impl LanguageServer for Backend {
     ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
         let uri = params.text_document_position_params.text_document.uri;
         let pos = params.text_document_position_params.position;
-        let docs = self.docs.read().await;
-        if let Some(text) = docs.get(&uri) {
-            if let Some(line) = text.lines().nth(pos.line as usize) {
-                // Prefer dotted identifiers when present (e.g., a.b.c)
-                let dotted = dotted_at(line, pos.character as usize);
-                let word = if dotted.contains('.') {
-                    dotted
-                } else {
-                    word_at(line, pos.character as usize)
+        let Some(table) = self.symbols(&uri).await else {
+            return Ok(None);
+        };
+        let Some(r) = table.reference_at(pos.line as usize + 1, pos.character as usize + 1) else {
+            return Ok(None);
+        };
+        if let Some(sym) = table.definition(r) {
+            let loc = Location::new(uri, span_range(sym.span));
+            return Ok(Some(GotoDefinitionResponse::Scalar(loc)));
+        }
+        // Top-level names declared by other scripts
+        let locations: Vec<Location> = self
+            .index
+            .read()
+            .await
+            .definitions(&r.name)
+            .into_iter()
+            .filter_map(|(path, sym)| Some(Location::new(path_uri(path)?, span_range(sym.span))))
+            .collect();
+        if !locations.is_empty() {
+            return Ok(Some(GotoDefinitionResponse::Array(locations)));
+        }
+        // Natives declared in a .qkd file
+        let decls = self.decls.read().await.clone();
+        if let Some(d) = decls.natives.get(&r.name) {
+            if let Some(target) = d.path.as_ref().and_then(|p| Url::from_file_path(p).ok()) {
+                let loc = Location::new(target, span_range(d.span));
+                return Ok(Some(GotoDefinitionResponse::Scalar(loc)));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
+        // On an event name: every `on` and `emit` of the event
+        if let Some(event) = self.event_at(&uri, pos).await {
+            return Ok(Some(self.event_locations(&event, None).await));
+        }
+        let Some(table) = self.symbols(&uri).await else {
+            return Ok(None);
+        };
+        let Some(r) = table.reference_at(pos.line as usize + 1, pos.character as usize + 1) else {
+            return Ok(None);
+        };
+        let include = |kind: RefKind| params.context.include_declaration || kind != RefKind::Decl;
+        // Top-level names (and names declared nowhere in this script) are shared by scripts
+        let global = match table.definition(r) {
+            Some(sym) => sym.scope == 0,
+            None => true,
+        };
+        if global {
+            let locations = self
+                .index
+                .read()
+                .await
+                .references(&r.name)
+                .into_iter()
+                .filter(|(_, o)| include(o.kind))
+                .filter_map(|(path, o)| Some(Location::new(path_uri(path)?, span_range(o.span))))
+                .collect();
+            return Ok(Some(locations));
+        }
+        let locations = table
+            .references_to(r)
+            .into_iter()
+            .filter(|o| include(o.kind))
+            .map(|o| Location::new(uri.clone(), span_range(o.span)))
+            .collect();
+        Ok(Some(locations))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        let Some(table) = self.symbols(&uri).await else {
+            return Ok(None);
+        };
+        let Some(r) = table.reference_at(pos.line as usize + 1, pos.character as usize + 1) else {
+            return Ok(None);
+        };
+        let highlights = table
+            .references_to(r)
+            .into_iter()
+            .map(|o| DocumentHighlight {
+                range: span_range(o.span),
+                kind: Some(match o.kind {
+                    RefKind::Decl => DocumentHighlightKind::TEXT,
+                    RefKind::Read => DocumentHighlightKind::READ,
+                    RefKind::Write => DocumentHighlightKind::WRITE,
+                }),
+            })
+            .collect();
+        Ok(Some(highlights))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<PrepareRenameResponse>> {
+        let Some(table) = self.symbols(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        let pos = params.position;
+        match table.reference_at(pos.line as usize + 1, pos.character as usize + 1) {
+            Some(r) if r.symbol.is_some() => {
+                Ok(Some(PrepareRenameResponse::Range(span_range(r.span))))
+            }
+            Some(r) => Err(jsonrpc::Error::invalid_params(format!(
+                "'{}' is not declared in this file and cannot be renamed",
+                r.name
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    async fn rename(&self, params: RenameParams) -> jsonrpc::Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
+        let Some(table) = self.symbols(&uri).await else {
+            return Ok(None);
+        };
+        let Some(id) = table
+            .reference_at(pos.line as usize + 1, pos.character as usize + 1)
+            .and_then(|r| r.symbol)
+        else {
+            return Ok(None);
+        };
+        let new_name = params.new_name;
+        let decls = self.decls.read().await.clone();
+        let conflict = if decls.natives.contains_key(&new_name) {
+            Some(format!("'{}' is a host native function", new_name))
+        } else {
+            table.rename_conflict(id, &new_name)
+        };
+        if let Some(reason) = conflict {
+            return Err(jsonrpc::Error::invalid_params(format!(
+                "Cannot rename to '{}': {}",
+                new_name, reason
+            )));
+        }
+        let edits = table
+            .refs
+            .iter()
+            .filter(|r| r.symbol == Some(id))
+            .map(|r| TextEdit::new(span_range(r.span), new_name.clone()))
+            .collect();
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        // Top-level names are shared by scripts: rename their uses in the other scripts too
+        let sym = &table.symbols[id];
+        if sym.scope == 0 {
+            let here = doc_path(&uri);
+            let index = self.index.read().await;
+            for (path, r) in index.references(&sym.name) {
+                if path == here {
+                    continue;
+                }
+                let Some(target) = path_uri(path) else {
+                    continue;
+                };
+                if !changes.contains_key(&target) {
+                    let file = index.file(path).map(|f| &f.symbols);
+                    if let Some(reason) =
+                        file.and_then(|t| t.global_rename_conflict(&sym.name, &new_name))
+                    {
+                        return Err(jsonrpc::Error::invalid_params(format!(
+                            "Cannot rename to '{}': in {}, {}",
+                            new_name,
+                            path.display(),
+                            reason
+                        )));
+                    }
+                }
+                changes
+                    .entry(target)
+                    .or_default()
+                    .push(TextEdit::new(span_range(r.span), new_name.clone()));
+            }
+        }
+        changes.insert(uri, edits);
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+        // While the document does not parse, keep showing the last good tokens
+        let (result_id, data) = match self.fresh_semantic_tokens(&uri).await {
+            Some(fresh) => fresh,
+            None => match self.semantic.read().await.get(&uri) {
+                Some((id, data)) => (id.clone(), data.clone()),
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        })))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> jsonrpc::Result<Option<SemanticTokensFullDeltaResult>> {
+        let uri = params.text_document.uri;
+        let previous = self.semantic.read().await.get(&uri).cloned();
+        let Some((result_id, data)) = self.fresh_semantic_tokens(&uri).await else {
+            // Unparseable: report no change against the last tokens
+            return Ok(previous.map(|(id, _)| {
+                SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                    result_id: Some(id),
+                    edits: Vec::new(),
+                })
+            }));
+        };
+        match previous {
+            Some((id, old)) if id == params.previous_result_id => Ok(Some(
+                SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                    result_id: Some(result_id),
+                    edits: token_edits(&old, &data),
+                }),
+            )),
+            _ => Ok(Some(SemanticTokensFullDeltaResult::Tokens(
+                SemanticTokens {
+                    result_id: Some(result_id),
+                    data,
+                },
+            ))),
+        }
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> jsonrpc::Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri;
+        let Some(text) = self.docs.read().await.get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let opts = self.settings.read().await.inlay_hints;
+        let check = self.check_options(&uri, &text).await;
+        let Some(found) = hints::inlay_hints(&text, &opts, &check) else {
+            return Ok(None);
+        };
+        let range = params.range;
+        let hints = found
+            .into_iter()
+            .map(|h| {
+                let position = Position::new(
+                    h.line.saturating_sub(1) as u32,
+                    h.col.saturating_sub(1) as u32,
+                );
+                let (kind, padding_left, padding_right) = match h.kind {
+                    HintKind::Type => (InlayHintKind::TYPE, true, false),
+                    HintKind::Parameter => (InlayHintKind::PARAMETER, false, true),
+                };
+                InlayHint {
+                    position,
+                    label: InlayHintLabel::String(h.label),
+                    kind: Some(kind),
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: Some(padding_left),
+                    padding_right: Some(padding_right),
+                    data: None,
+                }
+            })
+            .filter(|h| range.start <= h.position && h.position <= range.end)
+            .collect();
+        Ok(Some(hints))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+        let index = self.index.read().await;
+        let found = index
+            .symbols(&params.query)
+            .into_iter()
+            .filter_map(|(path, sym)| {
+                let kind = match sym.kind {
+                    symbols::SymbolKind::Function => SymbolKind::FUNCTION,
+                    _ => SymbolKind::VARIABLE,
+                };
+                #[allow(deprecated)]
+                Some(SymbolInformation {
+                    name: sym.name.clone(),
+                    kind,
+                    location: Location::new(path_uri(path)?, span_range(sym.span)),
+                    tags: None,
+                    deprecated: None,
+                    container_name: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+                })
+            })
+            .collect();
+        Ok(Some(found))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let Some(text) = self.docs.read().await.get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let r = params.range;
+        let range = Span::new(
+            r.start.line as usize + 1,
+            r.start.character as usize + 1,
+            r.end.line as usize + 1,
+            r.end.character as usize + 1,
+        );
+        let check = self.check_options(&uri, &text).await;
+        let only = params.context.only.unwrap_or_default();
+        let response = actions::code_actions(&text, range, &check)
+            .into_iter()
+            .filter_map(|a| {
+                let kind = match a.kind {
+                    ActionKind::QuickFix => CodeActionKind::QUICKFIX,
+                    ActionKind::Extract => CodeActionKind::REFACTOR_EXTRACT,
                 };
-                if !word.is_empty() {
-                    if let Some((l, s, e)) = find_decl_of(text, &word) {
-                        let loc = Location {
-                            uri: uri.clone(),
-                            range: Range::new(
-                                Position::new(l as u32, s as u32),
-                                Position::new(l as u32, e as u32),
-                            ),
+                // `only` lists kinds or their prefixes ("refactor" covers "refactor.extract")
+                let wanted = only.is_empty()
+                    || only.iter().any(|k| {
+                        kind.as_str() == k.as_str()
+                            || kind.as_str().starts_with(&format!("{}.", k.as_str()))
+                    });
+                if !wanted {
+                    return None;
+                }
+                let edits = a
+                    .edits
+                    .into_iter()
+                    .map(|e| TextEdit::new(span_range(e.span), e.new_text))
+                    .collect();
+                let changes = HashMap::from([(uri.clone(), edits)]);
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: a.title,
+                    kind: Some(kind),
+                    edit: Some(WorkspaceEdit::new(changes)),
+                    is_preferred: Some(a.preferred),
+                    ..Default::default()
+                }))
+            })
+            .collect();
+        Ok(Some(response))
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> jsonrpc::Result<Option<Vec<CallHierarchyItem>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        let node = match self.event_at(&uri, pos).await {
+            Some(name) => Node::Event { name },
+            None => {
+                let Some(table) = self.symbols(&uri).await else {
+                    return Ok(None);
+                };
+                let Some(r) = table.reference_at(pos.line as usize + 1, pos.character as usize + 1)
+                else {
+                    return Ok(None);
+                };
+                let path = match table.definition(r) {
+                    Some(sym) if sym.scope == 0 => doc_path(&uri),
+                    Some(_) => return Ok(None),
+                    None => match self.index.read().await.definitions(&r.name).first() {
+                        Some((path, _)) => path.to_path_buf(),
+                        None => return Ok(None),
+                    },
+                };
+                Node::Function {
+                    name: r.name.clone(),
+                    path,
+                }
+            }
+        };
+        let index = self.index.read().await;
+        Ok(hierarchy_item(&index, &node).map(|item| vec![item]))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> jsonrpc::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let Some(node) = item_node(&params.item) else {
+            return Ok(None);
+        };
+        let index = self.index.read().await;
+        let mut calls: Vec<(Node, Vec<Range>)> = Vec::new();
+        match &node {
+            // Scripts and functions that emit the event
+            Node::Event { name } => {
+                for (path, site) in index.event_sites(name) {
+                    if site.kind == SiteKind::Emitter {
+                        let from = Node::caller(path, site.function.as_deref());
+                        add_call(&mut calls, from, site.name_span);
+                    }
+                }
+            }
+            // Callers of the function, and the events it handles
+            Node::Function { name, path } => {
+                for (p, call) in index.calls_to(name) {
+                    add_call(
+                        &mut calls,
+                        Node::caller(p, call.caller.as_deref()),
+                        call.span,
+                    );
+                }
+                for (p, site) in index
+                    .files()
+                    .flat_map(|(p, f)| f.events.sites.iter().map(move |s| (p.as_path(), s)))
+                {
+                    let anonymous = name.starts_with("on(") && p != path.as_path();
+                    if site.handler.as_ref() == Some(name) && !anonymous {
+                        let from = Node::Event {
+                            name: site.event.clone(),
                         };
-                        return Ok(Some(GotoDefinitionResponse::Scalar(loc)));
+                        add_call(&mut calls, from, site.name_span);
                     }
                 }
             }
+            Node::Script { .. } => {}
         }
-        Ok(None)
+        let incoming = calls
+            .into_iter()
+            .filter_map(|(from, from_ranges)| {
+                Some(CallHierarchyIncomingCall {
+                    from: hierarchy_item(&index, &from)?,
+                    from_ranges,
+                })
+            })
+            .collect();
+        Ok(Some(incoming))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> jsonrpc::Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let Some(node) = item_node(&params.item) else {
+            return Ok(None);
+        };
+        let index = self.index.read().await;
+        let mut calls: Vec<(Node, Vec<Range>)> = Vec::new();
+        let (path, owner) = match &node {
+            // The handlers run for the event
+            Node::Event { name } => {
+                for (path, site) in index.event_sites(name) {
+                    if let Some(handler) = &site.handler {
+                        let to = Node::Function {
+                            name: handler.clone(),
+                            path: path.to_path_buf(),
+                        };
+                        add_call(&mut calls, to, site.name_span);
+                    }
+                }
+                (None, None)
+            }
+            Node::Function { name, path } => (Some(path), Some(name.as_str())),
+            Node::Script { path } => (Some(path), None),
+        };
+        // Events a function (or a script's top level) emits and the functions it calls
+        if let Some((path, file)) = path.and_then(|p| Some((p, index.file(p)?))) {
+            for site in &file.events.sites {
+                if site.kind == SiteKind::Emitter && site.function.as_deref() == owner {
+                    let to = Node::Event {
+                        name: site.event.clone(),
+                    };
+                    add_call(&mut calls, to, site.name_span);
+                }
+            }
+            for call in &file.events.calls {
+                if call.caller.as_deref() == owner {
+                    let to = Node::Function {
+                        name: call.callee.clone(),
+                        path: path.clone(),
+                    };
+                    add_call(&mut calls, to, call.span);
+                }
+            }
+        }
+        let outgoing = calls
+            .into_iter()
+            .filter_map(|(to, from_ranges)| {
+                Some(CallHierarchyOutgoingCall {
+                    to: hierarchy_item(&index, &to)?,
+                    from_ranges,
+                })
+            })
+            .collect();
+        Ok(Some(outgoing))
     }
 
     async fn initialized(&self, _: InitializedParams) {
         self.client
             .log_message(MessageType::INFO, "Questicle LSP initialized")
             .await;
+        self.reload_lint_config().await;
+        self.reload_decls().await;
+        self.rebuild_index().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        if params
+            .changes
+            .iter()
+            .any(|c| c.uri.path().ends_with(".qkd"))
+        {
+            self.reload_decls().await;
+        }
+        if params
+            .changes
+            .iter()
+            .any(|c| c.uri.path().ends_with(lint::CONFIG_FILE))
+        {
+            self.reload_lint_config().await;
+            self.republish_all().await;
+        }
+        // Scripts edited outside the editor; open documents keep their unsaved text
+        let open: Vec<Url> = self.docs.read().await.keys().cloned().collect();
+        for change in &params.changes {
+            if !change.uri.path().ends_with(".qk") || open.contains(&change.uri) {
+                continue;
+            }
+            let checked = self.index.write().await.reload(&doc_path(&change.uri));
+            self.publish_checked(&checked).await;
+        }
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
@@ -90,7 +682,32 @@ impl LanguageServer for Backend {
         let uri = params.text_document.uri;
         let text = params.text_document.text;
         self.docs.write().await.insert(uri.clone(), text.clone());
-        self.publish_diagnostics(uri, text).await;
+        // Unchanged from the indexed text on disk: nothing was re-checked, but the
+        // document still needs its diagnostics
+        if !self.index_document(&uri, text).await {
+            self.publish_diagnostics(uri).await;
+        }
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        // Back to the text on disk
+        let uri = params.text_document.uri;
+        self.docs.write().await.remove(&uri);
+        let checked = self.index.write().await.reload(&doc_path(&uri));
+        self.publish_checked(&checked).await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        // Accept either the bare settings object or one nested under "questicle"
+        let value = match params.settings.get("questicle") {
+            Some(v) => v.clone(),
+            None => params.settings,
+        };
+        if let Ok(settings) = serde_json::from_value::<Settings>(value) {
+            *self.settings.write().await = settings;
+        }
+        self.republish_all().await;
+        let _ = self.client.inlay_hint_refresh().await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
@@ -98,15 +715,62 @@ impl LanguageServer for Backend {
         if let Some(change) = params.content_changes.into_iter().last() {
             let text = change.text;
             self.docs.write().await.insert(uri.clone(), text.clone());
-            self.publish_diagnostics(uri, text).await;
+            self.index_document(&uri, text).await;
         }
     }
 
     async fn completion(
         &self,
-        _params: CompletionParams,
+        params: CompletionParams,
     ) -> jsonrpc::Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
+        let decls = self.decls.read().await.clone();
         let mut items = Vec::new();
+        let text = self.docs.read().await.get(&uri).cloned();
+        if let Some(text) = &text {
+            let before = text_before(text, pos);
+            // Inside host("...: offer the declared op names
+            if host_op_prefix(before).is_some() {
+                let items = decls
+                    .host_ops
+                    .values()
+                    .map(|d| decl_completion(d, DeclKind::HostOp, CompletionItemKind::EVENT))
+                    .collect();
+                return Ok(Some(CompletionResponse::Array(items)));
+            }
+            // Inside the payload record of host("op", { ...: offer the fields not written yet
+            if let Some((op, written)) = host_payload_context(before) {
+                if let Some(d) = decls.host_ops.get(&op) {
+                    if let Some(typecheck::Type::Record(fields)) =
+                        d.param_types().into_iter().next()
+                    {
+                        let items = fields
+                            .iter()
+                            .filter(|(name, _)| !written.contains(name))
+                            .map(|(name, ty)| CompletionItem {
+                                label: name.clone(),
+                                kind: Some(CompletionItemKind::FIELD),
+                                detail: Some(ty.to_string()),
+                                insert_text: Some(format!("{}: ", name)),
+                                ..Default::default()
+                            })
+                            .collect();
+                        return Ok(Some(CompletionResponse::Array(items)));
+                    }
+                }
+            }
+            let check = self.check_options(&uri, text).await;
+            let (line, col) = (pos.line as usize + 1, pos.character as usize + 1);
+            let found = match complete::complete(text, line, col, &check) {
+                Completions::Fields(found) | Completions::MissingFields(found) => {
+                    let items = found.into_iter().map(completion_item).collect();
+                    return Ok(Some(CompletionResponse::Array(items)));
+                }
+                Completions::Names(found) => found,
+            };
+            items.extend(found.into_iter().map(completion_item));
+        }
         for kw in [
             "let", "fn", "if", "else", "while", "for", "in", "return", "true", "false", "null",
         ] {
@@ -117,7 +781,8 @@ impl LanguageServer for Backend {
             });
         }
         for bi in [
-            "print", "clock", "random", "len", "keys", "push", "pop", "on", "emit", "host",
+            "print", "clock", "random", "len", "keys", "push", "pop", "on", "emit", "host", "log",
+            "set",
         ] {
             items.push(CompletionItem {
                 label: bi.to_string(),
@@ -125,6 +790,13 @@ impl LanguageServer for Backend {
                 ..Default::default()
             });
         }
+        for d in decls.natives.values() {
+            items.push(decl_completion(
+                d,
+                DeclKind::Native,
+                CompletionItemKind::FUNCTION,
+            ));
+        }
         for ty_kw in ["number", "string", "bool", "null", "any", "list", "map"] {
             items.push(CompletionItem {
                 label: ty_kw.to_string(),
@@ -144,6 +816,25 @@ impl LanguageServer for Backend {
             // naive: split by lines and try to identify a word
             if let Some(line) = text.lines().nth(pos.line as usize) {
                 let word = word_at(line, pos.character as usize);
+                // Declared host op names (inside host("...")) and natives
+                let decls = self.decls.read().await.clone();
+                let dotted = dotted_at(line, pos.character as usize);
+                let declared = match decls.host_ops.get(&dotted) {
+                    Some(d) if line.contains(&format!("host(\"{}\"", dotted)) => {
+                        Some((d, DeclKind::HostOp))
+                    }
+                    _ => decls.natives.get(&word).map(|d| (d, DeclKind::Native)),
+                };
+                if let Some((d, kind)) = declared {
+                    let contents = HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: decl_markdown(d, kind),
+                    });
+                    return Ok(Some(Hover {
+                        contents,
+                        range: None,
+                    }));
+                }
                 let doc = builtin_doc(&word).or_else(|| keyword_doc(&word));
                 if let Some(d) = doc {
                     let contents = HoverContents::Scalar(MarkedString::String(d.to_string()));
@@ -153,8 +844,8 @@ impl LanguageServer for Backend {
                     }));
                 }
                 // Try type info
-                if let Ok(program) = Parser::new(text).parse_program() {
-                    let tc = questicle::typecheck::check_program(&program);
+                let index = self.index.read().await;
+                if let Some(tc) = index.file(&doc_path(&uri)).and_then(|f| f.types.as_ref()) {
                     // Support dotted paths like slime.name or deeper a.b.c
                     let dotted = dotted_at(line, pos.character as usize);
                     let token = if dotted.contains('.') { dotted } else { word };
@@ -264,8 +955,8 @@ impl LanguageServer for Backend {
                 let upto_len = std::cmp::min(pos.character as usize, line_str.len());
                 let upto = &line_str[..upto_len];
                 if let Some((fname, arg_index)) = extract_call_context(upto) {
-                    if let Ok(program) = Parser::new(text).parse_program() {
-                        let tc = questicle::typecheck::check_program(&program);
+                    let index = self.index.read().await;
+                    if let Some(tc) = index.file(&doc_path(&uri)).and_then(|f| f.types.as_ref()) {
                         if let Some(questicle::typecheck::Type::Func(params, ret)) =
                             tc.env.vars.get(&fname)
                         {
@@ -287,9 +978,17 @@ impl LanguageServer for Backend {
                                     documentation: None,
                                 })
                                 .collect();
+                            let documentation = self
+                                .decls
+                                .read()
+                                .await
+                                .natives
+                                .get(&fname)
+                                .and_then(|d| d.doc.clone())
+                                .map(Documentation::String);
                             let sig = SignatureInformation {
                                 label,
-                                documentation: None,
+                                documentation,
                                 parameters: Some(parameters),
                                 active_parameter: Some(arg_index as u32),
                             };
@@ -308,92 +1007,384 @@ impl LanguageServer for Backend {
 }
 
 impl Backend {
-    async fn publish_diagnostics(&self, uri: Url, text: String) {
-        // Parse and publish errors
-        match Parser::new(&text).parse_program() {
-            Ok(program) => {
-                // Run type checker
-                let tc = typecheck::check_program(&program);
-                let mut diags = Vec::new();
-                for e in tc.errors {
-                    // Heuristic: if we know the subject (var/function name), find its first occurrence
-                    let range = if let Some(ref name) = e.subject {
-                        if let Some((line, start, end)) = find_first_occurrence(&text, name) {
-                            Range::new(
-                                Position::new(line as u32, start as u32),
-                                Position::new(line as u32, end as u32),
-                            )
-                        } else {
-                            Range::new(Position::new(0, 0), Position::new(0, 1))
-                        }
-                    } else {
-                        Range::new(Position::new(0, 0), Position::new(0, 1))
-                    };
-                    // Append location and hint if available
-                    let line = range.start.line + 1; // 1-based for display
-                    let col = range.start.character + 1;
-                    let full_msg = if let Some(ref hint) = e.hint {
-                        format!(
-                            "{} (at line {}, col {})\nHint: {}",
-                            e.message, line, col, hint
-                        )
-                    } else {
-                        format!("{} (at line {}, col {})", e.message, line, col)
-                    };
-                    let d = Diagnostic {
-                        range,
-                        severity: Some(DiagnosticSeverity::WARNING),
-                        code: None,
-                        code_description: None,
-                        source: Some("questicle-typecheck".into()),
-                        message: full_msg,
-                        related_information: None,
-                        tags: None,
-                        data: None,
-                    };
-                    diags.push(d);
-                }
-                self.client.publish_diagnostics(uri, diags, None).await;
+    // questicle/emitters and questicle/handlers: the `emit` or `on` sites of the event
+    // named at the position
+    async fn emitters(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<Vec<Location>>> {
+        let Some(event) = self
+            .event_at(&params.text_document.uri, params.position)
+            .await
+        else {
+            return Ok(None);
+        };
+        Ok(Some(
+            self.event_locations(&event, Some(SiteKind::Emitter)).await,
+        ))
+    }
+
+    async fn handlers(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<Vec<Location>>> {
+        let Some(event) = self
+            .event_at(&params.text_document.uri, params.position)
+            .await
+        else {
+            return Ok(None);
+        };
+        Ok(Some(
+            self.event_locations(&event, Some(SiteKind::Handler)).await,
+        ))
+    }
+
+    // The event whose name literal in an `on`/`emit` call is at the position
+    async fn event_at(&self, uri: &Url, pos: Position) -> Option<String> {
+        let index = self.index.read().await;
+        let file = index.file(&doc_path(uri))?;
+        let site = file
+            .events
+            .site_at(pos.line as usize + 1, pos.character as usize + 1)?;
+        Some(site.event.clone())
+    }
+
+    async fn event_locations(&self, event: &str, kind: Option<SiteKind>) -> Vec<Location> {
+        self.index
+            .read()
+            .await
+            .event_sites(event)
+            .into_iter()
+            .filter(|(_, site)| kind.is_none_or(|k| site.kind == k))
+            .filter_map(|(path, site)| {
+                Some(Location::new(path_uri(path)?, span_range(site.name_span)))
+            })
+            .collect()
+    }
+
+    // Compute and remember the semantic tokens of a document under a new result id
+    async fn fresh_semantic_tokens(&self, uri: &Url) -> Option<SentTokens> {
+        let text = self.docs.read().await.get(uri)?.clone();
+        let decls = self.decls.read().await.clone();
+        let tokens = highlight::semantic_tokens(&text, Some(&decls))?;
+        let data = encode_tokens(&tokens);
+        let id = self
+            .next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        self.semantic
+            .write()
+            .await
+            .insert(uri.clone(), (id.clone(), data.clone()));
+        Some((id, data))
+    }
+
+    // Scope analysis of a document, if it parses
+    async fn symbols(&self, uri: &Url) -> Option<SymbolTable> {
+        let index = self.index.read().await;
+        let file = index.file(&doc_path(uri))?;
+        file.program()?;
+        Some(file.symbols.clone())
+    }
+
+    // Options for checking one document, with the names it takes from other scripts
+    async fn check_options(&self, uri: &Url, text: &str) -> typecheck::CheckOptions {
+        let globals = self.index.read().await.globals_for(&doc_path(uri));
+        typecheck::CheckOptions {
+            strict: self.settings.read().await.strict || typecheck::has_strict_pragma(text),
+            decls: Some(self.decls.read().await.clone()),
+            globals: Some(Arc::new(globals)),
+        }
+    }
+
+    // Options for the index, which applies each script's strict pragma itself
+    async fn index_options(&self) -> typecheck::CheckOptions {
+        typecheck::CheckOptions {
+            strict: self.settings.read().await.strict,
+            decls: Some(self.decls.read().await.clone()),
+            ..Default::default()
+        }
+    }
+
+    // Scan the workspace roots into a fresh index
+    async fn rebuild_index(&self) {
+        let roots = self.roots.read().await.clone();
+        let mut index = WorkspaceIndex::new(self.index_options().await);
+        let count = index.scan(&roots);
+        for (uri, text) in self.docs.read().await.iter() {
+            index.update(&doc_path(uri), text.clone());
+        }
+        *self.index.write().await = index;
+        self.client
+            .log_message(MessageType::INFO, format!("Indexed {} script(s)", count))
+            .await;
+    }
+
+    // Index new text for a document and republish diagnostics for it and every open
+    // document re-checked because it depends on it, or sharing an event whose `on`/`emit`
+    // sites changed; false when the text was unchanged
+    async fn index_document(&self, uri: &Url, text: String) -> bool {
+        let checked = self.index.write().await.update(&doc_path(uri), text);
+        self.publish_checked(&checked).await;
+        !checked.is_empty()
+    }
+
+    async fn publish_checked(&self, checked: &[PathBuf]) {
+        let open: Vec<Url> = self.docs.read().await.keys().cloned().collect();
+        for uri in open {
+            if checked.contains(&doc_path(&uri)) {
+                self.publish_diagnostics(uri).await;
             }
+        }
+    }
+
+    // Re-read qklint.json from the first workspace root
+    async fn reload_lint_config(&self) {
+        let root = self.roots.read().await.first().cloned();
+        let config = match root {
+            Some(dir) => LintConfig::find(&dir),
+            None => Ok(LintConfig::default()),
+        };
+        match config {
+            Ok(c) => *self.lint.write().await = c,
             Err(e) => {
-                // crude mapping: only has line/col in our error types in most cases
-                let (line, col, msg) = match e {
-                    questicle::parser::ParseError::Unexpected { line, col } => {
-                        (line, col, "Unexpected token".to_string())
-                    }
-                    questicle::parser::ParseError::Expected {
-                        expected,
-                        line,
-                        col,
-                    } => (line, col, format!("Expected {expected}")),
-                    questicle::parser::ParseError::Eof => (1, 1, "Unexpected end of input".into()),
+                self.client
+                    .show_message(MessageType::WARNING, format!("Lint config error: {}", e))
+                    .await;
+            }
+        }
+    }
+
+    // Re-read every .qkd file under the workspace roots and re-check open documents
+    async fn reload_decls(&self) {
+        let roots = self.roots.read().await.clone();
+        let decls = match Declarations::load(&roots) {
+            Ok(d) => d,
+            Err(e) => {
+                self.client
+                    .show_message(MessageType::WARNING, format!("Declaration error: {}", e))
+                    .await;
+                return;
+            }
+        };
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!(
+                    "Loaded {} host op(s) and {} native(s) from declaration files",
+                    decls.host_ops.len(),
+                    decls.natives.len()
+                ),
+            )
+            .await;
+        *self.decls.write().await = Arc::new(decls);
+        self.republish_all().await;
+    }
+
+    // Re-check every script with the current settings and declarations
+    async fn republish_all(&self) {
+        let options = self.index_options().await;
+        self.index.write().await.set_options(options);
+        let open: Vec<Url> = self.docs.read().await.keys().cloned().collect();
+        for uri in open {
+            self.publish_diagnostics(uri).await;
+        }
+    }
+
+    // Parse and type-check results come from the index; strict diagnostics are errors
+    async fn publish_diagnostics(&self, uri: Url) {
+        let mut found = Vec::new();
+        let index = self.index.read().await;
+        if let Some(file) = index.file(&doc_path(&uri)) {
+            found = file.diagnostics();
+            if let Some(program) = file.program() {
+                // Events handled by other scripts in the workspace count as handled
+                found.extend(lint::lint_program_in(
+                    program,
+                    &file.source,
+                    &*self.lint.read().await,
+                    &index.event_graph(),
+                ));
+            }
+        }
+        drop(index);
+        let diags = found
+            .into_iter()
+            .map(|d| {
+                let message = match d.hint {
+                    Some(ref hint) => format!("{}\nHint: {}", d.message, hint),
+                    None => d.message.clone(),
                 };
-                let diag = Diagnostic {
-                    range: Range::new(
-                        Position::new(
-                            (line.saturating_sub(1)) as u32,
-                            (col.saturating_sub(1)) as u32,
-                        ),
-                        Position::new(
-                            (line.saturating_sub(1)) as u32,
-                            (col.saturating_sub(1) + 1) as u32,
-                        ),
-                    ),
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    code: None,
-                    code_description: None,
-                    source: Some("questicle".into()),
-                    message: msg,
-                    related_information: None,
-                    tags: None,
-                    data: None,
+                let severity = match d.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
                 };
-                self.client.publish_diagnostics(uri, vec![diag], None).await;
-            }
+                let source = match d.source {
+                    "parse" => "questicle",
+                    "lint" => "questicle-lint",
+                    _ => "questicle-typecheck",
+                };
+                Diagnostic {
+                    range: span_range(d.span),
+                    severity: Some(severity),
+                    source: Some(source.into()),
+                    code: d.code.map(|c| NumberOrString::String(c.into())),
+                    message,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        self.client.publish_diagnostics(uri, diags, None).await;
+    }
+}
+
+// What a call hierarchy item stands for; sent to the client as the item's data
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Node {
+    Event { name: String },
+    // A named function, or a handler literal `on("event")`, of the script at `path`
+    Function { name: String, path: PathBuf },
+    // Code at the top level of a script
+    Script { path: PathBuf },
+}
+
+impl Node {
+    fn caller(path: &Path, function: Option<&str>) -> Node {
+        match function {
+            Some(name) => Node::Function {
+                name: name.to_string(),
+                path: path.to_path_buf(),
+            },
+            None => Node::Script {
+                path: path.to_path_buf(),
+            },
         }
     }
 }
 
+fn item_node(item: &CallHierarchyItem) -> Option<Node> {
+    serde_json::from_value(item.data.clone()?).ok()
+}
+
+fn add_call(calls: &mut Vec<(Node, Vec<Range>)>, node: Node, span: Span) {
+    match calls.iter_mut().find(|(n, _)| *n == node) {
+        Some((_, ranges)) => ranges.push(span_range(span)),
+        None => calls.push((node, vec![span_range(span)])),
+    }
+}
+
+// None for functions declared in no script (builtins and host natives)
+fn hierarchy_item(index: &WorkspaceIndex, node: &Node) -> Option<CallHierarchyItem> {
+    let file_name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned());
+    let (name, kind, path, range, selection) = match node {
+        Node::Event { name } => {
+            let sites = index.event_sites(name);
+            let (path, site) = sites.first()?;
+            (
+                name.clone(),
+                SymbolKind::EVENT,
+                path.to_path_buf(),
+                site.name_span,
+                site.name_span,
+            )
+        }
+        Node::Function { name, path } if name.starts_with("on(") => {
+            let file = index.file(path)?;
+            let site = file
+                .events
+                .sites
+                .iter()
+                .find(|s| s.handler.as_ref() == Some(name))?;
+            (
+                name.clone(),
+                SymbolKind::FUNCTION,
+                path.clone(),
+                site.span,
+                site.name_span,
+            )
+        }
+        Node::Function { name, path } => {
+            let defs: Vec<_> = index
+                .definitions(name)
+                .into_iter()
+                .filter(|(_, sym)| sym.kind == symbols::SymbolKind::Function)
+                .collect();
+            let (p, sym) = defs
+                .iter()
+                .find(|(p, _)| *p == path.as_path())
+                .or(defs.first())?;
+            (
+                name.clone(),
+                SymbolKind::FUNCTION,
+                p.to_path_buf(),
+                sym.span,
+                sym.span,
+            )
+        }
+        Node::Script { path } => {
+            let start = Span::new(1, 1, 1, 1);
+            (
+                file_name(path).unwrap_or_default(),
+                SymbolKind::FILE,
+                path.clone(),
+                start,
+                start,
+            )
+        }
+    };
+    Some(CallHierarchyItem {
+        name,
+        kind,
+        tags: None,
+        detail: file_name(&path),
+        uri: path_uri(&path)?,
+        range: span_range(range),
+        selection_range: span_range(selection),
+        data: serde_json::to_value(node).ok(),
+    })
+}
+
+// Index key of a document: its file path, or the URI itself for unsaved documents
+fn doc_path(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|_| PathBuf::from(uri.as_str()))
+}
+
+fn path_uri(path: &Path) -> Option<Url> {
+    Url::from_file_path(path)
+        .ok()
+        .or_else(|| Url::parse(path.to_str()?).ok())
+}
+
+fn completion_item(c: complete::Completion) -> CompletionItem {
+    CompletionItem {
+        label: c.label,
+        kind: Some(match c.kind {
+            Kind::Field => CompletionItemKind::FIELD,
+            Kind::Variable => CompletionItemKind::VARIABLE,
+            Kind::Function => CompletionItemKind::FUNCTION,
+        }),
+        detail: Some(c.detail),
+        insert_text: c.insert_text,
+        ..Default::default()
+    }
+}
+
+// LSP range (0-based) of a 1-based source span
+fn span_range(span: Span) -> Range {
+    Range::new(
+        Position::new(
+            span.line.saturating_sub(1) as u32,
+            span.col.saturating_sub(1) as u32,
+        ),
+        Position::new(
+            span.end_line.saturating_sub(1) as u32,
+            span.end_col.saturating_sub(1) as u32,
+        ),
+    )
+}
+
 fn word_at(line: &str, col: usize) -> String {
     fn is_ident(c: char) -> bool {
         c.is_alphanumeric() || c == '_'
@@ -411,41 +1402,6 @@ fn word_at(line: &str, col: usize) -> String {
     chars[start..end].iter().collect()
 }
 
-fn find_first_occurrence(text: &str, needle: &str) -> Option<(usize, usize, usize)> {
-    for (i, line) in text.lines().enumerate() {
-        if let Some(pos) = line.find(needle) {
-            return Some((i, pos, pos + needle.len()));
-        }
-    }
-    None
-}
-
-fn find_decl_of(text: &str, name: &str) -> Option<(usize, usize, usize)> {
-    for (i, line) in text.lines().enumerate() {
-        let trimmed = line.trim_start();
-        if let Some(after) = trimmed.strip_prefix("let ") {
-            let ident: String = after
-                .chars()
-                .take_while(|c| c.is_alphanumeric() || *c == '_')
-                .collect();
-            if ident == name {
-                let start = line.find(&ident)?;
-                return Some((i, start, start + ident.len()));
-            }
-        } else if let Some(after) = trimmed.strip_prefix("fn ") {
-            let ident: String = after
-                .chars()
-                .take_while(|c| c.is_alphanumeric() || *c == '_')
-                .collect();
-            if ident == name {
-                let start = line.find(&ident)?;
-                return Some((i, start, start + ident.len()));
-            }
-        }
-    }
-    None
-}
-
 fn builtin_doc(name: &str) -> Option<&'static str> {
     match name {
         "print" => Some("print(...): prints values to console"),
@@ -455,9 +1411,11 @@ fn builtin_doc(name: &str) -> Option<&'static str> {
         "keys" => Some("keys(map): list of string keys"),
         "push" => Some("push(list, value): returns new list with value appended"),
         "pop" => Some("pop(list): returns last element or null"),
+        "set" => Some("set(map, key, value) / set(list, index, value): returns updated copy"),
         "on" => Some("on(name, fn): register event handler"),
         "emit" => Some("emit(name, data): emit event"),
         "host" => Some("host(op, payload): call host bridge"),
+        "log" => Some("log.debug/info/warn/error(message, fields): log with structured fields"),
         _ => None,
     }
 }
@@ -479,10 +1437,20 @@ fn keyword_doc(name: &str) -> Option<&'static str> {
 #[tokio::main]
 async fn main() {
     let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
-    let (service, socket) = LspService::new(|client| Backend {
+    let (service, socket) = LspService::build(|client| Backend {
         client,
         docs: Arc::new(RwLock::new(HashMap::new())),
-    });
+        settings: Arc::new(RwLock::new(Settings::default())),
+        roots: Arc::new(RwLock::new(Vec::new())),
+        decls: Arc::new(RwLock::new(Arc::new(Declarations::default()))),
+        lint: Arc::new(RwLock::new(LintConfig::default())),
+        semantic: Arc::new(RwLock::new(HashMap::new())),
+        next_result_id: Arc::new(AtomicU64::new(1)),
+        index: Arc::new(RwLock::new(WorkspaceIndex::default())),
+    })
+    .custom_method("questicle/emitters", Backend::emitters)
+    .custom_method("questicle/handlers", Backend::handlers)
+    .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
 
@@ -583,3 +1551,124 @@ fn resolve_hover_type(env: &questicle::typecheck::TypeEnv, token: &str) -> Optio
     }
     Some(format!("{}: {}", token, t))
 }
+
+// Text of the document before the given position
+fn text_before(text: &str, pos: Position) -> &str {
+    let mut offset = 0;
+    for (i, line) in text.split('\n').enumerate() {
+        if i == pos.line as usize {
+            let col = line
+                .char_indices()
+                .nth(pos.character as usize)
+                .map(|(b, _)| b)
+                .unwrap_or(line.len());
+            return &text[..offset + col];
+        }
+        offset += line.len() + 1;
+    }
+    text
+}
+
+// If the cursor is inside the op string of `host("...`, return what has been typed of it
+fn host_op_prefix(before: &str) -> Option<&str> {
+    let idx = before.rfind("host(\"")?;
+    let rest = &before[idx + 6..];
+    if rest.contains('"') || rest.contains('\n') {
+        None
+    } else {
+        Some(rest)
+    }
+}
+
+// If the cursor is inside the payload record of `host("op", { ...`, return the op
+// and the field names already written
+fn host_payload_context(before: &str) -> Option<(String, Vec<String>)> {
+    let idx = before.rfind("host(\"")?;
+    let rest = &before[idx + 6..];
+    let close = rest.find('"')?;
+    let op = &rest[..close];
+    let body = rest[close + 1..]
+        .trim_start()
+        .strip_prefix(',')?
+        .trim_start()
+        .strip_prefix('{')?;
+    let mut depth = 1;
+    for c in body.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return None;
+        }
+    }
+    let written = body
+        .split([',', '{', '\n'])
+        .filter_map(|part| part.split_once(':'))
+        .map(|(key, _)| key.trim().to_string())
+        .collect();
+    Some((op.to_string(), written))
+}
+
+fn decl_markdown(d: &FnDecl, kind: DeclKind) -> String {
+    let mut md = format!("```questicle\n{}\n```", d.signature(kind));
+    if let Some(ref doc) = d.doc {
+        md.push_str("\n\n");
+        md.push_str(doc);
+    }
+    md
+}
+
+fn decl_completion(d: &FnDecl, kind: DeclKind, item_kind: CompletionItemKind) -> CompletionItem {
+    CompletionItem {
+        label: d.name.clone(),
+        kind: Some(item_kind),
+        detail: Some(d.signature(kind)),
+        documentation: d.doc.clone().map(Documentation::String),
+        ..Default::default()
+    }
+}
+
+// LSP encoding: each token relative to the previous one, 0-based
+fn encode_tokens(tokens: &[highlight::SemanticToken]) -> Vec<SemanticToken> {
+    let (mut line, mut col) = (0u32, 0u32);
+    tokens
+        .iter()
+        .map(|t| {
+            let l = t.line.saturating_sub(1) as u32;
+            let c = t.col.saturating_sub(1) as u32;
+            let delta_line = l - line;
+            let delta_start = if delta_line == 0 { c - col } else { c };
+            line = l;
+            col = c;
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: t.len as u32,
+                token_type: t.ty.index(),
+                token_modifiers_bitset: t.modifiers,
+            }
+        })
+        .collect()
+}
+
+// A single edit replacing the changed middle of the token array (offsets count u32s,
+// five per token)
+fn token_edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    if prefix == old.len() && prefix == new.len() {
+        return Vec::new();
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: ((old.len() - prefix - suffix) * 5) as u32,
+        data: Some(new[prefix..new.len() - suffix].to_vec()),
+    }]
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: MIT
+// Source-level checking shared by `qk check` and the language server: parse, type-check
+// and report everything found as located diagnostics.
+use serde::Serialize;
+
+use crate::ast::Span;
+use crate::parser::{ParseError, Parser};
+use crate::typecheck::{self, CheckOptions, TypeCheckResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // Which pass produced it: "parse", "typecheck" or "lint"
+    pub source: &'static str,
+    // Lint rule name, for lint diagnostics
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub hint: Option<String>,
+    pub span: Span,
+}
+
+// Parse and type-check one source file. A `// qk: strict` pragma turns strict mode on
+// for that file; type errors are errors in strict mode and warnings otherwise.
+pub fn check_source(src: &str, opts: &CheckOptions) -> Vec<Diagnostic> {
+    let program = match Parser::new(src).parse_program() {
+        Ok(p) => p,
+        Err(e) => return vec![parse_diagnostic(src, &e)],
+    };
+    let mut opts = opts.clone();
+    opts.strict |= typecheck::has_strict_pragma(src);
+    type_diagnostics(&typecheck::check_program_with_options(&program, &opts))
+}
+
+// The problems found by the type checker; errors in strict mode, warnings otherwise
+pub fn type_diagnostics(tc: &TypeCheckResult) -> Vec<Diagnostic> {
+    let severity = if tc.strict {
+        Severity::Error
+    } else {
+        Severity::Warning
+    };
+    tc.errors
+        .iter()
+        .map(|e| Diagnostic {
+            severity,
+            source: "typecheck",
+            code: None,
+            message: e.message.clone(),
+            hint: e.hint.clone(),
+            span: e.span,
+        })
+        .collect()
+}
+
+pub fn parse_diagnostic(src: &str, e: &ParseError) -> Diagnostic {
+    let (line, col, message) = match e {
+        ParseError::Unexpected { line, col } => (*line, *col, "Unexpected token".to_string()),
+        ParseError::Expected {
+            expected,
+            line,
+            col,
+        } => (*line, *col, format!("Expected {}", expected)),
+        ParseError::Eof => {
+            // Point just past the last character of the file
+            let line = src.lines().count().max(1);
+            let col = src.lines().last().map(|l| l.chars().count()).unwrap_or(0) + 1;
+            (line, col, "Unexpected end of input".to_string())
+        }
+    };
+    Diagnostic {
+        severity: Severity::Error,
+        source: "parse",
+        code: None,
+        message,
+        hint: None,
+        span: Span::new(line, col, line, col + 1),
+    }
+}
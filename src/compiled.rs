@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: MIT
+// Compiled scripts: a script parsed and type-checked once, then instantiated any number of
+// times, e.g. once per NPC running the same behavior. The program is shared behind an Arc;
+// each instance is an interpreter of its own, with its own globals and host, that has run
+// the script's top level.
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::ast::Program;
+use crate::eval::{Interpreter, RuntimeError};
+use crate::host::Host;
+use crate::parser::{ParseError, Parser};
+use crate::typecheck::{self, CheckOptions, TypeError};
+use crate::value::Value;
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("Parse error: {0}")]
+    Parse(#[from] ParseError),
+    // Strict scripts must type-check cleanly
+    #[error("Type error: {}", .0[0].message)]
+    Type(Vec<TypeError>),
+}
+
+// Cloning is cheap: clones share the program
+#[derive(Debug, Clone)]
+pub struct CompiledScript {
+    program: Arc<Program>,
+    // Type problems found in a script that is not strict, which do not stop it compiling
+    warnings: Arc<Vec<TypeError>>,
+}
+
+impl CompiledScript {
+    pub fn compile(src: &str) -> Result<Self, CompileError> {
+        Self::compile_with_options(src, &CheckOptions::default())
+    }
+
+    // Parse and type-check `src`. As with `qk check`, a `// qk: strict` pragma turns strict
+    // mode on; type errors then fail the compile, and are warnings otherwise.
+    pub fn compile_with_options(src: &str, opts: &CheckOptions) -> Result<Self, CompileError> {
+        let program = Parser::new(src).parse_program()?;
+        let mut opts = opts.clone();
+        opts.strict |= typecheck::has_strict_pragma(src);
+        let tc = typecheck::check_program_with_options(&program, &opts);
+        if tc.strict && !tc.errors.is_empty() {
+            return Err(CompileError::Type(tc.errors));
+        }
+        Ok(Self {
+            program: Arc::new(program),
+            warnings: Arc::new(tc.errors),
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn warnings(&self) -> &[TypeError] {
+        &self.warnings
+    }
+
+    // A new instance bound to `host`, with the script's top level run
+    pub fn instantiate(&self, host: Host) -> Result<Instance, RuntimeError> {
+        self.instantiate_in(Interpreter::with_host(host))
+    }
+
+    // Run the script's top level in `interp`, set up beforehand with an output, hooks or
+    // checked mode, and make it an instance
+    pub fn instantiate_in(&self, mut interp: Interpreter) -> Result<Instance, RuntimeError> {
+        interp.run(&self.program)?;
+        Ok(Instance {
+            script: self.clone(),
+            interp,
+        })
+    }
+}
+
+// One running copy of a compiled script
+pub struct Instance {
+    script: CompiledScript,
+    interp: Interpreter,
+}
+
+impl Instance {
+    pub fn script(&self) -> &CompiledScript {
+        &self.script
+    }
+
+    // Call one of the script's top-level functions, e.g. its `tick`
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.interp.call(name, args)
+    }
+
+    // The value of the global `name` in this instance
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.interp.env.borrow().get(name)
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interp
+    }
+}
@@ -0,0 +1,445 @@
+// SPDX-License-Identifier: MIT
+// Type-aware completion for the language server: the fields of a record after `expr.`, the
+// fields still missing from a record literal of a known type, and the variables and
+// functions in scope. A document being typed rarely parses, so when it does not, the text
+// before the cursor's line is analyzed instead, with its open brackets closed.
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::symbols::{self, Symbol, SymbolKind, SymbolTable};
+use crate::token::{Token, TokenKind};
+use crate::typecheck::{self, CheckOptions, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Field,
+    Variable,
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    // The type, or the signature of a function
+    pub detail: String,
+    pub insert_text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Completions {
+    // After `expr.`: the fields of its record type (empty when the type is not known)
+    Fields(Vec<Completion>),
+    // At a key position inside a record literal of a known record type
+    MissingFields(Vec<Completion>),
+    // Anywhere else: the variables and functions visible at the cursor
+    Names(Vec<Completion>),
+}
+
+// Completions at a 1-based (line, col) position
+pub fn complete(src: &str, line: usize, col: usize, check: &CheckOptions) -> Completions {
+    let analysis = Analysis::new(src, line, col, check);
+    let before = offset(src, line, col).map_or(src, |o| &src[..o]);
+    let current_line = before.rsplit('\n').next().unwrap_or("");
+
+    if let Some((root, path)) = member_chain(current_line) {
+        let ty = analysis
+            .as_ref()
+            .and_then(|a| a.path_type(&root, &path, line, col));
+        let items = match ty {
+            Some(Type::Record(fields)) => fields
+                .iter()
+                .map(|(name, ty)| field(name, ty, None))
+                .collect(),
+            _ => Vec::new(),
+        };
+        return Completions::Fields(items);
+    }
+    if let Some(items) = missing_fields(before, analysis.as_ref(), line, col) {
+        return Completions::MissingFields(items);
+    }
+    Completions::Names(analysis.map(|a| a.names(line, col)).unwrap_or_default())
+}
+
+fn field(name: &str, ty: &Type, insert_text: Option<String>) -> Completion {
+    Completion {
+        label: name.to_string(),
+        kind: CompletionKind::Field,
+        detail: ty.to_string(),
+        insert_text,
+    }
+}
+
+struct Analysis {
+    table: SymbolTable,
+    inferred: HashMap<Span, Type>,
+}
+
+impl Analysis {
+    fn new(src: &str, line: usize, col: usize, check: &CheckOptions) -> Option<Self> {
+        let program = match Parser::new(src).parse_program() {
+            Ok(p) => p,
+            Err(_) => repair(src, line, col)?,
+        };
+        let tc = typecheck::check_program_with_options(&program, check);
+        Some(Self {
+            table: symbols::analyze(&program),
+            inferred: tc.inferred.into_iter().collect(),
+        })
+    }
+
+    // The annotation, unless it is `any`; otherwise what the checker inferred
+    fn symbol_type(&self, sym: &Symbol) -> Type {
+        match &sym.ty {
+            Some(t) if !matches!(t, TypeExpr::Any) => Type::from_expr(t),
+            _ => self.inferred.get(&sym.span).cloned().unwrap_or(Type::Any),
+        }
+    }
+
+    // The innermost binding of each name visible at a position, innermost scopes first;
+    // variables count once declared, functions and parameters anywhere in their scope
+    fn visible(&self, line: usize, col: usize) -> Vec<&Symbol> {
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        let mut scope = Some(self.table.scope_at(line, col));
+        while let Some(id) = scope {
+            for sym in self.table.symbols.iter().rev().filter(|s| s.scope == id) {
+                let declared =
+                    sym.kind != SymbolKind::Variable || (sym.span.line, sym.span.col) < (line, col);
+                if declared && seen.insert(sym.name.as_str()) {
+                    out.push(sym);
+                }
+            }
+            scope = self.table.scopes[id].parent;
+        }
+        out
+    }
+
+    fn lookup(&self, name: &str, line: usize, col: usize) -> Option<Type> {
+        let sym = self
+            .visible(line, col)
+            .into_iter()
+            .find(|s| s.name == name)?;
+        Some(self.symbol_type(sym))
+    }
+
+    fn path_type(&self, root: &str, path: &[Segment], line: usize, col: usize) -> Option<Type> {
+        let mut t = self.lookup(root, line, col)?;
+        for seg in path {
+            t = match (seg, t) {
+                (Segment::Field(name), Type::Record(fields)) => fields.get(name)?.clone(),
+                (Segment::Field(_), Type::Map(inner)) => *inner,
+                (Segment::Index, Type::List(inner)) | (Segment::Index, Type::Map(inner)) => *inner,
+                _ => return None,
+            };
+        }
+        Some(t)
+    }
+
+    fn names(&self, line: usize, col: usize) -> Vec<Completion> {
+        self.visible(line, col)
+            .into_iter()
+            .map(|sym| {
+                let ty = self.symbol_type(sym);
+                match (sym.kind, ty) {
+                    (SymbolKind::Function, Type::Func(args, ret)) => Completion {
+                        label: sym.name.clone(),
+                        kind: CompletionKind::Function,
+                        detail: signature(sym, &args, &ret),
+                        insert_text: None,
+                    },
+                    (kind, ty) => Completion {
+                        label: sym.name.clone(),
+                        kind: if kind == SymbolKind::Function {
+                            CompletionKind::Function
+                        } else {
+                            CompletionKind::Variable
+                        },
+                        detail: ty.to_string(),
+                        insert_text: None,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+// `fn name(a: number, b: string) -> bool`
+fn signature(sym: &Symbol, args: &[Type], ret: &Type) -> String {
+    let params: Vec<String> = sym
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("{}: {}", p, args.get(i).unwrap_or(&Type::Any)))
+        .collect();
+    format!("fn {}({}) -> {}", sym.name, params.join(", "), ret)
+}
+
+// The text before the cursor's line with its open brackets closed; each closer may need a
+// `;` after it (a function literal in a `let`) or not (a block), so every combination is
+// tried until one parses
+fn repair(src: &str, line: usize, col: usize) -> Option<Program> {
+    let prefix = &src[..offset(src, line, 1)?];
+    let mut open = Vec::new();
+    for t in Lexer::new(prefix).lex() {
+        match t.kind {
+            TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket => {
+                open.push(t.kind)
+            }
+            TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    let closers: Vec<&str> = open
+        .iter()
+        .rev()
+        .map(|k| match k {
+            TokenKind::LeftParen => ")",
+            TokenKind::LeftBracket => "]",
+            _ => "}",
+        })
+        .collect();
+    // Closers go at the cursor column so the scopes they end still contain the cursor
+    let pad = " ".repeat(col.saturating_sub(1));
+    let n = closers.len().min(8);
+    for mask in 0..(1u32 << n) {
+        let mut text = format!("{}{}", prefix, pad);
+        for (i, c) in closers.iter().enumerate() {
+            text.push_str(c);
+            if i < n && mask & (1 << i) != 0 {
+                text.push(';');
+            }
+        }
+        if let Ok(p) = Parser::new(&text).parse_program() {
+            return Some(p);
+        }
+    }
+    None
+}
+
+enum Segment {
+    Field(String),
+    Index,
+}
+
+// `hero.bag[0].` (with or without a partial name after the last dot): the root name and the
+// path after it
+fn member_chain(line: &str) -> Option<(String, Vec<Segment>)> {
+    let chars: Vec<char> = line.chars().collect();
+    let ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut i = chars.len();
+    while i > 0 && ident(chars[i - 1]) {
+        i -= 1;
+    }
+    if i == 0 || chars[i - 1] != '.' {
+        return None;
+    }
+    i -= 1;
+    let mut path = Vec::new();
+    loop {
+        if i > 0 && chars[i - 1] == ']' {
+            let mut depth = 0;
+            loop {
+                i = i.checked_sub(1)?;
+                match chars[i] {
+                    ']' => depth += 1,
+                    '[' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            path.push(Segment::Index);
+            continue;
+        }
+        let end = i;
+        while i > 0 && ident(chars[i - 1]) {
+            i -= 1;
+        }
+        if i == end {
+            return None;
+        }
+        let name: String = chars[i..end].iter().collect();
+        if i > 0 && chars[i - 1] == '.' {
+            path.push(Segment::Field(name));
+            i -= 1;
+            continue;
+        }
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        path.reverse();
+        return Some((name, path));
+    }
+}
+
+// Fields not yet written when the cursor is at a key position of a record literal whose
+// type is known from a `let` annotation, an assigned variable or an enclosing literal
+fn missing_fields(
+    before: &str,
+    analysis: Option<&Analysis>,
+    line: usize,
+    col: usize,
+) -> Option<Vec<Completion>> {
+    let tokens = Lexer::new(before).lex();
+    let mut open: Vec<usize> = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        match t.kind {
+            TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket => open.push(i),
+            TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    let &brace = open.last()?;
+    if tokens[brace].kind != TokenKind::LeftBrace {
+        return None;
+    }
+    let mut key = true;
+    let mut depth = 0;
+    let mut written = Vec::new();
+    for (i, t) in tokens.iter().enumerate().skip(brace + 1) {
+        match &t.kind {
+            TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket => depth += 1,
+            TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket => depth -= 1,
+            TokenKind::Comma if depth == 0 => key = true,
+            TokenKind::Colon if depth == 0 => {
+                key = false;
+                if let TokenKind::Identifier(n) | TokenKind::String(n) = &tokens[i - 1].kind {
+                    written.push(n.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    if !key {
+        return None;
+    }
+    let cx = LiteralCx {
+        before,
+        tokens: &tokens,
+        open: &open,
+        analysis,
+        line,
+        col,
+    };
+    let Type::Record(fields) = cx.expected(open.len() - 1)? else {
+        return None;
+    };
+    Some(
+        fields
+            .iter()
+            .filter(|(name, _)| !written.contains(name))
+            .map(|(name, ty)| field(name, ty, Some(format!("{}: ", name))))
+            .collect(),
+    )
+}
+
+struct LiteralCx<'a> {
+    before: &'a str,
+    tokens: &'a [Token],
+    // Indices of the unclosed brackets, outermost first
+    open: &'a [usize],
+    analysis: Option<&'a Analysis>,
+    line: usize,
+    col: usize,
+}
+
+impl LiteralCx<'_> {
+    fn kind(&self, i: usize) -> Option<&TokenKind> {
+        self.tokens.get(i).map(|t| &t.kind)
+    }
+
+    // The type expected for the literal opened by the `k`-th unclosed bracket
+    fn expected(&self, k: usize) -> Option<Type> {
+        let brace = self.open[k];
+        match self.kind(brace.checked_sub(1)?)? {
+            TokenKind::Assign => {
+                let eq = brace - 1;
+                if let Some(t) = self.let_annotation(eq) {
+                    return Some(t);
+                }
+                let Some(TokenKind::Identifier(name)) = self.kind(eq.checked_sub(1)?) else {
+                    return None;
+                };
+                self.analysis?.lookup(name, self.line, self.col)
+            }
+            // `{ field: {`: the field's type in the enclosing literal
+            TokenKind::Colon => {
+                let outer = k.checked_sub(1)?;
+                if self.kind(self.open[outer]) != Some(&TokenKind::LeftBrace) {
+                    return None;
+                }
+                let Some(TokenKind::Identifier(name)) = self.kind(brace.checked_sub(2)?) else {
+                    return None;
+                };
+                match self.expected(outer)? {
+                    Type::Record(fields) => fields.get(name).cloned(),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // The annotation of `let name: Type =`, where `eq` is the index of the `=`
+    fn let_annotation(&self, eq: usize) -> Option<Type> {
+        let mut depth = 0;
+        let mut j = eq;
+        while j > 0 {
+            j -= 1;
+            match self.kind(j)? {
+                TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket => {
+                    depth += 1
+                }
+                TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket => {
+                    if depth == 0 {
+                        return None;
+                    }
+                    depth -= 1;
+                }
+                TokenKind::Semicolon if depth == 0 => return None,
+                TokenKind::Colon if depth == 0 => {
+                    let named =
+                        matches!(self.kind(j.checked_sub(1)?), Some(TokenKind::Identifier(_)));
+                    if !named || self.kind(j.checked_sub(2)?) != Some(&TokenKind::Let) {
+                        return None;
+                    }
+                    let (colon, assign) = (&self.tokens[j], &self.tokens[eq]);
+                    let start = offset(self.before, colon.end_line, colon.end_col)?;
+                    let end = offset(self.before, assign.line, assign.col)?;
+                    let ty = Parser::new(&self.before[start..end])
+                        .parse_type_annotation()
+                        .ok()?;
+                    return Some(Type::from_expr(&ty));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+// Byte offset of a 1-based (line, col) position, col counting characters
+pub(crate) fn offset(src: &str, line: usize, col: usize) -> Option<usize> {
+    let mut start = 0;
+    for (i, l) in src.split('\n').enumerate() {
+        if i + 1 == line {
+            let c = l
+                .char_indices()
+                .nth(col.saturating_sub(1))
+                .map_or(l.len(), |(b, _)| b);
+            return Some(start + c);
+        }
+        start += l.len() + 1;
+    }
+    None
+}
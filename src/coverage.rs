@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: MIT
+// Statement and branch coverage, collected through interpreter hooks. `Coverage` counts how
+// often each statement ran and which way each `if`, `while` and `for` went; `report` lays
+// those counts over a program's lines. Test blocks are test code and are not covered.
+// Reports are written as lcov, which editors and CI services read, or as a summary table.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::eval::Interpreter;
+use crate::hooks::{Flow, Hooks};
+
+// Counts shared by its clones, so one Coverage can watch several interpreters
+#[derive(Clone, Default)]
+pub struct Coverage {
+    counts: Rc<RefCell<Counts>>,
+}
+
+#[derive(Default)]
+struct Counts {
+    statements: HashMap<Span, u64>,
+    // Times each branching statement went [its way, the other way]
+    branches: HashMap<Span, [u64; 2]>,
+}
+
+impl Hooks for Coverage {
+    fn statement(&mut self, _: &mut Interpreter, span: Span) -> Flow {
+        *self.counts.borrow_mut().statements.entry(span).or_default() += 1;
+        Flow::Continue
+    }
+
+    fn branch(&mut self, _: &mut Interpreter, span: Span, taken: bool) -> Flow {
+        let mut counts = self.counts.borrow_mut();
+        let arms = counts.branches.entry(span).or_default();
+        arms[usize::from(!taken)] += 1;
+        Flow::Continue
+    }
+}
+
+// One branching statement: arm 0 is the `if` body (or a loop iteration), arm 1 the `else`
+// (or leaving the loop)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub line: usize,
+    // Index among the branching statements on the line
+    pub block: usize,
+    // None when the statement never ran
+    pub taken: Option<[u64; 2]>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileCoverage {
+    // Times the statements starting on each line ran
+    pub lines: BTreeMap<usize, u64>,
+    pub branches: Vec<Branch>,
+}
+
+impl Coverage {
+    pub fn report(&self, program: &Program) -> FileCoverage {
+        let counts = self.counts.borrow();
+        let mut points = Points::default();
+        for s in &program.statements {
+            points.stmt(s);
+        }
+        let mut report = FileCoverage::default();
+        for span in &points.statements {
+            let hits = counts.statements.get(span).copied().unwrap_or(0);
+            let line = report.lines.entry(span.line).or_default();
+            *line = (*line).max(hits);
+        }
+        let mut per_line: HashMap<usize, usize> = HashMap::new();
+        for span in &points.branches {
+            let block = per_line.entry(span.line).or_default();
+            let reached = counts.statements.contains_key(span);
+            report.branches.push(Branch {
+                line: span.line,
+                block: *block,
+                taken: reached.then(|| counts.branches.get(span).copied().unwrap_or_default()),
+            });
+            *block += 1;
+        }
+        report
+    }
+}
+
+impl FileCoverage {
+    // (hit, found)
+    pub fn line_counts(&self) -> (usize, usize) {
+        let hit = self.lines.values().filter(|&&n| n > 0).count();
+        (hit, self.lines.len())
+    }
+
+    // (hit, found), counting each arm of each branch
+    pub fn branch_counts(&self) -> (usize, usize) {
+        let hit = self
+            .branches
+            .iter()
+            .filter_map(|b| b.taken)
+            .map(|t| t.iter().filter(|&&n| n > 0).count())
+            .sum();
+        (hit, self.branches.len() * 2)
+    }
+}
+
+pub fn to_lcov(files: &[(PathBuf, FileCoverage)]) -> String {
+    let mut out = String::new();
+    for (path, cov) in files {
+        out.push_str(&format!("TN:\nSF:{}\n", path.display()));
+        for b in &cov.branches {
+            for arm in 0..2 {
+                let taken = match b.taken {
+                    Some(t) => t[arm].to_string(),
+                    None => "-".into(),
+                };
+                out.push_str(&format!("BRDA:{},{},{},{}\n", b.line, b.block, arm, taken));
+            }
+        }
+        let (hit, found) = cov.branch_counts();
+        out.push_str(&format!("BRF:{}\nBRH:{}\n", found, hit));
+        for (line, n) in &cov.lines {
+            out.push_str(&format!("DA:{},{}\n", line, n));
+        }
+        let (hit, found) = cov.line_counts();
+        out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", found, hit));
+    }
+    out
+}
+
+pub fn summary_table(files: &[(PathBuf, FileCoverage)]) -> String {
+    let names: Vec<String> = files.iter().map(|(p, _)| p.display().to_string()).collect();
+    let width = names.iter().map(|n| n.len()).chain([5]).max().unwrap_or(5);
+    let cell = |(hit, found): (usize, usize)| {
+        let pct = if found == 0 {
+            100.0
+        } else {
+            hit as f64 * 100.0 / found as f64
+        };
+        format!("{:>9} {:>6.1}%", format!("{}/{}", hit, found), pct)
+    };
+    let mut out = format!("{:<width$}  {:>17}  {:>17}\n", "File", "Lines", "Branches");
+    let (mut lines, mut branches) = ((0, 0), (0, 0));
+    for (name, (_, cov)) in names.iter().zip(files) {
+        let (l, b) = (cov.line_counts(), cov.branch_counts());
+        out.push_str(&format!("{:<width$}  {}  {}\n", name, cell(l), cell(b)));
+        lines = (lines.0 + l.0, lines.1 + l.1);
+        branches = (branches.0 + b.0, branches.1 + b.1);
+    }
+    out.push_str(&format!(
+        "{:<width$}  {}  {}\n",
+        "Total",
+        cell(lines),
+        cell(branches)
+    ));
+    out
+}
+
+// Statements and branching statements of a program, outside test blocks
+#[derive(Default)]
+struct Points {
+    statements: Vec<Span>,
+    branches: Vec<Span>,
+}
+
+impl Points {
+    fn stmt(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Block(body, _) => body.iter().for_each(|s| self.stmt(s)),
+            StmtKind::Test { .. } => {}
+            _ => self.statements.push(s.span),
+        }
+        match &s.kind {
+            StmtKind::Let { init: e, .. } | StmtKind::Expr(e) | StmtKind::Return(Some(e)) => {
+                self.expr(e)
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.branches.push(s.span);
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            }
+            StmtKind::While { cond: e, body } | StmtKind::For { iter: e, body, .. } => {
+                self.branches.push(s.span);
+                self.expr(e);
+                self.stmt(body);
+            }
+            StmtKind::Block(..)
+            | StmtKind::Test { .. }
+            | StmtKind::Return(None)
+            | StmtKind::Break
+            | StmtKind::Continue => {}
+        }
+    }
+
+    // Statements inside function literals
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Fn(f) => f.body.iter().for_each(|s| self.stmt(s)),
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                args.iter().for_each(|a| self.expr(a));
+            }
+            ExprKind::Assign { value: e, .. }
+            | ExprKind::Unary { expr: e, .. }
+            | ExprKind::Field { target: e, .. } => self.expr(e),
+            ExprKind::Binary { left, right, .. }
+            | ExprKind::Index {
+                target: left,
+                index: right,
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::List(items) => items.iter().for_each(|i| self.expr(i)),
+            ExprKind::Map(entries) => entries.iter().for_each(|(_, v)| self.expr(v)),
+            ExprKind::Literal(_) | ExprKind::Var(_, _) => {}
+        }
+    }
+}
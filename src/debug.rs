@@ -0,0 +1,364 @@
+// SPDX-License-Identifier: MIT
+// Debugging support. `Session` is a set of interpreter hooks with line breakpoints
+// (optionally conditional, or logpoints that print instead of stopping) and stepping; it
+// pauses the script by blocking in the statement hook while a `Frontend` such as qk-dap
+// decides what happens.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::*;
+use crate::eval::{Interpreter, RuntimeError};
+use crate::hooks::{Flow, Hooks};
+use crate::parser::Parser;
+use crate::value::{EnvRef, Function, Value};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Breakpoint {
+    pub line: usize,
+    // Stop only when this expression is truthy
+    pub condition: Option<String>,
+    // Log this message instead of stopping; `{expr}` placeholders are evaluated
+    pub log_message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Entry,
+    Breakpoint,
+    Step,
+    Pause,
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+    // End the script with an error
+    Abort,
+}
+
+// A script function being run; the first frame is the script's top level
+#[derive(Clone)]
+pub struct Frame {
+    pub name: String,
+    // The statement running in this frame
+    pub span: Span,
+    // Its environment; None until the frame's first statement
+    pub env: Option<EnvRef>,
+}
+
+// The state of a paused script, for a frontend to inspect
+pub struct Paused<'a> {
+    pub interp: &'a mut Interpreter,
+    // Outermost first
+    pub frames: &'a [Frame],
+    pub breakpoints: &'a mut BTreeMap<usize, Breakpoint>,
+}
+
+impl Paused<'_> {
+    pub fn env(&self, frame: usize) -> EnvRef {
+        self.frames
+            .get(frame)
+            .and_then(|f| f.env.clone())
+            .unwrap_or_else(|| self.interp.env.clone())
+    }
+
+    pub fn evaluate(&mut self, frame: usize, src: &str) -> Result<Value, String> {
+        let env = self.env(frame);
+        evaluate(self.interp, &env, src)
+    }
+}
+
+pub trait Frontend {
+    // Called between statements while the script runs, e.g. to apply breakpoints set
+    // meanwhile; true asks the script to pause
+    fn poll(&mut self, breakpoints: &mut BTreeMap<usize, Breakpoint>) -> bool;
+    // The script is paused until this returns
+    fn stopped(&mut self, reason: &StopReason, paused: Paused<'_>) -> Resume;
+    // Output of a logpoint
+    fn log(&mut self, message: &str, span: Span);
+}
+
+pub struct Session<F> {
+    pub frontend: F,
+    pub breakpoints: BTreeMap<usize, Breakpoint>,
+    pub stop_on_entry: bool,
+    pub stop_on_error: bool,
+    frames: Vec<Frame>,
+    // Pending step and the frame depth it was requested at
+    step: Option<(Resume, usize)>,
+    // Depth and span of the last statement: the statements nested in a statement that
+    // stopped (the body of a one-line `if`, say) do not stop again
+    last: Option<(usize, Span)>,
+    aborted: bool,
+}
+
+impl<F: Frontend> Session<F> {
+    pub fn new(frontend: F) -> Self {
+        Self {
+            frontend,
+            breakpoints: BTreeMap::new(),
+            stop_on_entry: false,
+            stop_on_error: true,
+            frames: vec![Frame {
+                name: "<script>".into(),
+                span: Span::default(),
+                env: None,
+            }],
+            step: None,
+            last: None,
+            aborted: false,
+        }
+    }
+
+    fn stop(&mut self, interp: &mut Interpreter, reason: StopReason) -> Resume {
+        let resume = self.frontend.stopped(
+            &reason,
+            Paused {
+                interp,
+                frames: &self.frames,
+                breakpoints: &mut self.breakpoints,
+            },
+        );
+        self.step = match resume {
+            Resume::StepIn | Resume::StepOver | Resume::StepOut => {
+                Some((resume, self.frames.len()))
+            }
+            _ => None,
+        };
+        resume
+    }
+
+    // Whether the breakpoint on the current line stops; logpoints print instead
+    fn hit(&mut self, interp: &mut Interpreter, env: &EnvRef, span: Span) -> bool {
+        let Some(bp) = self.breakpoints.get(&span.line).cloned() else {
+            return false;
+        };
+        if let Some(cond) = &bp.condition {
+            match evaluate(interp, env, cond) {
+                Ok(v) if !v.truthy() => return false,
+                Ok(_) => {}
+                Err(e) => {
+                    let message = format!("Breakpoint condition '{}' failed: {}", cond, e);
+                    self.frontend.log(&message, span);
+                }
+            }
+        }
+        match &bp.log_message {
+            Some(template) => {
+                let message = interpolate(interp, env, template);
+                self.frontend.log(&message, span);
+                false
+            }
+            None => true,
+        }
+    }
+}
+
+impl<F: Frontend> Hooks for Session<F> {
+    fn statement(&mut self, interp: &mut Interpreter, span: Span) -> Flow {
+        let depth = self.frames.len();
+        let env = interp.env.clone();
+        if let Some(top) = self.frames.last_mut() {
+            top.span = span;
+            top.env = Some(env.clone());
+        }
+        let nested = self.last.is_some_and(|(d, prev)| {
+            d == depth
+                && prev != span
+                && (prev.line, prev.col) <= (span.line, span.col)
+                && (span.end_line, span.end_col) <= (prev.end_line, prev.end_col)
+                && prev.line == span.line
+        });
+        self.last = Some((depth, span));
+
+        let reason = if self.frontend.poll(&mut self.breakpoints) {
+            Some(StopReason::Pause)
+        } else if std::mem::take(&mut self.stop_on_entry) {
+            Some(StopReason::Entry)
+        } else if !nested && self.hit(interp, &env, span) {
+            Some(StopReason::Breakpoint)
+        } else {
+            let stepped = match self.step {
+                Some((Resume::StepIn, _)) => true,
+                Some((Resume::StepOver, from)) => depth <= from,
+                Some((Resume::StepOut, from)) => depth < from,
+                _ => false,
+            };
+            (!nested && stepped).then_some(StopReason::Step)
+        };
+        let Some(reason) = reason else {
+            return Flow::Continue;
+        };
+        if self.stop(interp, reason) == Resume::Abort {
+            self.aborted = true;
+            return Flow::Abort("Stopped by the debugger".into());
+        }
+        Flow::Continue
+    }
+
+    fn enter(&mut self, _: &mut Interpreter, name: &str, call: Span, _: &[Value]) -> Flow {
+        self.frames.push(Frame {
+            name: name.to_string(),
+            span: call,
+            env: None,
+        });
+        Flow::Continue
+    }
+
+    fn exit(&mut self, _: &mut Interpreter, _: &str, _: &Value) -> Flow {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+        }
+        Flow::Continue
+    }
+
+    fn error(&mut self, interp: &mut Interpreter, error: &RuntimeError) {
+        if self.stop_on_error && !self.aborted {
+            self.stop(interp, StopReason::Error(error.to_string()));
+        }
+    }
+}
+
+// Evaluate an expression typed into a debugger (a trailing `;` is optional) in `env`
+pub fn evaluate(interp: &mut Interpreter, env: &EnvRef, src: &str) -> Result<Value, String> {
+    let src = src.trim().trim_end_matches(';');
+    let program = Parser::new(&format!("{};", src))
+        .parse_unresolved()
+        .map_err(|e| e.to_string())?;
+    match program.statements.as_slice() {
+        [Stmt {
+            kind: StmtKind::Expr(e),
+            ..
+        }] => interp.eval_in(env, e).map_err(|e| e.to_string()),
+        _ => Err("Only an expression can be evaluated".into()),
+    }
+}
+
+// Replace each `{expr}` in a logpoint message with the expression's value
+pub fn interpolate(interp: &mut Interpreter, env: &EnvRef, template: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        match evaluate(interp, env, &rest[open + 1..open + close]) {
+            Ok(Value::String(s)) => out.push_str(&s),
+            Ok(v) => out.push_str(&v.to_string()),
+            Err(e) => out.push_str(&format!("<{}>", e)),
+        }
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Bindings visible from `env` short of the global scope, innermost first
+pub fn locals(env: &EnvRef) -> Vec<(String, Value)> {
+    let mut seen = BTreeSet::new();
+    let mut out = Vec::new();
+    let mut scope = Some(env.clone());
+    while let Some(e) = scope {
+        let parent = e.borrow().parent();
+        if parent.is_none() {
+            break;
+        }
+        for (name, v) in e.borrow().bindings() {
+            if seen.insert(name.to_string()) {
+                out.push((name.to_string(), v.clone()));
+            }
+        }
+        scope = parent;
+    }
+    out
+}
+
+// Bindings of the global scope reached from `env`, without the builtins
+pub fn globals(env: &EnvRef) -> Vec<(String, Value)> {
+    let mut root = env.clone();
+    loop {
+        let parent = root.borrow().parent();
+        match parent {
+            Some(p) => root = p,
+            None => break,
+        }
+    }
+    let root = root.borrow();
+    root.bindings()
+        .filter(|(_, v)| !matches!(v, Value::Function(f) if matches!(f.as_ref(), Function::Native { .. })))
+        .map(|(n, v)| (n.to_string(), v.clone()))
+        .collect()
+}
+
+// Lines where a statement starts, which is where breakpoints can stop
+pub fn breakable_lines(program: &Program) -> BTreeSet<usize> {
+    let mut lines = BTreeSet::new();
+    for s in &program.statements {
+        stmt_lines(s, &mut lines);
+    }
+    lines
+}
+
+// The line a breakpoint set on `line` stops at: the first breakable line from there
+pub fn resolve_line(lines: &BTreeSet<usize>, line: usize) -> Option<usize> {
+    lines.range(line..).next().copied()
+}
+
+fn stmt_lines(s: &Stmt, lines: &mut BTreeSet<usize>) {
+    if !matches!(s.kind, StmtKind::Block(..) | StmtKind::Test { .. }) {
+        lines.insert(s.span.line);
+    }
+    match &s.kind {
+        StmtKind::Let { init: e, .. } | StmtKind::Expr(e) | StmtKind::Return(Some(e)) => {
+            expr_lines(e, lines)
+        }
+        StmtKind::Block(body, _) | StmtKind::Test { body, .. } => {
+            body.iter().for_each(|s| stmt_lines(s, lines))
+        }
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            expr_lines(cond, lines);
+            stmt_lines(then_branch, lines);
+            if let Some(e) = else_branch {
+                stmt_lines(e, lines);
+            }
+        }
+        StmtKind::While { cond: e, body } | StmtKind::For { iter: e, body, .. } => {
+            expr_lines(e, lines);
+            stmt_lines(body, lines);
+        }
+        StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => {}
+    }
+}
+
+// Statements inside function literals
+fn expr_lines(e: &Expr, lines: &mut BTreeSet<usize>) {
+    match &e.kind {
+        ExprKind::Fn(f) => f.body.iter().for_each(|s| stmt_lines(s, lines)),
+        ExprKind::Call { callee, args } => {
+            expr_lines(callee, lines);
+            args.iter().for_each(|a| expr_lines(a, lines));
+        }
+        ExprKind::Assign { value: e, .. }
+        | ExprKind::Unary { expr: e, .. }
+        | ExprKind::Field { target: e, .. } => expr_lines(e, lines),
+        ExprKind::Binary { left, right, .. }
+        | ExprKind::Index {
+            target: left,
+            index: right,
+        } => {
+            expr_lines(left, lines);
+            expr_lines(right, lines);
+        }
+        ExprKind::List(items) => items.iter().for_each(|i| expr_lines(i, lines)),
+        ExprKind::Map(entries) => entries.iter().for_each(|(_, v)| expr_lines(v, lines)),
+        ExprKind::Literal(_) | ExprKind::Var(_, _) => {}
+    }
+}
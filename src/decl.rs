@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MIT
+// Declaration files (.qkd) describing the host ops and native functions an engine exposes.
+//
+//   /// Spawn an entity and return its id.
+//   host spawn(payload: record { kind: string, name: string }) -> record { id: number };
+//
+//   /// Straight-line distance between two points.
+//   fn distance(a: record { x: number, y: number }, b: record { x: number, y: number }) -> number;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+use walkdir::WalkDir;
+
+use crate::ast::{DeclKind, Param, Span, TypeExpr};
+use crate::parser::{ParseError, Parser};
+use crate::typecheck::Type;
+
+#[derive(Debug, Error)]
+pub enum DeclError {
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{path}: {source}")]
+    Parse { path: PathBuf, source: ParseError },
+}
+
+// A declared host op or native function, with the `///` comment written above it
+#[derive(Debug, Clone)]
+pub struct FnDecl {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret: TypeExpr,
+    pub doc: Option<String>,
+    pub path: Option<PathBuf>,
+    pub span: Span,
+}
+
+impl FnDecl {
+    pub fn param_types(&self) -> Vec<Type> {
+        self.params
+            .iter()
+            .map(|p| p.ty.as_ref().map(Type::from_expr).unwrap_or(Type::Any))
+            .collect()
+    }
+
+    pub fn ret_type(&self) -> Type {
+        Type::from_expr(&self.ret)
+    }
+
+    // Human-readable signature, e.g. `host spawn(payload: record{kind:string}) -> number`
+    pub fn signature(&self, kind: DeclKind) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|p| match &p.ty {
+                Some(t) => format!("{}: {}", p.name, Type::from_expr(t)),
+                None => p.name.clone(),
+            })
+            .collect();
+        let prefix = match kind {
+            DeclKind::HostOp => "host",
+            DeclKind::Native => "fn",
+        };
+        format!(
+            "{} {}({}) -> {}",
+            prefix,
+            self.name,
+            params.join(", "),
+            self.ret_type()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Declarations {
+    pub host_ops: BTreeMap<String, FnDecl>,
+    pub natives: BTreeMap<String, FnDecl>,
+}
+
+impl Declarations {
+    pub fn is_empty(&self) -> bool {
+        self.host_ops.is_empty() && self.natives.is_empty()
+    }
+
+    // Parse the contents of one declaration file; later declarations override earlier ones
+    pub fn add_source(&mut self, src: &str, path: Option<&Path>) -> Result<(), ParseError> {
+        let lines: Vec<&str> = src.lines().collect();
+        for d in Parser::new(src).parse_declarations()? {
+            let decl = FnDecl {
+                doc: doc_above(&lines, d.span.line),
+                name: d.name,
+                params: d.params,
+                ret: d.ret,
+                path: path.map(Path::to_path_buf),
+                span: d.span,
+            };
+            match d.kind {
+                DeclKind::HostOp => self.host_ops.insert(decl.name.clone(), decl),
+                DeclKind::Native => self.natives.insert(decl.name.clone(), decl),
+            };
+        }
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, path: &Path) -> Result<(), DeclError> {
+        let src = std::fs::read_to_string(path).map_err(|source| DeclError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.add_source(&src, Some(path))
+            .map_err(|source| DeclError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    // Load every .qkd file found under the given files/directories
+    pub fn load(paths: &[PathBuf]) -> Result<Declarations, DeclError> {
+        let mut decls = Declarations::default();
+        for p in find_decl_files(paths) {
+            decls.add_file(&p)?;
+        }
+        Ok(decls)
+    }
+}
+
+pub fn find_decl_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for p in paths {
+        for entry in WalkDir::new(p)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+        {
+            if entry.file_type().is_file()
+                && entry
+                    .path()
+                    .extension()
+                    .map(|e| e == "qkd")
+                    .unwrap_or(false)
+            {
+                files.push(entry.path().to_path_buf());
+            }
+        }
+    }
+    files
+}
+
+// Collect the consecutive `///` lines directly above a 1-based line
+fn doc_above(lines: &[&str], line: usize) -> Option<String> {
+    let mut doc: Vec<&str> = Vec::new();
+    let mut i = line.saturating_sub(1);
+    while i > 0 {
+        match lines.get(i - 1).and_then(|l| l.trim().strip_prefix("///")) {
+            Some(text) => doc.push(text.strip_prefix(' ').unwrap_or(text)),
+            None => break,
+        }
+        i -= 1;
+    }
+    if doc.is_empty() {
+        return None;
+    }
+    doc.reverse();
+    Some(doc.join("\n"))
+}
@@ -1,36 +1,121 @@
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::ast::{SlotNames, Span, TypeExpr};
 use crate::value::{EnvRef, Value};
 
+// One scope at runtime. Locals the resolver gave a slot live in `slots`, found by index;
+// everything else (globals, builtins, and locals of code that was not resolved) lives in
+// `values`, found by name. Slots keep their names, shared with the resolver's, so by-name
+// lookups see them too.
 #[derive(Default)]
 pub struct Env {
     parent: Option<EnvRef>,
+    // Value by slot; None until the slot's `let` runs
+    slots: Vec<Option<Value>>,
+    names: SlotNames,
     values: BTreeMap<String, Value>,
+    // Declared types of annotated bindings (checked mode only)
+    annotations: BTreeMap<String, (TypeExpr, Span)>,
 }
 
 impl Env {
     pub fn new() -> Self {
-        Self {
-            parent: None,
-            values: BTreeMap::new(),
-        }
+        Self::default()
     }
     pub fn with_parent(parent: EnvRef) -> Self {
         Self {
             parent: Some(parent),
-            values: BTreeMap::new(),
+            ..Self::default()
         }
     }
 
+    // A frame for a scope the resolver laid out, with room for all of its slots
+    pub fn frame(parent: &EnvRef, names: &SlotNames) -> EnvRef {
+        Rc::new(RefCell::new(Self {
+            parent: Some(parent.clone()),
+            slots: vec![None; names.len()],
+            names: names.clone(),
+            ..Self::default()
+        }))
+    }
+
     pub fn define(&mut self, name: String, value: Value) {
+        self.annotations.remove(&name);
+        self.values.insert(name, value);
+    }
+
+    pub fn define_annotated(&mut self, name: String, value: Value, ty: TypeExpr, span: Span) {
+        self.annotations.insert(name.clone(), (ty, span));
         self.values.insert(name, value);
     }
 
+    // Bind `name` in slot `index` of this scope
+    pub fn define_slot(&mut self, index: usize, name: &str, value: Value) {
+        if !self.annotations.is_empty() {
+            self.annotations.remove(name);
+        }
+        self.set_slot(index, name, value);
+    }
+
+    pub fn define_slot_annotated(
+        &mut self,
+        index: usize,
+        name: &str,
+        value: Value,
+        ty: TypeExpr,
+        span: Span,
+    ) {
+        self.annotations.insert(name.to_string(), (ty, span));
+        self.set_slot(index, name, value);
+    }
+
+    fn set_slot(&mut self, index: usize, name: &str, value: Value) {
+        // Only a frame the resolver did not lay out lacks the slot or its name
+        if index >= self.slots.len() {
+            self.slots.resize(index + 1, None);
+        }
+        if self.names.get(index).map(|n| &**n) != Some(name) {
+            let mut names = self.names.to_vec();
+            names.resize(names.len().max(index + 1), Arc::from(""));
+            names[index] = name.into();
+            self.names = names.into();
+        }
+        self.slots[index] = Some(value);
+    }
+
+    // The bound slot of this scope holding `name`; the last, if parameters repeat a name
+    fn slot_named(&self, name: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .zip(self.names.iter())
+            .rposition(|(v, n)| v.is_some() && &**n == name)
+    }
+
+    fn binds(&self, name: &str) -> bool {
+        self.slot_named(name).is_some() || self.values.contains_key(name)
+    }
+
+    // Annotation of the binding `name` resolves to, if it was declared with one
+    pub fn annotation(&self, name: &str) -> Option<(TypeExpr, Span)> {
+        if self.binds(name) {
+            return self.annotations.get(name).cloned();
+        }
+        if let Some(p) = &self.parent {
+            return p.borrow().annotation(name);
+        }
+        None
+    }
+
     pub fn assign(&mut self, name: &str, value: Value) -> Result<(), String> {
-        if self.values.contains_key(name) {
-            self.values.insert(name.to_string(), value);
+        if let Some(i) = self.slot_named(name) {
+            self.slots[i] = Some(value);
+            return Ok(());
+        }
+        if let Some(v) = self.values.get_mut(name) {
+            *v = value;
             return Ok(());
         }
         if let Some(p) = &self.parent {
@@ -40,6 +125,9 @@ impl Env {
     }
 
     pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(i) = self.slot_named(name) {
+            return self.slots[i].clone();
+        }
         if let Some(v) = self.values.get(name) {
             return Some(v.clone());
         }
@@ -49,6 +137,62 @@ impl Env {
         None
     }
 
+    // Slot `index` of the scope `depth` levels out
+    pub fn get_slot(&self, depth: usize, index: usize) -> Option<Value> {
+        if depth == 0 {
+            return self.slots.get(index).cloned().flatten();
+        }
+        self.parent.as_ref()?.borrow().get_slot(depth - 1, index)
+    }
+
+    pub fn assign_slot(&mut self, depth: usize, index: usize, value: Value) -> bool {
+        if depth > 0 {
+            return match &self.parent {
+                Some(p) => p.borrow_mut().assign_slot(depth - 1, index, value),
+                None => false,
+            };
+        }
+        match self.slots.get_mut(index) {
+            Some(v @ Some(_)) => {
+                *v = Some(value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // A name no slot can hold: only the by-name bindings are searched
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        if let Some(v) = self.values.get(name) {
+            return Some(v.clone());
+        }
+        self.parent.as_ref()?.borrow().get_global(name)
+    }
+
+    pub fn assign_global(&mut self, name: &str, value: Value) -> Result<(), String> {
+        if let Some(v) = self.values.get_mut(name) {
+            *v = value;
+            return Ok(());
+        }
+        match &self.parent {
+            Some(p) => p.borrow_mut().assign_global(name, value),
+            None => Err(format!("Undefined variable '{name}'")),
+        }
+    }
+
+    // Bindings of this scope alone, by name
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.names
+            .iter()
+            .zip(self.slots.iter())
+            .filter_map(|(n, v)| Some((&**n, v.as_ref()?)))
+            .chain(self.values.iter().map(|(n, v)| (n.as_str(), v)))
+    }
+
+    pub fn parent(&self) -> Option<EnvRef> {
+        self.parent.clone()
+    }
+
     pub fn new_global() -> EnvRef {
         Rc::new(RefCell::new(Self::new()))
     }
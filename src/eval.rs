@@ -1,10 +1,14 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::ast::*;
 use crate::env::Env;
+use crate::hooks::{Flow, Hooks};
 use crate::host::Host;
+use crate::output::{Level, Output, OutputRef, Sink, Stdout};
 use crate::stdlib::install_std;
-use crate::value::{EnvRef, Function, Value};
+use crate::typecheck::Type;
+use crate::value::{EnvRef, Function, NativeFn, Value};
 
 use thiserror::Error;
 
@@ -12,36 +16,109 @@ use thiserror::Error;
 pub enum RuntimeError {
     #[error("{0}")]
     Msg(String),
+    // A value did not match a type annotation (checked mode); span points at the annotation
+    #[error("{message} (annotation at line {}, col {})", .span.line, .span.col)]
+    Type { message: String, span: Span },
     #[error("break")]
     Break,
     #[error("continue")]
     Continue,
+    // A hook stopped the script
+    #[error("{0}")]
+    Aborted(String),
 }
 
 pub struct Interpreter {
     pub env: EnvRef,
     pub host: Host,
+    // Validate annotated lets, assignments, arguments and returns at runtime
+    pub checked: bool,
+    // Observes execution: statements, calls, events, host calls and errors (see hooks.rs)
+    pub hooks: Option<Box<dyn Hooks>>,
+    // Where `print` and `log` write
+    output: OutputRef,
 }
 
 impl Interpreter {
     pub fn with_host(host: Host) -> Self {
         let env = Env::new_global();
-        install_std(&env, host.clone());
-        Self { env, host }
+        let output: OutputRef = Rc::new(RefCell::new(Sink::new(Stdout)));
+        install_std(&env, host.clone(), output.clone());
+        Self {
+            env,
+            host,
+            checked: false,
+            hooks: None,
+            output,
+        }
+    }
+
+    // Send what `print` and `log` write to `output` instead of stdout and stderr
+    pub fn set_output(&mut self, output: impl Output + 'static) {
+        self.output.borrow_mut().set_output(output);
+    }
+
+    // Drop log records below `level` (Info unless set)
+    pub fn set_log_level(&mut self, level: Level) {
+        self.output.borrow_mut().level = level;
     }
 
     pub fn eval(&mut self, program: Program) -> Result<Option<Value>, RuntimeError> {
+        self.run(&program)
+    }
+
+    // Run a program without taking it: one parsed program can be run by any number of
+    // interpreters, as the parser already resolved it and closures share its function bodies
+    pub fn run(&mut self, program: &Program) -> Result<Option<Value>, RuntimeError> {
+        let result = self.eval_statements(program);
+        self.report_error(result)
+    }
+
+    // Call the global function `name`, as a host does to run a script's entry points
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let Some(f @ Value::Function(_)) = self.env.borrow().get(name) else {
+            let e = RuntimeError::Msg(format!("Undefined function '{name}'"));
+            return self.report_error(Err(e));
+        };
+        // Errors unwind without restoring the environment
+        let saved = self.env.clone();
+        let result = self.call_named(name, Span::default(), f, args);
+        self.env = saved;
+        self.report_error(result)
+    }
+
+    // Let the hooks see the error an entry point (`run` or `call`) stops with
+    fn report_error<T>(&mut self, result: Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+        if let Err(e) = &result {
+            if let Some(mut h) = self.hooks.take() {
+                h.error(self, e);
+                self.hooks = Some(h);
+            }
+        }
+        result
+    }
+
+    // Evaluate an expression with `env` as the current environment
+    pub fn eval_in(&mut self, env: &EnvRef, expr: &Expr) -> Result<Value, RuntimeError> {
+        let saved = std::mem::replace(&mut self.env, env.clone());
+        let result = self.eval_expr(expr);
+        self.env = saved;
+        result
+    }
+
+    fn eval_statements(&mut self, program: &Program) -> Result<Option<Value>, RuntimeError> {
         let mut last: Option<Value> = None;
-        for s in program.statements {
-            match s {
-                Stmt::Expr(ref e) => {
+        for s in &program.statements {
+            match &s.kind {
+                StmtKind::Expr(e) => {
                     // Evaluate expression statements but do not affect control flow
+                    self.hook_statement(s)?;
                     let v = self.eval_expr(e)?;
                     last = Some(v);
                 }
                 _ => {
                     // Other statements; only Return bubbles up as Some
-                    if let Some(v) = self.exec_stmt(&s)? {
+                    if let Some(v) = self.exec_stmt(s)? {
                         last = Some(v);
                     }
                 }
@@ -50,8 +127,17 @@ impl Interpreter {
         Ok(last)
     }
 
-    fn exec_block(&mut self, body: &[Stmt]) -> Result<Option<Value>, RuntimeError> {
-        let child = crate::env::Env::child_of(&self.env);
+    fn exec_block(&mut self, body: &[Stmt], frame: &Frame) -> Result<Option<Value>, RuntimeError> {
+        // The resolver found the block declares nothing: it runs in the enclosing scope
+        let Frame::Own(slots) = frame else {
+            for s in body {
+                if let Some(v) = self.exec_stmt(s)? {
+                    return Ok(Some(v));
+                }
+            }
+            return Ok(None);
+        };
+        let child = crate::env::Env::frame(&self.env, slots);
         let saved = self.env.clone();
         self.env = child.clone();
         let mut result = None;
@@ -65,24 +151,163 @@ impl Interpreter {
         Ok(result)
     }
 
+    // Run a hook if any are installed; they are taken out meanwhile so a hook that evaluates
+    // code does not see its own evaluation
+    fn hook(
+        &mut self,
+        f: impl FnOnce(&mut dyn Hooks, &mut Self) -> Flow,
+    ) -> Result<(), RuntimeError> {
+        let Some(mut hooks) = self.hooks.take() else {
+            return Ok(());
+        };
+        let flow = f(hooks.as_mut(), self);
+        self.hooks = Some(hooks);
+        match flow {
+            Flow::Continue => Ok(()),
+            Flow::Abort(message) => Err(RuntimeError::Aborted(message)),
+        }
+    }
+
+    // Let the hooks see a statement before it runs (blocks only group statements)
+    fn hook_statement(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        if self.hooks.is_none() || matches!(stmt.kind, StmtKind::Block(..) | StmtKind::Test { .. })
+        {
+            return Ok(());
+        }
+        self.hook(|h, i| h.statement(i, stmt.span))
+    }
+
+    // Evaluate the condition of the `if` or `while` at `span`
+    fn condition(&mut self, span: Span, cond: &Expr) -> Result<bool, RuntimeError> {
+        let taken = self.eval_expr(cond)?.truthy();
+        self.hook_branch(span, taken)?;
+        Ok(taken)
+    }
+
+    // A list or map was cloned out of a variable or collection; counted for the profiler
+    // only while hooks are installed, so it costs an Option check otherwise
+    fn count_clone(&self, v: &Value) {
+        if self.hooks.is_some() && matches!(v, Value::List(_) | Value::Map(_)) {
+            crate::value::count_collection_clone();
+        }
+    }
+
+    fn hook_branch(&mut self, span: Span, taken: bool) -> Result<(), RuntimeError> {
+        if self.hooks.is_none() {
+            return Ok(());
+        }
+        self.hook(|h, i| h.branch(i, span, taken))
+    }
+
+    // Call a function from the call at `call`, reporting script functions to the hooks
+    fn call_named(
+        &mut self,
+        name: &str,
+        call: Span,
+        callee: Value,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let user =
+            matches!(&callee, Value::Function(f) if matches!(f.as_ref(), Function::User { .. }));
+        if self.hooks.is_none() || !user {
+            return self.call_function(callee, args);
+        }
+        self.hook(|h, i| h.enter(i, name, call, &args))?;
+        let v = self.call_function(callee, args)?;
+        self.hook(|h, i| h.exit(i, name, &v))?;
+        Ok(v)
+    }
+
+    // host(op, payload): answered by a script function if the host names one for the op,
+    // else by the host itself
+    fn host_op(&mut self, fun: &NativeFn, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let Some(Value::String(op)) = args.first().cloned() else {
+            return fun(args, self.env.clone()).map_err(RuntimeError::Msg);
+        };
+        let payload = args.get(1).cloned().unwrap_or(Value::Null);
+        let result = match self.host.script(&op, &payload) {
+            Some(f) => {
+                // Errors unwind without restoring the environment
+                let saved = self.env.clone();
+                let result = self.call_function(Value::Function(f), vec![payload.clone()]);
+                self.env = saved;
+                match result {
+                    Err(e @ RuntimeError::Aborted(_)) => return Err(e),
+                    r => r.map_err(|e| e.to_string()),
+                }
+            }
+            None => fun(args, self.env.clone()),
+        };
+        self.hook(|h, i| h.host_call(i, &op, &payload, &result))?;
+        result.map_err(RuntimeError::Msg)
+    }
+
+    // emit(event, payload): native handlers run in the host, script handlers here
+    fn emit(&mut self, fun: &NativeFn, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let Some(Value::String(event)) = args.first().cloned() else {
+            return fun(args, self.env.clone()).map_err(RuntimeError::Msg);
+        };
+        let payload = args.get(1).cloned().unwrap_or(Value::Null);
+        self.hook(|h, i| h.emit(i, &event, &payload))?;
+        let v = fun(args, self.env.clone()).map_err(RuntimeError::Msg)?;
+        let handlers: Vec<Rc<Function>> = match self.host.events.borrow().get(&event) {
+            Some(list) => list
+                .iter()
+                .filter(|f| matches!(f.as_ref(), Function::User { .. }))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        let name = format!("on({:?})", event);
+        for f in handlers {
+            self.hook(|h, i| h.dispatch(i, &event, &f))?;
+            let span = match f.as_ref() {
+                Function::User { span, .. } => *span,
+                Function::Native { .. } => Span::default(),
+            };
+            self.call_named(&name, span, Value::Function(f), vec![payload.clone()])?;
+        }
+        Ok(v)
+    }
+
     fn exec_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
-        match stmt {
-            Stmt::Let { name, ty: _, init } => {
+        self.hook_statement(stmt)?;
+        match &stmt.kind {
+            StmtKind::Let {
+                name,
+                ty,
+                ty_span,
+                init,
+                slot,
+                ..
+            } => {
                 let v = self.eval_expr(init)?;
-                self.env.borrow_mut().define(name.clone(), v);
+                let mut env = self.env.borrow_mut();
+                match (ty, slot) {
+                    (Some(t), Slot::Local { index, .. }) if self.checked => {
+                        check_value(&v, t, *ty_span, || format!("variable '{name}'"))?;
+                        env.define_slot_annotated(*index, name, v, t.clone(), *ty_span);
+                    }
+                    (Some(t), _) if self.checked => {
+                        check_value(&v, t, *ty_span, || format!("variable '{name}'"))?;
+                        env.define_annotated(name.clone(), v, t.clone(), *ty_span);
+                    }
+                    (_, Slot::Local { index, .. }) => env.define_slot(*index, name, v),
+                    _ => env.define(name.clone(), v),
+                }
                 Ok(None)
             }
-            Stmt::Expr(e) => {
+            StmtKind::Expr(e) => {
                 let _ = self.eval_expr(e)?;
                 Ok(None)
             }
-            Stmt::Block(b) => self.exec_block(b),
-            Stmt::If {
+            StmtKind::Block(b, frame) => self.exec_block(b, frame),
+            StmtKind::If {
                 cond,
                 then_branch,
                 else_branch,
             } => {
-                if self.eval_expr(cond)?.truthy() {
+                if self.condition(stmt.span, cond)? {
                     self.exec_stmt(then_branch)
                 } else if let Some(e) = else_branch {
                     self.exec_stmt(e)
@@ -90,8 +315,8 @@ impl Interpreter {
                     Ok(None)
                 }
             }
-            Stmt::While { cond, body } => {
-                while self.eval_expr(cond)?.truthy() {
+            StmtKind::While { cond, body } => {
+                while self.condition(stmt.span, cond)? {
                     match self.exec_stmt(body) {
                         Ok(Some(v)) => return Ok(Some(v)), // return from inside loop
                         Ok(None) => {}
@@ -102,19 +327,28 @@ impl Interpreter {
                 }
                 Ok(None)
             }
-            Stmt::For { name, iter, body } => {
+            StmtKind::For {
+                name,
+                iter,
+                body,
+                slots,
+                ..
+            } => {
                 let it = self.eval_expr(iter)?;
                 match it {
                     Value::List(list) => {
+                        let mut ran_out = true;
                         for item in list {
-                            let child = crate::env::Env::child_of(&self.env);
-                            child.borrow_mut().define(name.clone(), item);
+                            self.hook_branch(stmt.span, true)?;
+                            let child = crate::env::Env::frame(&self.env, slots);
+                            child.borrow_mut().define_slot(0, name, item);
                             let saved = self.env.clone();
                             self.env = child;
                             let r = match self.exec_stmt(body) {
                                 Ok(v) => Ok(v),
                                 Err(RuntimeError::Break) => {
                                     self.env = saved;
+                                    ran_out = false;
                                     break;
                                 }
                                 Err(RuntimeError::Continue) => {
@@ -131,41 +365,69 @@ impl Interpreter {
                                 return Ok(r);
                             }
                         }
+                        if ran_out {
+                            self.hook_branch(stmt.span, false)?;
+                        }
                         Ok(None)
                     }
                     _ => Err(RuntimeError::Msg("for expects list".into())),
                 }
             }
-            Stmt::Return(v) => {
+            StmtKind::Return(v) => {
                 let val = match v {
                     Some(e) => self.eval_expr(e)?,
                     None => Value::Null,
                 };
                 Ok(Some(val))
             }
-            Stmt::Break => Err(RuntimeError::Break),
-            Stmt::Continue => Err(RuntimeError::Continue),
+            StmtKind::Break => Err(RuntimeError::Break),
+            StmtKind::Continue => Err(RuntimeError::Continue),
+            // Tests run only under `qk test` (see testing.rs)
+            StmtKind::Test { .. } => Ok(None),
         }
     }
 
     fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
-        use Expr::*;
-        Ok(match expr {
+        use ExprKind::*;
+        Ok(match &expr.kind {
             Literal(Lit::Number(n)) => Value::Number(*n),
             Literal(Lit::Bool(b)) => Value::Bool(*b),
             Literal(Lit::String(s)) => Value::String(s.clone()),
             Literal(Lit::Null) => Value::Null,
-            Var(name) => self
-                .env
-                .borrow()
-                .get(name)
-                .ok_or_else(|| RuntimeError::Msg(format!("Undefined variable '{name}'")))?,
-            Assign { name, value } => {
+            Var(name, slot) => {
+                let env = self.env.borrow();
+                let v = match slot {
+                    Slot::Local { depth, index } => env.get_slot(*depth, *index),
+                    Slot::Global => env.get_global(name),
+                    Slot::Dynamic => env.get(name),
+                };
+                let v =
+                    v.ok_or_else(|| RuntimeError::Msg(format!("Undefined variable '{name}'")))?;
+                self.count_clone(&v);
+                v
+            }
+            Assign { name, slot, value } => {
                 let v = self.eval_expr(value)?;
-                self.env
-                    .borrow_mut()
-                    .assign(name, v.clone())
-                    .map_err(RuntimeError::Msg)?;
+                if self.checked {
+                    let ann = self.env.borrow().annotation(name);
+                    if let Some((t, span)) = ann {
+                        check_value(&v, &t, span, || format!("variable '{name}'"))?;
+                    }
+                }
+                let mut env = self.env.borrow_mut();
+                match slot {
+                    Slot::Local { depth, index } => {
+                        if !env.assign_slot(*depth, *index, v.clone()) {
+                            return Err(RuntimeError::Msg(format!("Undefined variable '{name}'")));
+                        }
+                    }
+                    Slot::Global => env
+                        .assign_global(name, v.clone())
+                        .map_err(RuntimeError::Msg)?,
+                    Slot::Dynamic => env.assign(name, v.clone()).map_err(RuntimeError::Msg)?,
+                }
+                drop(env);
+                self.count_clone(&v);
                 v
             }
             Unary { op, expr } => {
@@ -203,23 +465,26 @@ impl Interpreter {
                 for x in args {
                     a.push(self.eval_expr(x)?);
                 }
-                self.call_function(c, a)?
+                let name = match &callee.kind {
+                    Var(name, _) => name.as_str(),
+                    _ => "<anonymous>",
+                };
+                self.call_named(name, expr.span, c, a)?
             }
-            Fn { params, ret, body } => Value::Function(Rc::new(Function::User {
-                params: params.clone(),
-                ret: ret.clone(),
-                body: body.clone(),
+            Fn(literal) => Value::Function(Rc::new(Function::User {
+                literal: literal.clone(),
                 env: self.env.clone(),
+                span: expr.span,
             })),
             List(items) => {
-                let mut v = Vec::new();
+                let mut v = crate::value::List::new();
                 for e in items {
-                    v.push(self.eval_expr(e)?);
+                    v.push_back(self.eval_expr(e)?);
                 }
                 Value::List(v)
             }
             Map(props) => {
-                let mut m = std::collections::BTreeMap::new();
+                let mut m = crate::value::Map::new();
                 for (k, e) in props {
                     m.insert(k.clone(), self.eval_expr(e)?);
                 }
@@ -228,50 +493,76 @@ impl Interpreter {
             Index { target, index } => {
                 let t = self.eval_expr(target)?;
                 let i = self.eval_expr(index)?;
-                match (t, i) {
+                let v = match (t, i) {
                     (Value::List(v), Value::Number(n)) => {
                         v.get(n as usize).cloned().unwrap_or(Value::Null)
                     }
                     (Value::Map(m), Value::String(s)) => m.get(&s).cloned().unwrap_or(Value::Null),
                     _ => Value::Null,
-                }
+                };
+                self.count_clone(&v);
+                v
             }
             Field { target, name } => {
                 let t = self.eval_expr(target)?;
-                match t {
+                let v = match t {
                     Value::Map(m) => m.get(name).cloned().unwrap_or(Value::Null),
                     _ => Value::Null,
-                }
+                };
+                self.count_clone(&v);
+                v
             }
         })
     }
 
-    fn call_function(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    pub(crate) fn call_function(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
         match callee {
             Value::Function(f) => match f.as_ref() {
-                Function::Native { fun, .. } => {
-                    fun(args, self.env.clone()).map_err(RuntimeError::Msg)
-                }
-                Function::User {
-                    params,
-                    ret: _,
-                    body,
-                    env,
-                } => {
-                    let child = crate::env::Env::child_of(env);
+                Function::Native { name, fun } => match name.as_str() {
+                    "emit" => self.emit(fun, args),
+                    "assert_throws" => crate::testing::assert_throws(self, args),
+                    "host" => self.host_op(fun, args),
+                    _ => fun(args, self.env.clone()).map_err(RuntimeError::Msg),
+                },
+                Function::User { literal, env, .. } => {
+                    let FnLiteral {
+                        params,
+                        ret: ret_ty,
+                        ret_span,
+                        body,
+                        slots,
+                    } = &**literal;
+                    let child = crate::env::Env::frame(env, slots);
                     for (i, p) in params.iter().enumerate() {
-                        child
-                            .borrow_mut()
-                            .define(p.0.clone(), args.get(i).cloned().unwrap_or(Value::Null));
+                        let arg = args.get(i).cloned().unwrap_or(Value::Null);
+                        match &p.ty {
+                            Some(t) if self.checked => {
+                                let what = || format!("parameter '{}'", p.name);
+                                check_value(&arg, t, p.ty_span, what)?;
+                                child.borrow_mut().define_slot_annotated(
+                                    i,
+                                    &p.name,
+                                    arg,
+                                    t.clone(),
+                                    p.ty_span,
+                                );
+                            }
+                            _ => child.borrow_mut().define_slot(i, &p.name, arg),
+                        }
                     }
                     let saved = self.env.clone();
                     self.env = child.clone();
                     let mut ret = Value::Null;
                     let mut last_expr_value: Option<Value> = None;
                     for s in body {
-                        match s {
-                            Stmt::Expr(e) => {
+                        match &s.kind {
+                            StmtKind::Expr(e) => {
                                 // Capture value of expression statements for implicit return
+                                self.hook_statement(s)?;
                                 let v = self.eval_expr(e)?;
                                 last_expr_value = Some(v);
                             }
@@ -290,6 +581,9 @@ impl Interpreter {
                         }
                     }
                     self.env = saved;
+                    if let (Some(t), true) = (ret_ty, self.checked) {
+                        check_value(&ret, t, *ret_span, || "return value".to_string())?;
+                    }
                     Ok(ret)
                 }
             },
@@ -298,6 +592,28 @@ impl Interpreter {
     }
 }
 
+// Checked mode: fail with a type error pointing at the annotation when `v` does not fit `t`
+fn check_value(
+    v: &Value,
+    t: &TypeExpr,
+    span: Span,
+    what: impl FnOnce() -> String,
+) -> Result<(), RuntimeError> {
+    if v.conforms_to(t) {
+        return Ok(());
+    }
+    Err(RuntimeError::Type {
+        message: format!(
+            "{} is annotated as {} but got {} {}",
+            what(),
+            Type::from_expr(t),
+            v.type_name(),
+            v
+        ),
+        span,
+    })
+}
+
 fn add(l: Value, r: Value) -> Result<Value, RuntimeError> {
     match (l, r) {
         (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
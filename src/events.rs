@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: MIT
+// Static event graph: scripts talk through `on("name", handler)` and `emit("name", payload)`,
+// so every call with a literal event name is a site of that event. Emit sites carry the
+// checker's type of the payload and handler sites the type of the handler's first
+// parameter. Calls of named functions are collected too, for call hierarchies; code inside
+// a function literal passed to `on` counts as being in a function named `on("event")`.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::ast::*;
+use crate::typecheck::{Type, TypeCheckResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SiteKind {
+    Handler,
+    Emitter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventSite {
+    pub event: String,
+    pub kind: SiteKind,
+    // The whole `on(...)`/`emit(...)` call, and the event name literal
+    pub span: Span,
+    pub name_span: Span,
+    // Unknown when the script was not type-checked or the handler is not a function
+    pub payload: Option<Type>,
+    // Innermost function the call is in; None at the top level of the script
+    pub function: Option<String>,
+    // For handlers: the function run for the event, by name or as `on("event")`
+    pub handler: Option<String>,
+}
+
+// A call of a function by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    pub caller: Option<String>,
+    pub callee: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileEvents {
+    pub sites: Vec<EventSite>,
+    pub calls: Vec<CallSite>,
+}
+
+impl FileEvents {
+    // The site whose event name contains the 1-based position
+    pub fn site_at(&self, line: usize, col: usize) -> Option<&EventSite> {
+        self.sites.iter().find(|s| contains(s.name_span, line, col))
+    }
+}
+
+fn contains(span: Span, line: usize, col: usize) -> bool {
+    (span.line, span.col) <= (line, col) && (line, col) < (span.end_line, span.end_col)
+}
+
+pub fn analyze(program: &Program, types: Option<&TypeCheckResult>) -> FileEvents {
+    let mut cx = Collector {
+        inferred: types
+            .map(|tc| tc.inferred.iter().cloned().collect())
+            .unwrap_or_default(),
+        vars: types.map(|tc| &tc.env.vars),
+        typed: types.is_some(),
+        functions: Vec::new(),
+        out: FileEvents::default(),
+    };
+    for s in &program.statements {
+        cx.stmt(s);
+    }
+    cx.out
+}
+
+struct Collector<'a> {
+    inferred: HashMap<Span, Type>,
+    vars: Option<&'a BTreeMap<String, Type>>,
+    typed: bool,
+    functions: Vec<String>,
+    out: FileEvents,
+}
+
+impl Collector<'_> {
+    fn stmt(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Let { name, init, .. } if matches!(init.kind, ExprKind::Fn(_)) => {
+                self.functions.push(name.clone());
+                self.expr(init);
+                self.functions.pop();
+            }
+            StmtKind::Let { init: e, .. } | StmtKind::Expr(e) | StmtKind::Return(Some(e)) => {
+                self.expr(e)
+            }
+            StmtKind::Block(body, _) | StmtKind::Test { body, .. } => {
+                body.iter().for_each(|s| self.stmt(s))
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            StmtKind::For { iter, body, .. } => {
+                self.expr(iter);
+                self.stmt(body);
+            }
+            StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Call { callee, args } => {
+                let literal = match &callee.kind {
+                    ExprKind::Var(f, _) => self.call(f, callee.span, e.span, args),
+                    _ => None,
+                };
+                self.expr(callee);
+                for (i, a) in args.iter().enumerate() {
+                    match &literal {
+                        Some(name) if i == 1 => {
+                            self.functions.push(name.clone());
+                            self.expr(a);
+                            self.functions.pop();
+                        }
+                        _ => self.expr(a),
+                    }
+                }
+            }
+            ExprKind::Assign { value, .. } => self.expr(value),
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { expr, .. } => self.expr(expr),
+            ExprKind::Fn(f) => f.body.iter().for_each(|s| self.stmt(s)),
+            ExprKind::List(items) => items.iter().for_each(|i| self.expr(i)),
+            ExprKind::Map(entries) => entries.iter().for_each(|(_, v)| self.expr(v)),
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Field { target, .. } => self.expr(target),
+            ExprKind::Literal(_) | ExprKind::Var(_, _) => {}
+        }
+    }
+
+    // Records the call; returns the name given to a handler function literal
+    fn call(&mut self, f: &str, callee_span: Span, span: Span, args: &[Expr]) -> Option<String> {
+        let function = self.functions.last().cloned();
+        let kind = match f {
+            "on" => SiteKind::Handler,
+            "emit" => SiteKind::Emitter,
+            _ => {
+                self.out.calls.push(CallSite {
+                    caller: function,
+                    callee: f.to_string(),
+                    span: callee_span,
+                });
+                return None;
+            }
+        };
+        let Some(Expr {
+            kind: ExprKind::Literal(Lit::String(event)),
+            span: name_span,
+        }) = args.first()
+        else {
+            return None;
+        };
+        let payload = match (kind, args.get(1)) {
+            _ if !self.typed => None,
+            (SiteKind::Emitter, None) => Some(Type::Null),
+            (SiteKind::Emitter, Some(data)) => self.inferred.get(&data.span).cloned(),
+            (SiteKind::Handler, Some(handler)) => self.first_param(handler),
+            (SiteKind::Handler, None) => None,
+        };
+        let (handler, literal) = match (kind, args.get(1).map(|a| &a.kind)) {
+            (SiteKind::Handler, Some(ExprKind::Var(name, _))) => (Some(name.clone()), None),
+            (SiteKind::Handler, Some(ExprKind::Fn(_))) => {
+                let name = format!("on({:?})", event);
+                (Some(name.clone()), Some(name))
+            }
+            _ => (None, None),
+        };
+        self.out.sites.push(EventSite {
+            event: event.clone(),
+            kind,
+            span,
+            name_span: *name_span,
+            payload,
+            function,
+            handler,
+        });
+        literal
+    }
+
+    // Type of the first parameter of a handler given as a literal or by name
+    fn first_param(&self, handler: &Expr) -> Option<Type> {
+        let t = match &handler.kind {
+            ExprKind::Fn(_) => self.inferred.get(&handler.span),
+            ExprKind::Var(name, _) => self.vars.and_then(|v| v.get(name)),
+            _ => None,
+        };
+        match t {
+            Some(Type::Func(params, _)) => params.first().cloned(),
+            _ => None,
+        }
+    }
+}
+
+// Where an event is emitted or handled
+#[derive(Debug, Clone, Serialize)]
+pub struct Endpoint {
+    pub file: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    pub function: Option<String>,
+    pub payload: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventNode {
+    pub emitters: Vec<Endpoint>,
+    pub handlers: Vec<Endpoint>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventGraph {
+    pub events: BTreeMap<String, EventNode>,
+}
+
+impl EventGraph {
+    pub fn build<'a>(files: impl IntoIterator<Item = (&'a Path, &'a FileEvents)>) -> Self {
+        let mut graph = EventGraph::default();
+        for (path, file) in files {
+            for site in &file.sites {
+                let node = graph.events.entry(site.event.clone()).or_default();
+                let endpoint = Endpoint {
+                    file: path.to_path_buf(),
+                    line: site.span.line,
+                    col: site.span.col,
+                    function: site.function.clone(),
+                    payload: site.payload.as_ref().map(|t| t.to_string()),
+                };
+                match site.kind {
+                    SiteKind::Emitter => node.emitters.push(endpoint),
+                    SiteKind::Handler => node.handlers.push(endpoint),
+                }
+            }
+        }
+        graph
+    }
+
+    // Graphviz rendering: scripts are boxes and events ellipses, with an edge from each
+    // emitting script (labelled with the payload types) and to each handling script.
+    // Events missing either side are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph events {\n  rankdir=LR;\n  node [shape=box];\n");
+        let scripts: BTreeSet<&Path> = self
+            .events
+            .values()
+            .flat_map(|n| n.emitters.iter().chain(&n.handlers))
+            .map(|e| e.file.as_path())
+            .collect();
+        for script in &scripts {
+            let _ = writeln!(out, "  {};", quote(&script.display().to_string()));
+        }
+        for (event, node) in &self.events {
+            let id = quote(&format!("event:{}", event));
+            let style = if node.emitters.is_empty() || node.handlers.is_empty() {
+                ", style=dashed"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                out,
+                "  {} [label={}, shape=ellipse{}];",
+                id,
+                quote(event),
+                style
+            );
+            let mut emitted: BTreeMap<&Path, BTreeSet<&str>> = BTreeMap::new();
+            for e in &node.emitters {
+                let types = emitted.entry(e.file.as_path()).or_default();
+                types.extend(e.payload.as_deref());
+            }
+            for (script, types) in emitted {
+                let label = types.into_iter().collect::<Vec<_>>().join(" | ");
+                let _ = writeln!(
+                    out,
+                    "  {} -> {} [label={}];",
+                    quote(&script.display().to_string()),
+                    id,
+                    quote(&label)
+                );
+            }
+            let handlers: BTreeSet<&Path> =
+                node.handlers.iter().map(|h| h.file.as_path()).collect();
+            for script in handlers {
+                let _ = writeln!(out, "  {} -> {};", id, quote(&script.display().to_string()));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
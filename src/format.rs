@@ -18,8 +18,8 @@ fn indent(n: usize, out: &mut String) {
 }
 
 fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
-    match s {
-        Stmt::Let { name, ty, init } => {
+    match &s.kind {
+        StmtKind::Let { name, ty, init, .. } => {
             indent(ind, out);
             out.push_str("let ");
             out.push_str(name);
@@ -31,12 +31,12 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
             fmt_expr(init, out);
             out.push(';');
         }
-        Stmt::Expr(e) => {
+        StmtKind::Expr(e) => {
             indent(ind, out);
             fmt_expr(e, out);
             out.push(';');
         }
-        Stmt::Block(b) => {
+        StmtKind::Block(b, _) => {
             indent(ind, out);
             out.push_str("{\n");
             for st in b {
@@ -46,7 +46,7 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
             indent(ind, out);
             out.push('}');
         }
-        Stmt::If {
+        StmtKind::If {
             cond,
             then_branch,
             else_branch,
@@ -55,11 +55,11 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
             out.push_str("if (");
             fmt_expr(cond, out);
             out.push_str(") ");
-            match &**then_branch {
-                Stmt::Block(_) => fmt_stmt(then_branch, ind, out),
-                other => {
+            match &then_branch.kind {
+                StmtKind::Block(..) => fmt_stmt(then_branch, ind, out),
+                _ => {
                     out.push_str("{\n");
-                    fmt_stmt(other, ind + 1, out);
+                    fmt_stmt(then_branch, ind + 1, out);
                     out.push('\n');
                     indent(ind, out);
                     out.push('}');
@@ -67,11 +67,11 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
             }
             if let Some(e) = else_branch {
                 out.push_str(" else ");
-                match &**e {
-                    Stmt::Block(_) => fmt_stmt(e, ind, out),
-                    other => {
+                match &e.kind {
+                    StmtKind::Block(..) => fmt_stmt(e, ind, out),
+                    _ => {
                         out.push_str("{\n");
-                        fmt_stmt(other, ind + 1, out);
+                        fmt_stmt(e, ind + 1, out);
                         out.push('\n');
                         indent(ind, out);
                         out.push('}');
@@ -79,14 +79,16 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
                 }
             }
         }
-        Stmt::While { cond, body } => {
+        StmtKind::While { cond, body } => {
             indent(ind, out);
             out.push_str("while (");
             fmt_expr(cond, out);
             out.push_str(") ");
             fmt_stmt(body, ind, out);
         }
-        Stmt::For { name, iter, body } => {
+        StmtKind::For {
+            name, iter, body, ..
+        } => {
             indent(ind, out);
             out.push_str("for (");
             out.push_str(name);
@@ -95,7 +97,7 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
             out.push_str(") ");
             fmt_stmt(body, ind, out);
         }
-        Stmt::Return(v) => {
+        StmtKind::Return(v) => {
             indent(ind, out);
             out.push_str("return");
             if let Some(e) = v {
@@ -104,34 +106,48 @@ fn fmt_stmt(s: &Stmt, ind: usize, out: &mut String) {
             }
             out.push(';');
         }
-        Stmt::Break => {
+        StmtKind::Break => {
             indent(ind, out);
             out.push_str("break;");
         }
-        Stmt::Continue => {
+        StmtKind::Continue => {
             indent(ind, out);
             out.push_str("continue;");
         }
+        StmtKind::Test { name, body } => {
+            indent(ind, out);
+            out.push_str("test \"");
+            out.push_str(name);
+            out.push_str("\" {\n");
+            for st in body {
+                fmt_stmt(st, ind + 1, out);
+                out.push('\n');
+            }
+            indent(ind, out);
+            out.push('}');
+        }
     }
 }
 
 fn fmt_expr(e: &Expr, out: &mut String) {
-    match e {
-        Expr::Literal(Lit::Number(n)) => out.push_str(&crate::value::Value::Number(*n).to_string()),
-        Expr::Literal(Lit::Bool(b)) => out.push_str(&b.to_string()),
-        Expr::Literal(Lit::String(s)) => {
+    match &e.kind {
+        ExprKind::Literal(Lit::Number(n)) => {
+            out.push_str(&crate::value::Value::Number(*n).to_string())
+        }
+        ExprKind::Literal(Lit::Bool(b)) => out.push_str(&b.to_string()),
+        ExprKind::Literal(Lit::String(s)) => {
             out.push('"');
             out.push_str(s);
             out.push('"');
         }
-        Expr::Literal(Lit::Null) => out.push_str("null"),
-        Expr::Var(n) => out.push_str(n),
-        Expr::Assign { name, value } => {
+        ExprKind::Literal(Lit::Null) => out.push_str("null"),
+        ExprKind::Var(n, _) => out.push_str(n),
+        ExprKind::Assign { name, value, .. } => {
             out.push_str(name);
             out.push_str(" = ");
             fmt_expr(value, out);
         }
-        Expr::Binary { left, op, right } => {
+        ExprKind::Binary { left, op, right } => {
             fmt_expr(left, out);
             out.push(' ');
             out.push_str(match op {
@@ -152,14 +168,14 @@ fn fmt_expr(e: &Expr, out: &mut String) {
             out.push(' ');
             fmt_expr(right, out);
         }
-        Expr::Unary { op, expr } => {
+        ExprKind::Unary { op, expr } => {
             out.push_str(match op {
                 UnOp::Neg => "-",
                 UnOp::Not => "!",
             });
             fmt_expr(expr, out);
         }
-        Expr::Call { callee, args } => {
+        ExprKind::Call { callee, args } => {
             fmt_expr(callee, out);
             out.push('(');
             for (i, a) in args.iter().enumerate() {
@@ -170,14 +186,17 @@ fn fmt_expr(e: &Expr, out: &mut String) {
             }
             out.push(')');
         }
-        Expr::Fn { params, ret, body } => {
+        ExprKind::Fn(f) => {
+            let FnLiteral {
+                params, ret, body, ..
+            } = &**f;
             out.push_str("fn (");
-            for (i, (n, t)) in params.iter().enumerate() {
+            for (i, p) in params.iter().enumerate() {
                 if i > 0 {
                     out.push_str(", ");
                 }
-                out.push_str(n);
-                if let Some(tt) = t {
+                out.push_str(&p.name);
+                if let Some(tt) = &p.ty {
                     out.push_str(": ");
                     fmt_type(tt, out);
                 }
@@ -194,7 +213,7 @@ fn fmt_expr(e: &Expr, out: &mut String) {
             }
             out.push('}');
         }
-        Expr::List(items) => {
+        ExprKind::List(items) => {
             out.push('[');
             for (i, it) in items.iter().enumerate() {
                 if i > 0 {
@@ -204,7 +223,7 @@ fn fmt_expr(e: &Expr, out: &mut String) {
             }
             out.push(']');
         }
-        Expr::Map(props) => {
+        ExprKind::Map(props) => {
             out.push('{');
             for (i, (k, v)) in props.iter().enumerate() {
                 if i > 0 {
@@ -216,13 +235,13 @@ fn fmt_expr(e: &Expr, out: &mut String) {
             }
             out.push('}');
         }
-        Expr::Index { target, index } => {
+        ExprKind::Index { target, index } => {
             fmt_expr(target, out);
             out.push('[');
             fmt_expr(index, out);
             out.push(']');
         }
-        Expr::Field { target, name } => {
+        ExprKind::Field { target, name } => {
             fmt_expr(target, out);
             out.push('.');
             out.push_str(name);
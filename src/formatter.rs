@@ -741,10 +741,10 @@ fn render(toks: Vec<Tok>, opts: &FormatterOptions) -> String {
                     | Some(TKind::Number(_))
                     | Some(TKind::Str(_))
                     | Some(TKind::Keyword(_))
-                    | Some(TKind::LBrace) => {
-                        if !out.ends_with(' ') && !out.ends_with('\n') {
-                            out.push(' ');
-                        }
+                    | Some(TKind::LBrace)
+                        if !out.ends_with(' ') && !out.ends_with('\n') =>
+                    {
+                        out.push(' ');
                     }
                     _ => {}
                 }
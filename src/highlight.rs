@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: MIT
+// Semantic highlighting: classifies names using the resolver (what a name is bound to)
+// and the token stream (record fields and type names), for the language server.
+use std::collections::HashSet;
+
+use crate::ast::*;
+use crate::decl::Declarations;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::stdlib::BUILTINS;
+use crate::symbols::{self, RefKind, SymbolKind};
+use crate::token::TokenKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    Variable,
+    Parameter,
+    Function,
+    Property,
+    Type,
+}
+
+// Legend order: the index of each type/modifier is what the client receives
+pub const TOKEN_TYPES: &[&str] = &["variable", "parameter", "function", "property", "type"];
+pub const TOKEN_MODIFIERS: &[&str] = &["declaration", "modification", "defaultLibrary", "host"];
+
+pub const MOD_DECLARATION: u32 = 1;
+pub const MOD_MODIFICATION: u32 = 1 << 1;
+pub const MOD_DEFAULT_LIBRARY: u32 = 1 << 2;
+pub const MOD_HOST: u32 = 1 << 3;
+
+const TYPE_NAMES: &[&str] = &["number", "string", "bool", "any", "list", "map", "record"];
+
+// A highlighted range on one line; line and col are 1-based, len counts characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+    pub ty: TokenType,
+    pub modifiers: u32,
+}
+
+impl TokenType {
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+// Tokens in source order, or None when the source does not parse
+pub fn semantic_tokens(src: &str, decls: Option<&Declarations>) -> Option<Vec<SemanticToken>> {
+    let program = Parser::new(src).parse_program().ok()?;
+    let table = symbols::analyze(&program);
+    let mut out: Vec<SemanticToken> = Vec::new();
+
+    for r in &table.refs {
+        let (ty, mut modifiers) = match table.definition(r) {
+            Some(sym) => match sym.kind {
+                SymbolKind::Function => (TokenType::Function, 0),
+                SymbolKind::Parameter => (TokenType::Parameter, 0),
+                SymbolKind::Variable | SymbolKind::LoopVariable => (TokenType::Variable, 0),
+            },
+            None if BUILTINS.contains(&r.name.as_str()) => {
+                (TokenType::Function, MOD_DEFAULT_LIBRARY)
+            }
+            None if decls.is_some_and(|d| d.natives.contains_key(&r.name)) => {
+                (TokenType::Function, MOD_HOST)
+            }
+            None => (TokenType::Variable, 0),
+        };
+        modifiers |= match r.kind {
+            RefKind::Decl => MOD_DECLARATION,
+            RefKind::Write => MOD_MODIFICATION,
+            RefKind::Read => 0,
+        };
+        out.push(SemanticToken {
+            line: r.span.line,
+            col: r.span.col,
+            len: r.name.chars().count(),
+            ty,
+            modifiers,
+        });
+    }
+
+    let mut fields = Vec::new();
+    for s in &program.statements {
+        field_names_stmt(s, &mut fields);
+    }
+    out.extend(fields);
+
+    // Whatever identifiers are left are record keys (`name:`) or type names
+    let covered: HashSet<(usize, usize)> = out.iter().map(|t| (t.line, t.col)).collect();
+    let tokens = Lexer::new(src).lex();
+    for (i, t) in tokens.iter().enumerate() {
+        let TokenKind::Identifier(name) = &t.kind else {
+            continue;
+        };
+        if covered.contains(&(t.line, t.col)) {
+            continue;
+        }
+        let ty = if matches!(tokens.get(i + 1).map(|n| &n.kind), Some(TokenKind::Colon)) {
+            TokenType::Property
+        } else if TYPE_NAMES.contains(&name.as_str()) {
+            TokenType::Type
+        } else {
+            continue;
+        };
+        out.push(SemanticToken {
+            line: t.line,
+            col: t.col,
+            len: name.chars().count(),
+            ty,
+            modifiers: 0,
+        });
+    }
+
+    out.sort_by_key(|t| (t.line, t.col));
+    out.dedup_by_key(|t| (t.line, t.col));
+    Some(out)
+}
+
+// `.name` in field accesses; the name is the last thing in the expression's span
+fn field_names_expr(e: &Expr, out: &mut Vec<SemanticToken>) {
+    match &e.kind {
+        ExprKind::Field { target, name } => {
+            field_names_expr(target, out);
+            let len = name.chars().count();
+            out.push(SemanticToken {
+                line: e.span.end_line,
+                col: e.span.end_col.saturating_sub(len),
+                len,
+                ty: TokenType::Property,
+                modifiers: 0,
+            });
+        }
+        ExprKind::Literal(_) | ExprKind::Var(_, _) => {}
+        ExprKind::Assign { value, .. } => field_names_expr(value, out),
+        ExprKind::Binary { left, right, .. } => {
+            field_names_expr(left, out);
+            field_names_expr(right, out);
+        }
+        ExprKind::Unary { expr, .. } => field_names_expr(expr, out),
+        ExprKind::Call { callee, args } => {
+            field_names_expr(callee, out);
+            for a in args {
+                field_names_expr(a, out);
+            }
+        }
+        ExprKind::Fn(f) => {
+            for s in &f.body {
+                field_names_stmt(s, out);
+            }
+        }
+        ExprKind::List(items) => {
+            for i in items {
+                field_names_expr(i, out);
+            }
+        }
+        ExprKind::Map(props) => {
+            for (_, v) in props {
+                field_names_expr(v, out);
+            }
+        }
+        ExprKind::Index { target, index } => {
+            field_names_expr(target, out);
+            field_names_expr(index, out);
+        }
+    }
+}
+
+fn field_names_stmt(s: &Stmt, out: &mut Vec<SemanticToken>) {
+    match &s.kind {
+        StmtKind::Let { init, .. } => field_names_expr(init, out),
+        StmtKind::Expr(e) => field_names_expr(e, out),
+        StmtKind::Block(b, _) | StmtKind::Test { body: b, .. } => {
+            for s in b {
+                field_names_stmt(s, out);
+            }
+        }
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            field_names_expr(cond, out);
+            field_names_stmt(then_branch, out);
+            if let Some(e) = else_branch {
+                field_names_stmt(e, out);
+            }
+        }
+        StmtKind::While { cond, body } => {
+            field_names_expr(cond, out);
+            field_names_stmt(body, out);
+        }
+        StmtKind::For { iter, body, .. } => {
+            field_names_expr(iter, out);
+            field_names_stmt(body, out);
+        }
+        StmtKind::Return(v) => {
+            if let Some(e) = v {
+                field_names_expr(e, out);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue => {}
+    }
+}
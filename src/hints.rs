@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: MIT
+// Inlay hints for editors: inferred types of variables (loop variables, and `any`-annotated
+// lets whose initializer has a more precise type), inferred return types of functions
+// without a `-> Type` annotation, and parameter names at call sites.
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::ast::*;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::symbols::{self, SymbolKind};
+use crate::token::{Token, TokenKind};
+use crate::typecheck::{self, CheckOptions, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintKind {
+    Type,
+    Parameter,
+}
+
+// A label shown before the character at 1-based (line, col)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlayHint {
+    pub line: usize,
+    pub col: usize,
+    pub label: String,
+    pub kind: HintKind,
+}
+
+// Which hints to show; field names match the client settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HintOptions {
+    pub variable_types: bool,
+    pub return_types: bool,
+    pub parameter_names: bool,
+}
+
+impl Default for HintOptions {
+    fn default() -> Self {
+        Self {
+            variable_types: true,
+            return_types: true,
+            parameter_names: true,
+        }
+    }
+}
+
+// Hints in source order, or None when the source does not parse
+pub fn inlay_hints(src: &str, opts: &HintOptions, check: &CheckOptions) -> Option<Vec<InlayHint>> {
+    let program = Parser::new(src).parse_program().ok()?;
+    let tc = typecheck::check_program_with_options(&program, check);
+    let mut h = Hinter {
+        opts,
+        inferred: tc.inferred.into_iter().collect(),
+        tokens: Lexer::new(src).lex(),
+        table: symbols::analyze(&program),
+        check,
+        out: Vec::new(),
+    };
+    for s in &program.statements {
+        h.stmt(s);
+    }
+    let mut out = h.out;
+    out.sort_by_key(|hint| (hint.line, hint.col));
+    Some(out)
+}
+
+struct Hinter<'a> {
+    opts: &'a HintOptions,
+    check: &'a CheckOptions,
+    inferred: HashMap<Span, Type>,
+    tokens: Vec<Token>,
+    table: symbols::SymbolTable,
+    out: Vec<InlayHint>,
+}
+
+impl Hinter<'_> {
+    fn push(&mut self, line: usize, col: usize, label: String, kind: HintKind) {
+        self.out.push(InlayHint {
+            line,
+            col,
+            label,
+            kind,
+        });
+    }
+
+    // Index of the token starting at a 1-based position
+    fn token_at(&self, line: usize, col: usize) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|t| t.line == line && t.col == col)
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Let {
+                name_span,
+                ty,
+                init,
+                ..
+            } => {
+                if self.opts.variable_types && matches!(ty, Some(TypeExpr::Any)) {
+                    self.any_let(*name_span);
+                }
+                self.expr(init);
+            }
+            StmtKind::Expr(e) => self.expr(e),
+            StmtKind::Block(b, _) | StmtKind::Test { body: b, .. } => {
+                for s in b {
+                    self.stmt(s);
+                }
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            StmtKind::For {
+                name_span,
+                iter,
+                body,
+                ..
+            } => {
+                if self.opts.variable_types {
+                    if let Some(t) = self.inferred.get(name_span).cloned() {
+                        let label = format!(": {}", t);
+                        self.push(name_span.end_line, name_span.end_col, label, HintKind::Type);
+                    }
+                }
+                self.expr(iter);
+                self.stmt(body);
+            }
+            StmtKind::Return(v) => {
+                if let Some(e) = v {
+                    self.expr(e);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    // `let x: any = ...` whose initializer has a precise type: shown after `any`
+    fn any_let(&mut self, name_span: Span) {
+        let Some(t) = self.inferred.get(&name_span).cloned() else {
+            return;
+        };
+        if t == Type::Any {
+            return;
+        }
+        let Some(i) = self.token_at(name_span.line, name_span.col) else {
+            return;
+        };
+        if let Some(any) = self.tokens.get(i + 2) {
+            let (line, col) = (any.end_line, any.end_col);
+            self.push(line, col, format!("≈ {}", t), HintKind::Type);
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Literal(_) | ExprKind::Var(_, _) => {}
+            ExprKind::Assign { value, .. } => self.expr(value),
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { expr, .. } => self.expr(expr),
+            ExprKind::Call { callee, args } => {
+                if self.opts.parameter_names {
+                    self.parameter_names(callee, args);
+                }
+                self.expr(callee);
+                for a in args {
+                    self.expr(a);
+                }
+            }
+            ExprKind::Fn(f) => {
+                if self.opts.return_types && f.ret.is_none() {
+                    self.return_type(e);
+                }
+                for s in &f.body {
+                    self.stmt(s);
+                }
+            }
+            ExprKind::List(items) => {
+                for i in items {
+                    self.expr(i);
+                }
+            }
+            ExprKind::Map(props) => {
+                for (_, v) in props {
+                    self.expr(v);
+                }
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Field { target, .. } => self.expr(target),
+        }
+    }
+
+    // `-> T` after the parameter list of a function literal
+    fn return_type(&mut self, f: &Expr) {
+        let Some(Type::Func(_, ret)) = self.inferred.get(&f.span).cloned() else {
+            return;
+        };
+        let start = self
+            .tokens
+            .iter()
+            .position(|t| (t.line, t.col) >= (f.span.line, f.span.col));
+        let Some(start) = start else {
+            return;
+        };
+        let mut depth = 0;
+        for t in &self.tokens[start..] {
+            match t.kind {
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let (line, col) = (t.end_line, t.end_col);
+                        self.push(line, col, format!("-> {}", ret), HintKind::Type);
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // `name:` before each argument, unless the argument already spells the name
+    fn parameter_names(&mut self, callee: &Expr, args: &[Expr]) {
+        let ExprKind::Var(fname, _) = &callee.kind else {
+            return;
+        };
+        let names: Vec<String> = match self
+            .table
+            .reference_at(callee.span.line, callee.span.col)
+            .and_then(|r| self.table.definition(r))
+        {
+            Some(sym) if sym.kind == SymbolKind::Function => sym.params.clone(),
+            Some(_) => return,
+            None => match self.check.decls.as_ref().and_then(|d| d.natives.get(fname)) {
+                Some(d) => d.params.iter().map(|p| p.name.clone()).collect(),
+                None => return,
+            },
+        };
+        for (arg, name) in args.iter().zip(names) {
+            if matches!(&arg.kind, ExprKind::Var(v, _) if *v == name) {
+                continue;
+            }
+            self.push(
+                arg.span.line,
+                arg.span.col,
+                format!("{}:", name),
+                HintKind::Parameter,
+            );
+        }
+    }
+}
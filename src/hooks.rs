@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: MIT
+// Execution hooks for debuggers, profilers and coverage tools. An interpreter with hooks
+// installed reports each statement before it runs, which way conditions went, script
+// function calls and returns, emitted events and their dispatch to script handlers, host
+// calls and the error a script stops with. Without hooks each of these points is a single
+// `Option` check.
+//
+// A hook stops the script by returning `Flow::Abort`, and pauses it by not returning (the
+// debugger blocks until the client resumes); it gets the interpreter, e.g. to evaluate
+// expressions in the current environment. Hooks are not called while a hook runs.
+use std::rc::Rc;
+
+use crate::ast::Span;
+use crate::eval::{Interpreter, RuntimeError};
+use crate::value::{Function, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    Continue,
+    // End the script with RuntimeError::Aborted
+    Abort(String),
+}
+
+#[allow(unused_variables)]
+pub trait Hooks {
+    // Before a statement runs
+    fn statement(&mut self, interp: &mut Interpreter, span: Span) -> Flow {
+        Flow::Continue
+    }
+
+    // The statement at `span` decided where to go: an `if` or `while` condition was
+    // evaluated, or a `for` took its next item (`taken`) or found none left
+    fn branch(&mut self, interp: &mut Interpreter, span: Span, taken: bool) -> Flow {
+        Flow::Continue
+    }
+
+    // A script function is called by the call expression at `call`; `name` is the callee
+    // as written, `<anonymous>`, or `on("event")` for a handler run by an emit
+    fn enter(&mut self, interp: &mut Interpreter, name: &str, call: Span, args: &[Value]) -> Flow {
+        Flow::Continue
+    }
+
+    // The function last entered returned
+    fn exit(&mut self, interp: &mut Interpreter, name: &str, result: &Value) -> Flow {
+        Flow::Continue
+    }
+
+    // `emit(event, payload)`, before any handler runs
+    fn emit(&mut self, interp: &mut Interpreter, event: &str, payload: &Value) -> Flow {
+        Flow::Continue
+    }
+
+    // A script handler is about to be run for an emitted event
+    fn dispatch(&mut self, interp: &mut Interpreter, event: &str, handler: &Rc<Function>) -> Flow {
+        Flow::Continue
+    }
+
+    // `host(op, payload)` returned
+    fn host_call(
+        &mut self,
+        interp: &mut Interpreter,
+        op: &str,
+        payload: &Value,
+        result: &Result<Value, String>,
+    ) -> Flow {
+        Flow::Continue
+    }
+
+    // The script, or a host's `call` into it, stopped with an error; functions entered on
+    // the way have not exited
+    fn error(&mut self, interp: &mut Interpreter, error: &RuntimeError) {}
+}
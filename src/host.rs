@@ -2,19 +2,48 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::output::Record;
 use crate::value::{EnvRef, Function, Value};
 
 pub trait HostApi {
     fn call(&self, op: &str, payload: Value) -> Result<Value, String>;
+
+    // A script function that answers `op` in place of `call`; the interpreter runs it with
+    // the payload
+    fn script(&self, _op: &str, _payload: &Value) -> Option<Rc<Function>> {
+        None
+    }
+
+    // `emit(event, payload)` was called, before its handlers run
+    fn emitted(&self, _event: &str, _payload: &Value) {}
+
+    // A line the script printed, when the interpreter's output is the Host
+    fn print(&self, line: &str) {
+        println!("{}", line);
+    }
+
+    // A record the script logged, when the interpreter's output is the Host
+    fn log(&self, record: &Record) {
+        eprintln!("{}", record.text());
+    }
 }
 
 #[derive(Default, Clone)]
 pub struct Host {
     // simple in-process event bus
     pub events: Rc<RefCell<HashMap<String, Vec<Rc<Function>>>>>,
+    // The engine answering host(op, payload); without one ops are echoed back
+    pub api: Option<Rc<dyn HostApi>>,
 }
 
 impl Host {
+    pub fn with_api(api: Rc<dyn HostApi>) -> Self {
+        Self {
+            api: Some(api),
+            ..Default::default()
+        }
+    }
+
     pub fn on(&self, name: &str, func: Rc<Function>) {
         self.events
             .borrow_mut()
@@ -24,11 +53,14 @@ impl Host {
     }
 
     pub fn emit(&self, name: &str, data: Value, env: EnvRef) -> Result<Value, String> {
+        if let Some(api) = &self.api {
+            api.emitted(name, &data);
+        }
         if let Some(list) = self.events.borrow().get(name) {
             for f in list.iter() {
                 match f.as_ref() {
                     Function::User { .. } => {
-                        // call via interpreter will handle
+                        // Script handlers are run by the interpreter after this
                     }
                     Function::Native { fun, .. } => {
                         fun(vec![data.clone()], env.clone())?;
@@ -38,15 +70,128 @@ impl Host {
         }
         Ok(Value::Null)
     }
+
+    pub fn script(&self, op: &str, payload: &Value) -> Option<Rc<Function>> {
+        self.api.as_ref().and_then(|api| api.script(op, payload))
+    }
 }
 
 impl HostApi for Host {
     fn call(&self, op: &str, payload: Value) -> Result<Value, String> {
-        // Default stub: just echo as map { ok: true, op, payload }
-        let mut m = std::collections::BTreeMap::new();
-        m.insert("ok".into(), Value::Bool(true));
-        m.insert("op".into(), Value::String(op.to_string()));
-        m.insert("payload".into(), payload);
-        Ok(Value::Map(m))
+        match &self.api {
+            Some(api) => api.call(op, payload),
+            None => Ok(echo(op, payload)),
+        }
+    }
+}
+
+// Default stub: just echo as map { ok: true, op, payload }
+fn echo(op: &str, payload: Value) -> Value {
+    let mut m = crate::value::Map::new();
+    m.insert("ok".into(), Value::Bool(true));
+    m.insert("op".into(), Value::String(op.to_string()));
+    m.insert("payload".into(), payload);
+    Value::Map(m)
+}
+
+// How a MockHost answers an op
+#[derive(Clone)]
+pub enum Reply {
+    Value(Value),
+    Error(String),
+    Native(Rc<dyn Fn(Value) -> Result<Value, String>>),
+    // Run by the interpreter with the payload
+    Script(Rc<Function>),
+}
+
+#[derive(Clone)]
+pub enum Recorded {
+    Call { op: String, payload: Value },
+    Event { event: String, payload: Value },
+}
+
+// A host for tests: answers ops from a table of replies (echoing the others like the
+// default host) and records every host call and emitted event in order. Clones share the
+// table and the record.
+#[derive(Clone, Default)]
+pub struct MockHost {
+    replies: Rc<RefCell<HashMap<String, Reply>>>,
+    record: Rc<RefCell<Vec<Recorded>>>,
+}
+
+impl MockHost {
+    pub fn reply(&self, op: &str, reply: Reply) {
+        self.replies.borrow_mut().insert(op.to_string(), reply);
+    }
+
+    // A Host whose ops and events go through this mock
+    pub fn host(&self) -> Host {
+        Host::with_api(Rc::new(self.clone()))
+    }
+
+    // Host calls and emitted events so far, in order
+    pub fn recorded(&self) -> Vec<Recorded> {
+        self.record.borrow().clone()
+    }
+
+    // Payloads of the calls of `op`
+    pub fn calls(&self, op: &str) -> Vec<Value> {
+        self.record
+            .borrow()
+            .iter()
+            .filter_map(|r| match r {
+                Recorded::Call { op: o, payload } if o == op => Some(payload.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Names of the emitted events, in order
+    pub fn events(&self) -> Vec<String> {
+        self.record
+            .borrow()
+            .iter()
+            .filter_map(|r| match r {
+                Recorded::Event { event, .. } => Some(event.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn log_call(&self, op: &str, payload: &Value) {
+        self.record.borrow_mut().push(Recorded::Call {
+            op: op.to_string(),
+            payload: payload.clone(),
+        });
+    }
+}
+
+impl HostApi for MockHost {
+    fn call(&self, op: &str, payload: Value) -> Result<Value, String> {
+        self.log_call(op, &payload);
+        let reply = self.replies.borrow().get(op).cloned();
+        match reply {
+            Some(Reply::Value(v)) => Ok(v),
+            Some(Reply::Error(e)) => Err(e),
+            Some(Reply::Native(f)) => f(payload),
+            Some(Reply::Script(_)) | None => Ok(echo(op, payload)),
+        }
+    }
+
+    fn script(&self, op: &str, payload: &Value) -> Option<Rc<Function>> {
+        match self.replies.borrow().get(op) {
+            Some(Reply::Script(f)) => {
+                self.log_call(op, payload);
+                Some(f.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn emitted(&self, event: &str, payload: &Value) {
+        self.record.borrow_mut().push(Recorded::Event {
+            event: event.to_string(),
+            payload: payload.clone(),
+        });
     }
 }
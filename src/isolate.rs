@@ -0,0 +1,313 @@
+// SPDX-License-Identifier: MIT
+// Isolates: instances of a compiled script driven from other threads. The interpreter is
+// built on Rc and RefCell and stays on one thread, so each isolate lives on one worker thread
+// of an `IsolatePool` and is driven by messages. A pool has a fixed number of workers, each
+// serving many isolates from one queue, so a thousand NPCs do not need a thousand threads;
+// isolates on the same worker take turns, and a long call holds up the others there.
+// Isolates are Send and Sync and can be handed to job-system threads. Values cross between
+// threads as `SendValue`s, a copy of a value without the Rc sharing; functions cannot cross.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::OnceLock;
+use std::thread;
+
+use thiserror::Error;
+
+use crate::compiled::{CompiledScript, Instance};
+use crate::eval::{Interpreter, RuntimeError};
+use crate::host::Host;
+use crate::value::Value;
+
+// A Value that can be sent to another thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendValue {
+    Number(f64),
+    Bool(bool),
+    String(String),
+    Null,
+    List(Vec<SendValue>),
+    Map(BTreeMap<String, SendValue>),
+}
+
+#[derive(Debug, Error)]
+pub enum IsolateError {
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+    // A function was passed to or returned from an isolate
+    #[error("{0} cannot be sent to another isolate")]
+    NotSendable(String),
+    // The isolate is gone: one of its calls panicked, or its setup did
+    #[error("isolate stopped")]
+    Stopped,
+}
+
+impl TryFrom<&Value> for SendValue {
+    type Error = IsolateError;
+
+    fn try_from(v: &Value) -> Result<Self, IsolateError> {
+        Ok(match v {
+            Value::Number(n) => SendValue::Number(*n),
+            Value::Bool(b) => SendValue::Bool(*b),
+            Value::String(s) => SendValue::String(s.clone()),
+            Value::Null => SendValue::Null,
+            Value::List(items) => SendValue::List(
+                items
+                    .iter()
+                    .map(SendValue::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Map(m) => SendValue::Map(
+                m.iter()
+                    .map(|(k, v)| Ok((k.clone(), SendValue::try_from(v)?)))
+                    .collect::<Result<_, IsolateError>>()?,
+            ),
+            Value::Function(_) => return Err(IsolateError::NotSendable(v.to_string())),
+        })
+    }
+}
+
+impl From<SendValue> for Value {
+    fn from(v: SendValue) -> Self {
+        match v {
+            SendValue::Number(n) => Value::Number(n),
+            SendValue::Bool(b) => Value::Bool(b),
+            SendValue::String(s) => Value::String(s),
+            SendValue::Null => Value::Null,
+            SendValue::List(items) => Value::List(items.into_iter().map(Value::from).collect()),
+            SendValue::Map(m) => {
+                Value::Map(m.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+// Printed as the value it stands for
+impl Display for SendValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Value::from(self.clone()).fmt(f)
+    }
+}
+
+type Reply = Result<SendValue, IsolateError>;
+type Setup = Box<dyn FnOnce() -> Interpreter + Send>;
+
+enum Request {
+    Call {
+        name: String,
+        args: Vec<SendValue>,
+        reply: Sender<Reply>,
+    },
+    Get {
+        name: String,
+        reply: Sender<Reply>,
+    },
+}
+
+// What a worker is asked to do for the isolate with the given id
+enum Job {
+    Start {
+        id: usize,
+        script: CompiledScript,
+        setup: Setup,
+        ready: Sender<Result<(), IsolateError>>,
+    },
+    Request(usize, Request),
+    Stop {
+        id: usize,
+        done: Sender<()>,
+    },
+}
+
+// A fixed set of worker threads that isolates are spread over. Each isolate stays on the
+// worker it started on; workers exit once the pool and all of their isolates are dropped.
+// A host native that calls or drops an isolate on its own worker waits for itself forever,
+// so isolates that drive each other belong in separate pools.
+pub struct IsolatePool {
+    workers: Vec<Sender<Job>>,
+    next: AtomicUsize,
+}
+
+impl IsolatePool {
+    // A pool with `threads` workers (at least one)
+    pub fn new(threads: usize) -> Self {
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let (jobs, queue) = mpsc::channel();
+                thread::Builder::new()
+                    .name(format!("qk-isolate-{}", i))
+                    .spawn(move || work(queue))
+                    .expect("spawn isolate worker");
+                jobs
+            })
+            .collect();
+        Self {
+            workers,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // The pool `Isolate::spawn` uses, with a worker per available core
+    pub fn shared() -> &'static IsolatePool {
+        static SHARED: OnceLock<IsolatePool> = OnceLock::new();
+        SHARED.get_or_init(|| {
+            IsolatePool::new(thread::available_parallelism().map_or(4, |n| n.get()))
+        })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    // An instance of `script` with the default host
+    pub fn spawn(&self, script: &CompiledScript) -> Result<Isolate, IsolateError> {
+        self.spawn_with(script, || Interpreter::with_host(Host::default()))
+    }
+
+    // An instance of `script` in the interpreter `setup` makes. Hosts and outputs are not
+    // Send, so `setup` runs on the isolate's worker and creates them there.
+    pub fn spawn_with(
+        &self,
+        script: &CompiledScript,
+        setup: impl FnOnce() -> Interpreter + Send + 'static,
+    ) -> Result<Isolate, IsolateError> {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let jobs = self.workers[id % self.workers.len()].clone();
+        let (ready, started) = mpsc::channel();
+        let _ = jobs.send(Job::Start {
+            id,
+            script: script.clone(),
+            setup: Box::new(setup),
+            ready,
+        });
+        let isolate = Isolate { id, jobs };
+        started.recv().map_err(|_| IsolateError::Stopped)??;
+        Ok(isolate)
+    }
+}
+
+// A script instance on a pool worker. Requests run one at a time, in the order they were
+// sent; dropping the isolate lets it finish them and waits for its instance to be dropped.
+pub struct Isolate {
+    id: usize,
+    jobs: Sender<Job>,
+}
+
+impl Isolate {
+    // An instance of `script` on the shared pool, with the default host
+    pub fn spawn(script: &CompiledScript) -> Result<Self, IsolateError> {
+        IsolatePool::shared().spawn(script)
+    }
+
+    // An instance of `script` on the shared pool, in the interpreter `setup` makes
+    pub fn spawn_with(
+        script: &CompiledScript,
+        setup: impl FnOnce() -> Interpreter + Send + 'static,
+    ) -> Result<Self, IsolateError> {
+        IsolatePool::shared().spawn_with(script, setup)
+    }
+
+    // Call one of the script's top-level functions and wait for its result
+    pub fn call(&self, name: &str, args: Vec<SendValue>) -> Reply {
+        self.post(name, args).wait()
+    }
+
+    // Start a call without waiting for it, e.g. to tick many isolates at once
+    pub fn post(&self, name: &str, args: Vec<SendValue>) -> Pending {
+        self.request(|reply| Request::Call {
+            name: name.to_string(),
+            args,
+            reply,
+        })
+    }
+
+    // The value of the global `name`, or null if there is none
+    pub fn get(&self, name: &str) -> Reply {
+        self.request(|reply| Request::Get {
+            name: name.to_string(),
+            reply,
+        })
+        .wait()
+    }
+
+    fn request(&self, make: impl FnOnce(Sender<Reply>) -> Request) -> Pending {
+        let (reply, result) = mpsc::channel();
+        // If the instance is gone, `result` disconnects and waiting reports Stopped
+        let _ = self.jobs.send(Job::Request(self.id, make(reply)));
+        Pending(result)
+    }
+}
+
+impl Drop for Isolate {
+    fn drop(&mut self) {
+        let (done, stopped) = mpsc::channel();
+        if self.jobs.send(Job::Stop { id: self.id, done }).is_ok() {
+            let _ = stopped.recv();
+        }
+    }
+}
+
+// The result of a call posted to an isolate
+pub struct Pending(Receiver<Reply>);
+
+impl Pending {
+    pub fn wait(self) -> Reply {
+        self.0.recv().unwrap_or(Err(IsolateError::Stopped))
+    }
+}
+
+// Run jobs for this worker's isolates until every sender is dropped. A panic drops only
+// the instance it happened in, whose pending replies then report Stopped.
+fn work(queue: Receiver<Job>) {
+    let mut instances: HashMap<usize, Instance> = HashMap::new();
+    for job in queue {
+        match job {
+            Job::Start {
+                id,
+                script,
+                setup,
+                ready,
+            } => match panic::catch_unwind(AssertUnwindSafe(|| script.instantiate_in(setup()))) {
+                Ok(Ok(instance)) => {
+                    instances.insert(id, instance);
+                    let _ = ready.send(Ok(()));
+                }
+                Ok(Err(e)) => {
+                    let _ = ready.send(Err(IsolateError::Runtime(e)));
+                }
+                Err(_) => {}
+            },
+            Job::Request(id, request) => {
+                let Some(instance) = instances.get_mut(&id) else {
+                    continue;
+                };
+                if panic::catch_unwind(AssertUnwindSafe(|| answer(instance, request))).is_err() {
+                    instances.remove(&id);
+                }
+            }
+            Job::Stop { id, done } => {
+                instances.remove(&id);
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn answer(instance: &mut Instance, request: Request) {
+    match request {
+        Request::Call { name, args, reply } => {
+            let args = args.into_iter().map(Value::from).collect();
+            let result = instance
+                .call(&name, args)
+                .map_err(IsolateError::from)
+                .and_then(|v| SendValue::try_from(&v));
+            let _ = reply.send(result);
+        }
+        Request::Get { name, reply } => {
+            let v = instance.get(&name).unwrap_or(Value::Null);
+            let _ = reply.send(SendValue::try_from(&v));
+        }
+    }
+}
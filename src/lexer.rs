@@ -1,6 +1,12 @@
 use crate::token::{Token, TokenKind};
 use logos::Logos;
 
+// Reserved words; kept in sync with the identifier match in Lexer::lex
+pub const KEYWORDS: &[&str] = &[
+    "let", "fn", "if", "else", "while", "for", "in", "return", "true", "false", "null", "break",
+    "continue",
+];
+
 #[derive(Logos, Debug, PartialEq)]
 #[logos(skip r"[ \t\r\f]+")]
 enum LexToken {
@@ -69,19 +75,56 @@ enum LexToken {
     Newline,
     #[regex(r"//[^\n]*")]
     LineComment,
-    #[regex(r"/\*([^*]|\*+[^*/])*\*+/")]
+    #[token("/*", block_comment)]
     BlockComment,
 }
 
+// Consume the rest of a block comment; an unterminated comment runs to end of input
+fn block_comment(lex: &mut logos::Lexer<LexToken>) {
+    let rest = lex.remainder();
+    let len = rest.find("*/").map(|i| i + 2).unwrap_or(rest.len());
+    lex.bump(len);
+}
+
 pub struct Lexer<'a> {
     src: &'a str,
 }
 
+// A `//` comment: the 1-based position of its slashes and its text, slashes included
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineComment<'a> {
+    pub line: usize,
+    pub col: usize,
+    pub text: &'a str,
+}
+
 impl<'a> Lexer<'a> {
     pub fn new(src: &'a str) -> Self {
         Self { src }
     }
 
+    // The `//` comments of the source, in order; `//` inside a string is not one
+    pub fn line_comments(&self) -> Vec<LineComment<'a>> {
+        let mut comments = Vec::new();
+        let (mut line, mut col) = (1usize, 1usize);
+        let mut lex = LexToken::lexer(self.src);
+        let mut last_end = 0usize;
+        while let Some(tok) = lex.next() {
+            let span = lex.span();
+            advance(&self.src[last_end..span.end], &mut line, &mut col);
+            if let Ok(LexToken::LineComment) = tok {
+                let text = &self.src[span.start..span.end];
+                comments.push(LineComment {
+                    line,
+                    col: col - text.chars().count(),
+                    text,
+                });
+            }
+            last_end = span.end;
+        }
+        comments
+    }
+
     pub fn lex(&self) -> Vec<Token> {
         let mut tokens = Vec::new();
         let mut line = 1usize;
@@ -91,15 +134,7 @@ impl<'a> Lexer<'a> {
         while let Some(tok) = lex.next() {
             let span = lex.span();
             // Update line/col by counting newlines in skipped ranges
-            let skipped = &self.src[last_end..span.start];
-            for ch in skipped.chars() {
-                if ch == '\n' {
-                    line += 1;
-                    col = 1;
-                } else {
-                    col += 1;
-                }
-            }
+            advance(&self.src[last_end..span.start], &mut line, &mut col);
             let t = match tok {
                 Ok(LexToken::LParen) => TokenKind::LeftParen,
                 Ok(LexToken::RParen) => TokenKind::RightParen,
@@ -157,41 +192,38 @@ impl<'a> Lexer<'a> {
                         _ => TokenKind::Identifier(s.to_string()),
                     }
                 }
-                Ok(LexToken::Newline) => {
-                    line += 1;
-                    col = 1;
-                    last_end = span.end;
-                    continue;
-                }
-                Ok(LexToken::LineComment) => {
-                    last_end = span.end;
-                    continue;
-                }
-                Ok(LexToken::BlockComment) => {
-                    let comm = &self.src[span.start..span.end];
-                    for ch in comm.chars() {
-                        if ch == '\n' {
-                            line += 1;
-                            col = 1;
-                        } else {
-                            col += 1;
-                        }
-                    }
+                Ok(LexToken::Newline) | Ok(LexToken::LineComment) | Ok(LexToken::BlockComment) => {
+                    advance(&self.src[span.start..span.end], &mut line, &mut col);
                     last_end = span.end;
                     continue;
                 }
                 Err(_) => {
+                    advance(&self.src[span.start..span.end], &mut line, &mut col);
                     last_end = span.end;
                     continue;
                 }
             };
-            tokens.push(Token::new(t, line, col));
+            let (start_line, start_col) = (line, col);
+            advance(&self.src[span.start..span.end], &mut line, &mut col);
+            tokens.push(Token::new(t, start_line, start_col).with_end(line, col));
             last_end = span.end;
         }
         tokens
     }
 }
 
+// Move a line/col cursor over the given text
+fn advance(text: &str, line: &mut usize, col: &mut usize) {
+    for ch in text.chars() {
+        if ch == '\n' {
+            *line += 1;
+            *col = 1;
+        } else {
+            *col += 1;
+        }
+    }
+}
+
 fn unescape(s: &str) -> String {
     let mut out = String::new();
     let mut chars = s.chars();
@@ -261,4 +293,18 @@ mod tests {
             "lexer did not produce expected token sequence"
         );
     }
+
+    #[test]
+    fn positions_count_tokens_and_blank_lines() {
+        let src = "let hp = 1;\n\n\n  /* a\n b */ print(\"hi\");";
+        let toks = Lexer::new(src).lex();
+        let pos: Vec<_> = toks
+            .iter()
+            .map(|t| (t.line, t.col, t.end_line, t.end_col))
+            .collect();
+        assert_eq!(pos[1], (1, 5, 1, 7)); // hp
+        assert_eq!(pos[3], (1, 10, 1, 11)); // 1
+        assert_eq!(pos[5], (5, 7, 5, 12)); // print
+        assert_eq!(pos[7], (5, 13, 5, 17)); // "hi"
+    }
 }
@@ -1,16 +1,35 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Questicle
+pub mod actions;
 pub mod ast;
+pub mod check;
+pub mod compiled;
+pub mod complete;
+pub mod coverage;
+pub mod debug;
+pub mod decl;
 pub mod env;
 pub mod eval;
+pub mod events;
 pub mod format;
 pub mod formatter;
+pub mod highlight;
+pub mod hints;
+pub mod hooks;
 pub mod host;
+pub mod isolate;
 pub mod lexer;
+pub mod lint;
+pub mod output;
 pub mod parser;
+pub mod profile;
+pub mod resolve;
 pub mod stdlib;
+pub mod symbols;
+pub mod testing;
 pub mod token;
 pub mod typecheck;
 pub mod value;
+pub mod workspace;
 
-pub use crate::{eval::Interpreter, host::Host, parser::Parser};
+pub use crate::{compiled::CompiledScript, eval::Interpreter, host::Host, parser::Parser};
@@ -0,0 +1,606 @@
+// SPDX-License-Identifier: MIT
+// Lint engine: named rules over the AST. Each rule's severity can be changed (or the rule
+// turned off) in a qklint.json file:
+//
+//   { "rules": { "unused-variable": "off", "self-assignment": "error" } }
+//
+// and single findings can be silenced with comments:
+//
+//   // qk: allow(unused-variable)        next line (or this line, when trailing code)
+//   // qk: allow-file(unhandled-event)   whole file
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::ast::*;
+use crate::check::{parse_diagnostic, Diagnostic, Severity};
+use crate::events::EventGraph;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+pub const CONFIG_FILE: &str = "qklint.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    #[serde(alias = "allow")]
+    Off,
+    #[serde(alias = "warn")]
+    Warning,
+    Error,
+}
+
+pub struct Rule {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: Level,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "unused-variable",
+        description: "a `let` binding that is never read",
+        default: Level::Warning,
+    },
+    Rule {
+        name: "unreachable-code",
+        description: "statements after `return`, `break` or `continue`",
+        default: Level::Warning,
+    },
+    Rule {
+        name: "break-outside-loop",
+        description: "`break` or `continue` that is not inside a loop",
+        default: Level::Error,
+    },
+    Rule {
+        name: "constant-condition",
+        description: "an `if`/`while` condition that is always true or always false",
+        default: Level::Warning,
+    },
+    Rule {
+        name: "unhandled-event",
+        description: "`emit` of an event that has no `on` handler in the linted scripts",
+        default: Level::Warning,
+    },
+    Rule {
+        name: "self-assignment",
+        description: "assigning a variable to itself",
+        default: Level::Warning,
+    },
+    Rule {
+        name: "duplicate-key",
+        description: "a map literal that repeats a key",
+        default: Level::Warning,
+    },
+];
+
+#[derive(Debug, Error)]
+pub enum LintConfigError {
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{path}: {source}")]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("unknown lint rule '{0}'")]
+    UnknownRule(String),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    pub rules: BTreeMap<String, Level>,
+}
+
+impl LintConfig {
+    pub fn load(path: &Path) -> Result<LintConfig, LintConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| LintConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let config: LintConfig =
+            serde_json::from_str(&text).map_err(|source| LintConfigError::Json {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // qklint.json in `dir`, or the default configuration when there is none
+    pub fn find(dir: &Path) -> Result<LintConfig, LintConfigError> {
+        let path = dir.join(CONFIG_FILE);
+        if path.is_file() {
+            LintConfig::load(&path)
+        } else {
+            Ok(LintConfig::default())
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), LintConfigError> {
+        match self
+            .rules
+            .keys()
+            .find(|k| !RULES.iter().any(|r| r.name == *k))
+        {
+            Some(k) => Err(LintConfigError::UnknownRule(k.clone())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn level(&self, rule: &str) -> Level {
+        match self.rules.get(rule) {
+            Some(l) => *l,
+            None => RULES
+                .iter()
+                .find(|r| r.name == rule)
+                .map(|r| r.default)
+                .unwrap_or(Level::Off),
+        }
+    }
+}
+
+// Parse and lint one source file; a file that does not parse yields the parse error
+pub fn lint_source(src: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    match Parser::new(src).parse_program() {
+        Ok(program) => lint_program(&program, src, config),
+        Err(e) => vec![parse_diagnostic(src, &e)],
+    }
+}
+
+// Like lint_source, for one of several scripts linted together: an event handled in any
+// script of `events` (the workspace's event graph) counts as handled
+pub fn lint_source_in(src: &str, config: &LintConfig, events: &EventGraph) -> Vec<Diagnostic> {
+    match Parser::new(src).parse_program() {
+        Ok(program) => lint_program_in(&program, src, config, events),
+        Err(e) => vec![parse_diagnostic(src, &e)],
+    }
+}
+
+pub fn lint_program(program: &Program, src: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    lint(program, src, config, None)
+}
+
+pub fn lint_program_in(
+    program: &Program,
+    src: &str,
+    config: &LintConfig,
+    events: &EventGraph,
+) -> Vec<Diagnostic> {
+    lint(program, src, config, Some(events))
+}
+
+fn lint(
+    program: &Program,
+    src: &str,
+    config: &LintConfig,
+    events: Option<&EventGraph>,
+) -> Vec<Diagnostic> {
+    let mut l = Linter::default();
+    l.push_scope();
+    l.stmts(&program.statements);
+    l.pop_scope();
+    let handled_elsewhere = |name: &str| {
+        events
+            .and_then(|g| g.events.get(name))
+            .is_some_and(|node| !node.handlers.is_empty())
+    };
+    let scope = match events {
+        Some(_) => "in any linted script",
+        None => "in this file",
+    };
+    for (name, span) in std::mem::take(&mut l.emitted) {
+        if !l.handled.contains(&name) && !handled_elsewhere(&name) {
+            l.report(
+                "unhandled-event",
+                format!("No `on` handler for event '{}' {}", name, scope),
+                span,
+            );
+        }
+    }
+
+    let allowed = Suppressions::parse(src);
+    let mut diags: Vec<Diagnostic> = l
+        .found
+        .into_iter()
+        .filter(|(rule, _, span)| !allowed.allows(rule, span.line))
+        .filter_map(|(rule, message, span)| {
+            let severity = match config.level(rule) {
+                Level::Off => return None,
+                Level::Warning => Severity::Warning,
+                Level::Error => Severity::Error,
+            };
+            Some(Diagnostic {
+                severity,
+                source: "lint",
+                code: Some(rule),
+                message,
+                hint: None,
+                span,
+            })
+        })
+        .collect();
+    diags.sort_by_key(|d| (d.span.line, d.span.col));
+    diags
+}
+
+#[derive(Default)]
+struct Linter {
+    found: Vec<(&'static str, String, Span)>,
+    scopes: Vec<Scope>,
+    loop_depth: usize,
+    // Event names with an on("name", ...) handler, and literal emit("name", ...) calls
+    handled: HashSet<String>,
+    emitted: Vec<(String, Span)>,
+}
+
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    // Names used before any visible binding existed, e.g. a function body calling a
+    // function declared further down; resolved when the scope closes
+    pending: Vec<String>,
+}
+
+struct Binding {
+    name: String,
+    span: Span,
+    used: bool,
+    // Set while the binding's own initializer is visited, so recursion is not a use
+    defining: bool,
+}
+
+impl Linter {
+    fn report(&mut self, rule: &'static str, message: String, span: Span) {
+        self.found.push((rule, message, span));
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(mut scope) = self.scopes.pop() else {
+            return;
+        };
+        for name in std::mem::take(&mut scope.pending) {
+            match scope.bindings.iter_mut().rev().find(|b| b.name == name) {
+                Some(b) => b.used = true,
+                None => {
+                    if let Some(parent) = self.scopes.last_mut() {
+                        parent.pending.push(name);
+                    }
+                }
+            }
+        }
+        for b in scope.bindings {
+            if !b.used && !b.name.starts_with('_') {
+                self.report(
+                    "unused-variable",
+                    format!("Variable '{}' is never used", b.name),
+                    b.span,
+                );
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, span: Span, used: bool, defining: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.bindings.push(Binding {
+                name: name.to_string(),
+                span,
+                used,
+                defining,
+            });
+        }
+    }
+
+    fn finish_defining(&mut self, name: &str) {
+        if let Some(b) = self
+            .scopes
+            .last_mut()
+            .and_then(|s| s.bindings.iter_mut().rev().find(|b| b.name == name))
+        {
+            b.defining = false;
+        }
+    }
+
+    fn use_var(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(b) = scope.bindings.iter_mut().rev().find(|b| b.name == name) {
+                if !b.defining {
+                    b.used = true;
+                }
+                return;
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.pending.push(name.to_string());
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        let mut terminated = false;
+        let mut reported = false;
+        for s in stmts {
+            if terminated && !reported {
+                self.report("unreachable-code", "Unreachable code".into(), s.span);
+                reported = true;
+            }
+            self.stmt(s);
+            terminated |= terminates(s);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let {
+                name,
+                name_span,
+                init,
+                ..
+            } => {
+                if let ExprKind::Fn(_) = init.kind {
+                    // Top-level functions may be entry points called by the host
+                    let top_level = self.scopes.len() == 1;
+                    self.declare(name, *name_span, top_level, true);
+                    self.expr(init);
+                    self.finish_defining(name);
+                } else {
+                    self.expr(init);
+                    self.declare(name, *name_span, false, false);
+                }
+            }
+            StmtKind::Expr(e) => self.expr(e),
+            StmtKind::Block(b, _) | StmtKind::Test { body: b, .. } => {
+                self.push_scope();
+                self.stmts(b);
+                self.pop_scope();
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                if let Some(value) = constant_truthiness(cond) {
+                    self.report(
+                        "constant-condition",
+                        format!("If condition is always {}", value),
+                        cond.span,
+                    );
+                }
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                // `while (true)` with a way out is the usual loop-until idiom
+                match constant_truthiness(cond) {
+                    Some(true) if exits_loop(body) => {}
+                    Some(value) => self.report(
+                        "constant-condition",
+                        format!("While condition is always {}", value),
+                        cond.span,
+                    ),
+                    None => {}
+                }
+                self.expr(cond);
+                self.loop_depth += 1;
+                self.stmt(body);
+                self.loop_depth -= 1;
+            }
+            StmtKind::For {
+                name,
+                name_span,
+                iter,
+                body,
+                ..
+            } => {
+                self.expr(iter);
+                self.push_scope();
+                self.declare(name, *name_span, true, false);
+                self.loop_depth += 1;
+                self.stmt(body);
+                self.loop_depth -= 1;
+                self.pop_scope();
+            }
+            StmtKind::Return(v) => {
+                if let Some(e) = v {
+                    self.expr(e);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                if self.loop_depth == 0 {
+                    let keyword = match stmt.kind {
+                        StmtKind::Break => "break",
+                        _ => "continue",
+                    };
+                    self.report(
+                        "break-outside-loop",
+                        format!("`{}` outside of a loop", keyword),
+                        stmt.span,
+                    );
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Var(name, _) => self.use_var(name),
+            ExprKind::Assign { name, value, .. } => {
+                if matches!(&value.kind, ExprKind::Var(v, _) if v == name) {
+                    self.report(
+                        "self-assignment",
+                        format!("Variable '{}' is assigned to itself", name),
+                        expr.span,
+                    );
+                }
+                self.expr(value);
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { expr, .. } => self.expr(expr),
+            ExprKind::Call { callee, args } => {
+                let event = match args.first().map(|a| &a.kind) {
+                    Some(ExprKind::Literal(Lit::String(s))) => Some(s.clone()),
+                    _ => None,
+                };
+                match (&callee.kind, event) {
+                    (ExprKind::Var(f, _), Some(name)) if f == "on" => {
+                        self.handled.insert(name);
+                    }
+                    (ExprKind::Var(f, _), Some(name)) if f == "emit" => {
+                        self.emitted.push((name, args[0].span));
+                    }
+                    _ => {}
+                }
+                self.expr(callee);
+                for a in args {
+                    self.expr(a);
+                }
+            }
+            ExprKind::Fn(f) => {
+                self.push_scope();
+                for p in &f.params {
+                    self.declare(&p.name, p.span, true, false);
+                }
+                let outer_loops = std::mem::take(&mut self.loop_depth);
+                self.stmts(&f.body);
+                self.loop_depth = outer_loops;
+                self.pop_scope();
+            }
+            ExprKind::List(items) => {
+                for e in items {
+                    self.expr(e);
+                }
+            }
+            ExprKind::Map(props) => {
+                let mut seen: HashSet<&str> = HashSet::new();
+                for (k, e) in props {
+                    if !seen.insert(k.as_str()) {
+                        self.report(
+                            "duplicate-key",
+                            format!("Duplicate key '{}' in map literal; the last value wins", k),
+                            e.span,
+                        );
+                    }
+                    self.expr(e);
+                }
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Field { target, .. } => self.expr(target),
+        }
+    }
+}
+
+// Whether control never reaches the statement after `s`
+fn terminates(s: &Stmt) -> bool {
+    match &s.kind {
+        StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue => true,
+        StmtKind::Block(b, _) => b.iter().any(terminates),
+        StmtKind::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => terminates(then_branch) && terminates(else_branch),
+        _ => false,
+    }
+}
+
+// Whether a loop body contains a `break` or `return` for this loop (nested loops excluded)
+fn exits_loop(s: &Stmt) -> bool {
+    match &s.kind {
+        StmtKind::Break | StmtKind::Return(_) => true,
+        StmtKind::Block(b, _) => b.iter().any(exits_loop),
+        StmtKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => exits_loop(then_branch) || else_branch.as_deref().map(exits_loop).unwrap_or(false),
+        _ => false,
+    }
+}
+
+// Truthiness of a condition known without running it (mirrors Value::truthy)
+fn constant_truthiness(e: &Expr) -> Option<bool> {
+    match &e.kind {
+        ExprKind::Literal(Lit::Bool(b)) => Some(*b),
+        ExprKind::Literal(Lit::Number(n)) => Some(*n != 0.0),
+        ExprKind::Literal(Lit::String(s)) => Some(!s.is_empty()),
+        ExprKind::Literal(Lit::Null) => Some(false),
+        ExprKind::List(items) => Some(!items.is_empty()),
+        ExprKind::Map(props) => Some(!props.is_empty()),
+        ExprKind::Fn(_) => Some(true),
+        _ => None,
+    }
+}
+
+// `// qk: allow(...)` and `// qk: allow-file(...)` comments
+#[derive(Default)]
+struct Suppressions {
+    file: HashSet<String>,
+    lines: HashMap<usize, HashSet<String>>,
+}
+
+impl Suppressions {
+    fn parse(src: &str) -> Suppressions {
+        let mut s = Suppressions::default();
+        let lines: Vec<&str> = src.lines().collect();
+        for comment in Lexer::new(src).line_comments() {
+            let directive: String = comment.text[2..]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            let (file_wide, rest) = if let Some(rest) = directive.strip_prefix("qk:allow-file(") {
+                (true, rest)
+            } else if let Some(rest) = directive.strip_prefix("qk:allow(") {
+                (false, rest)
+            } else {
+                continue;
+            };
+            let Some(end) = rest.find(')') else {
+                continue;
+            };
+            let rules = rest[..end].split(',').map(str::to_string);
+            if file_wide {
+                s.file.extend(rules);
+            } else {
+                // A comment on its own line applies to the next line
+                let before = lines.get(comment.line - 1).map_or("", |l| {
+                    let end = l
+                        .char_indices()
+                        .nth(comment.col - 1)
+                        .map_or(l.len(), |(i, _)| i);
+                    &l[..end]
+                });
+                let target = if before.trim().is_empty() {
+                    comment.line + 1
+                } else {
+                    comment.line
+                };
+                s.lines.entry(target).or_default().extend(rules);
+            }
+        }
+        s
+    }
+
+    fn allows(&self, rule: &str, line: usize) -> bool {
+        let hit = |set: &HashSet<String>| set.contains(rule) || set.contains("all");
+        hit(&self.file) || self.lines.get(&line).map(hit).unwrap_or(false)
+    }
+}
@@ -1,12 +1,23 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Questicle
-use questicle::{Host, Interpreter, Parser};
+use questicle::check::{self, Severity};
+use questicle::coverage;
+use questicle::decl::Declarations;
+use questicle::output::Level;
+use questicle::profile::Profiler;
+use questicle::testing::{self, TestOptions};
+use questicle::workspace::WorkspaceIndex;
+use questicle::{lint, typecheck, Host, Interpreter, Parser};
+use std::fs;
 use std::io::{self, Read};
-use std::{fs, path::PathBuf};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 fn main() {
     let args = std::env::args().skip(1);
     let mut repl = false;
+    let mut strict = false;
+    let mut checked = false;
     let mut file: Option<PathBuf> = None;
     // fmt options
     let mut fmt_mode = false;
@@ -14,6 +25,25 @@ fn main() {
     let mut fmt_write = true;
     let mut fmt_stdin = false;
     let mut fmt_paths: Vec<PathBuf> = Vec::new();
+    // check/lint options
+    let mut check_mode = false;
+    let mut lint_mode = false;
+    let mut events_mode = false;
+    let mut test_mode = false;
+    let mut test_filter: Option<String> = None;
+    let mut junit: Option<PathBuf> = None;
+    let mut lcov: Option<PathBuf> = None;
+    let mut snapshot = false;
+    let mut bless = false;
+    // run options
+    let mut profile = false;
+    let mut folded: Option<PathBuf> = None;
+    let mut log_level = Level::Info;
+    let mut deny_warnings = false;
+    let mut json = false;
+    let mut list_rules = false;
+    let mut lint_config: Option<PathBuf> = None;
+    let mut check_paths: Vec<PathBuf> = Vec::new();
 
     for arg in args {
         match arg.as_str() {
@@ -22,9 +52,60 @@ fn main() {
                 return;
             }
             "-r" | "--repl" => repl = true,
+            "--strict" => strict = true,
+            "--checked" => checked = true,
             "fmt" => {
                 fmt_mode = true;
             }
+            "check" if !fmt_mode && !lint_mode && !events_mode && !test_mode && file.is_none() => {
+                check_mode = true;
+            }
+            "lint" if !fmt_mode && !check_mode && !events_mode && !test_mode && file.is_none() => {
+                lint_mode = true;
+            }
+            "events" if !fmt_mode && !check_mode && !lint_mode && !test_mode && file.is_none() => {
+                events_mode = true;
+            }
+            "test" if !fmt_mode && !check_mode && !lint_mode && !events_mode && file.is_none() => {
+                test_mode = true;
+            }
+            "--deny-warnings" => deny_warnings = true,
+            "--json" => json = true,
+            "--list-rules" => list_rules = true,
+            arg if arg.starts_with("--config=") => {
+                lint_config = Some(PathBuf::from(&arg["--config=".len()..]));
+            }
+            arg if arg.starts_with("--filter=") => {
+                test_filter = Some(arg["--filter=".len()..].to_string());
+            }
+            "run"
+                if !fmt_mode
+                    && !check_mode
+                    && !lint_mode
+                    && !events_mode
+                    && !test_mode
+                    && file.is_none() => {}
+            arg if arg.starts_with("--log-level=") => {
+                let name = &arg["--log-level=".len()..];
+                log_level = Level::parse(name).unwrap_or_else(|| {
+                    eprintln!("Unknown log level '{}' (debug, info, warn or error)", name);
+                    std::process::exit(2);
+                });
+            }
+            "--profile" => profile = true,
+            arg if arg.starts_with("--profile=") => {
+                profile = true;
+                folded = Some(PathBuf::from(&arg["--profile=".len()..]));
+            }
+            "--snapshot" => snapshot = true,
+            "--bless" => bless = true,
+            "--coverage" => lcov = Some(PathBuf::from("lcov.info")),
+            arg if arg.starts_with("--coverage=") => {
+                lcov = Some(PathBuf::from(&arg["--coverage=".len()..]));
+            }
+            arg if arg.starts_with("--junit=") => {
+                junit = Some(PathBuf::from(&arg["--junit=".len()..]));
+            }
             "--check" => {
                 fmt_check = true;
                 fmt_write = false;
@@ -38,6 +119,8 @@ fn main() {
             path => {
                 if fmt_mode {
                     fmt_paths.push(PathBuf::from(path));
+                } else if check_mode || lint_mode || events_mode || test_mode {
+                    check_paths.push(PathBuf::from(path));
                 } else {
                     file = Some(PathBuf::from(path));
                 }
@@ -50,12 +133,86 @@ fn main() {
         std::process::exit(code);
     }
 
+    if check_mode {
+        let code = run_check(&check_paths, strict, deny_warnings, json).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            2
+        });
+        std::process::exit(code);
+    }
+
+    if lint_mode {
+        if list_rules {
+            for rule in lint::RULES {
+                let level = match rule.default {
+                    lint::Level::Off => "off",
+                    lint::Level::Warning => "warning",
+                    lint::Level::Error => "error",
+                };
+                println!("{:<20} {:<8} {}", rule.name, level, rule.description);
+            }
+            return;
+        }
+        let code = run_lint(&check_paths, lint_config.as_deref(), deny_warnings, json)
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                2
+            });
+        std::process::exit(code);
+    }
+
+    if events_mode {
+        let code = run_events(&check_paths, json).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            2
+        });
+        std::process::exit(code);
+    }
+
+    if test_mode {
+        let opts = TestOptions {
+            filter: test_filter,
+            coverage: lcov.is_some(),
+            snapshot,
+            bless,
+        };
+        let code = run_tests(&check_paths, &opts, junit.as_deref(), lcov.as_deref())
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                2
+            });
+        std::process::exit(code);
+    }
+
     let host = Host::default();
     let mut interp = Interpreter::with_host(host);
+    interp.checked = checked;
+    interp.set_log_level(log_level);
+    let profiler = (profile && file.is_some()).then(Profiler::new);
+    if let Some(p) = &profiler {
+        interp.hooks = Some(Box::new(p.clone()));
+    }
 
     if let Some(ref path) = file {
         let src = fs::read_to_string(path).expect("failed to read file");
-        run_source(&src, &mut interp);
+        // Declaration files next to the script describe the host it runs against
+        let dir = path
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let code = run_source(&src, &mut interp, strict, &dir);
+        if let Some(p) = &profiler {
+            eprint!("\n{}", p.to_table());
+            if let Some(path) = &folded {
+                if let Err(e) = fs::write(path, p.to_folded()) {
+                    eprintln!("{}: {}", path.display(), e);
+                }
+            }
+        }
+        if code != 0 {
+            std::process::exit(code);
+        }
     }
 
     if repl || file.is_none() {
@@ -63,13 +220,40 @@ fn main() {
     }
 }
 
-fn run_source(src: &str, interp: &mut Interpreter) {
+// Parse, check and run a script; parse and type errors exit, a runtime error returns 70
+fn run_source(src: &str, interp: &mut Interpreter, strict: bool, decl_dir: &Path) -> i32 {
     match Parser::new(src).parse_program() {
         Ok(program) => {
+            // Strict files must type-check cleanly before they run
+            if strict || typecheck::has_strict_pragma(src) {
+                let decls = match Declarations::load(&[decl_dir.to_path_buf()]) {
+                    Ok(d) => d,
+                    Err(e) => {
+                        eprintln!("Declaration error: {}", e);
+                        std::process::exit(65);
+                    }
+                };
+                let opts = typecheck::CheckOptions {
+                    strict: true,
+                    decls: Some(Arc::new(decls)),
+                    ..Default::default()
+                };
+                let tc = typecheck::check_program_with_options(&program, &opts);
+                if !tc.errors.is_empty() {
+                    for e in &tc.errors {
+                        eprintln!("Type error: {}", e.message);
+                        if let Some(ref hint) = e.hint {
+                            eprintln!("  hint: {}", hint);
+                        }
+                    }
+                    std::process::exit(65);
+                }
+            }
             if let Err(e) = interp.eval(program) {
                 eprintln!("Runtime error: {}", e);
-                std::process::exit(70);
+                return 70;
             }
+            0
         }
         Err(e) => {
             eprintln!("Parse error: {}", e);
@@ -114,13 +298,12 @@ fn run_repl(interp: &mut Interpreter) {
 
 fn print_help() {
     println!("Questicle - game scripting language\n");
-    println!("Usage: qk [options] [file.qk]\n");
-    println!("Options:\n  -r, --repl   Start an interactive REPL\n  -h, --help   Show this help\n\nSubcommands:\n  fmt [--check|--write] [--stdin] [paths...]  Format files");
+    println!("Usage: qk [run] [options] [file.qk]\n");
+    println!("Options:\n  -r, --repl   Start an interactive REPL\n  --strict     Type-check in strict mode before running\n  --checked    Enforce type annotations at runtime\n  --log-level=LEVEL  Drop log records below LEVEL (debug, info, warn, error; default info)\n  --profile[=FILE]  Print a per-function profile to stderr (and write folded stacks to FILE)\n  -h, --help   Show this help\n\nSubcommands:\n  fmt [--check|--write] [--stdin] [paths...]  Format files\n  check [--strict] [--deny-warnings] [--json] [paths...]  Parse and type-check files\n  lint [--config=FILE] [--deny-warnings] [--json] [--list-rules] [paths...]  Lint files\n  events [--json] [paths...]  Print the on/emit event graph as DOT (or JSON)\n  test [--filter=NAME] [--junit=FILE] [--coverage[=FILE]] [--snapshot] [--bless] [paths...]  Run test blocks (and compare printed output with .out files)");
 }
 
 fn run_fmt(stdin_mode: bool, paths: &[PathBuf], check: bool, write: bool) -> io::Result<i32> {
     use std::fs;
-    use walkdir::WalkDir;
     // Helper to process one file content
     fn process(path: Option<&PathBuf>, content: &str, check: bool, write: bool) -> io::Result<i32> {
         let formatted = questicle::formatter::format_source(content);
@@ -153,41 +336,260 @@ fn run_fmt(stdin_mode: bool, paths: &[PathBuf], check: bool, write: bool) -> io:
     }
 
     let mut code = 0;
+    let targets = collect_qk_files(paths);
+    for t in targets {
+        let content = std::fs::read_to_string(&t)?;
+        let rc = process(Some(&t), &content, check, write)?;
+        if rc == 1 {
+            code = 1;
+        }
+    }
+    Ok(code)
+}
+
+// Every .qk file under the given files/directories (default: the current directory)
+fn collect_qk_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    use walkdir::WalkDir;
+    let default = [PathBuf::from(".")];
+    let roots = if paths.is_empty() {
+        &default[..]
+    } else {
+        paths
+    };
     let mut targets: Vec<PathBuf> = Vec::new();
-    if paths.is_empty() {
-        // default: **/*.qk under cwd
-        for entry in WalkDir::new(".").into_iter().filter_map(Result::ok) {
+    for p in roots {
+        if p.is_file() {
+            targets.push(p.clone());
+            continue;
+        }
+        for entry in WalkDir::new(p)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+        {
             if entry.file_type().is_file()
                 && entry.path().extension().map(|e| e == "qk").unwrap_or(false)
             {
                 targets.push(entry.path().to_path_buf());
             }
         }
+    }
+    targets
+}
+
+// qk check: parse and type-check every file, print all diagnostics, and return the exit
+// code (1 when errors were found, or warnings under --deny-warnings)
+fn run_check(paths: &[PathBuf], strict: bool, deny_warnings: bool, json: bool) -> io::Result<i32> {
+    require_paths_exist(paths)?;
+    let opts = typecheck::CheckOptions {
+        strict,
+        decls: Some(Arc::new(load_decls(paths)?)),
+        ..Default::default()
+    };
+
+    let files = collect_qk_files(paths);
+    let mut found: Vec<(PathBuf, check::Diagnostic)> = Vec::new();
+    for path in &files {
+        let src = fs::read_to_string(path)?;
+        for d in check::check_source(&src, &opts) {
+            found.push((path.clone(), d));
+        }
+    }
+    let (errors, warnings) = print_report(files.len(), &found, json);
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        Ok(1)
     } else {
-        for p in paths {
-            let md = std::fs::metadata(p);
-            if let Ok(md) = md {
-                if md.is_dir() {
-                    for entry in WalkDir::new(p).into_iter().filter_map(Result::ok) {
-                        if entry.file_type().is_file()
-                            && entry.path().extension().map(|e| e == "qk").unwrap_or(false)
-                        {
-                            targets.push(entry.path().to_path_buf());
-                        }
-                    }
-                } else if md.is_file() {
-                    targets.push(p.clone());
-                }
+        Ok(0)
+    }
+}
+
+// qk events: index the scripts together, so handlers and payloads can be typed across
+// files, and print which scripts emit and handle each event
+fn run_events(paths: &[PathBuf], json: bool) -> io::Result<i32> {
+    require_paths_exist(paths)?;
+    let opts = typecheck::CheckOptions {
+        decls: Some(Arc::new(load_decls(paths)?)),
+        ..Default::default()
+    };
+    let mut index = WorkspaceIndex::new(opts);
+    index.load_files(collect_qk_files(paths));
+    let graph = index.event_graph();
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&graph).expect("event graph serializes")
+        );
+    } else {
+        print!("{}", graph.to_dot());
+    }
+    Ok(0)
+}
+
+// qk test: run the test blocks of every script (and with --snapshot, the scripts themselves
+// against their .out files), print a report (and optionally write JUnit XML and lcov coverage), and return 1 when a test failed or a script did not load
+fn run_tests(
+    paths: &[PathBuf],
+    opts: &TestOptions,
+    junit: Option<&Path>,
+    lcov: Option<&Path>,
+) -> io::Result<i32> {
+    require_paths_exist(paths)?;
+    let reports: Vec<_> = collect_qk_files(paths)
+        .iter()
+        .map(|path| testing::run_file(path, opts))
+        .filter(|r| r.error.is_some() || !r.results.is_empty())
+        .collect();
+    print!("{}", testing::to_text(&reports));
+    if let Some(path) = junit {
+        fs::write(path, testing::to_junit(&reports))?;
+    }
+    if let Some(path) = lcov {
+        let covered: Vec<_> = reports
+            .iter()
+            .filter_map(|r| Some((r.path.clone(), r.coverage.clone()?)))
+            .collect();
+        print!("\n{}", coverage::summary_table(&covered));
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, coverage::to_lcov(&covered))?;
+    }
+    Ok(if reports.iter().all(|r| r.passed()) {
+        0
+    } else {
+        1
+    })
+}
+
+fn load_decls(paths: &[PathBuf]) -> io::Result<Declarations> {
+    // Declaration files are looked up in the checked directories (or next to checked files)
+    let mut decl_roots: Vec<PathBuf> = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths
+            .iter()
+            .map(|p| match p.parent() {
+                Some(dir) if p.is_file() && !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ if p.is_file() => PathBuf::from("."),
+                _ => p.clone(),
+            })
+            .collect()
+    };
+    decl_roots.sort();
+    decl_roots.dedup();
+    Declarations::load(&decl_roots)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+// Print diagnostics (human-readable or as one JSON report); returns (errors, warnings)
+fn print_report(
+    files: usize,
+    found: &[(PathBuf, check::Diagnostic)],
+    json: bool,
+) -> (usize, usize) {
+    let errors = found
+        .iter()
+        .filter(|(_, d)| d.severity == Severity::Error)
+        .count();
+    let warnings = found.len() - errors;
+
+    if json {
+        let diagnostics: Vec<serde_json::Value> = found
+            .iter()
+            .map(|(path, d)| {
+                let mut v = serde_json::to_value(d).expect("diagnostic serializes");
+                v["path"] = serde_json::Value::String(path.display().to_string());
+                v
+            })
+            .collect();
+        let report = serde_json::json!({
+            "files": files,
+            "errors": errors,
+            "warnings": warnings,
+            "diagnostics": diagnostics,
+        });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("report serializes")
+        );
+    } else {
+        for (path, d) in found {
+            let mut level = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            }
+            .to_string();
+            if let Some(rule) = d.code {
+                level = format!("{}[{}]", level, rule);
+            }
+            println!(
+                "{}:{}:{}: {}: {}",
+                path.display(),
+                d.span.line,
+                d.span.col,
+                level,
+                d.message
+            );
+            if let Some(ref hint) = d.hint {
+                println!("  hint: {}", hint);
             }
         }
+        println!(
+            "Checked {} file(s): {} error(s), {} warning(s)",
+            files, errors, warnings
+        );
     }
 
-    for t in targets {
-        let content = std::fs::read_to_string(&t)?;
-        let rc = process(Some(&t), &content, check, write)?;
-        if rc == 1 {
-            code = 1;
+    (errors, warnings)
+}
+
+fn require_paths_exist(paths: &[PathBuf]) -> io::Result<()> {
+    match paths.iter().find(|p| !p.exists()) {
+        Some(p) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: no such file or directory", p.display()),
+        )),
+        None => Ok(()),
+    }
+}
+
+// qk lint: run the lint rules over every file; rule levels come from --config or a
+// qklint.json in the current directory. Several files are indexed together first, so an
+// event emitted in one script and handled in another is not reported as unhandled.
+fn run_lint(
+    paths: &[PathBuf],
+    config: Option<&Path>,
+    deny_warnings: bool,
+    json: bool,
+) -> io::Result<i32> {
+    require_paths_exist(paths)?;
+    let config = match config {
+        Some(path) => lint::LintConfig::load(path),
+        None => lint::LintConfig::find(Path::new(".")),
+    }
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let files = collect_qk_files(paths);
+    let events = (files.len() > 1).then(|| {
+        let mut index = WorkspaceIndex::new(typecheck::CheckOptions::default());
+        index.load_files(files.clone());
+        index.event_graph()
+    });
+    let mut found: Vec<(PathBuf, check::Diagnostic)> = Vec::new();
+    for path in &files {
+        let src = fs::read_to_string(path)?;
+        let diags = match &events {
+            Some(events) => lint::lint_source_in(&src, &config, events),
+            None => lint::lint_source(&src, &config),
+        };
+        for d in diags {
+            found.push((path.clone(), d));
         }
     }
-    Ok(code)
+    let (errors, warnings) = print_report(files.len(), &found, json);
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        Ok(1)
+    } else {
+        Ok(0)
+    }
 }
@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: MIT
+// Where a script's output goes. `print` hands each line to the interpreter's sink, and
+// `log.debug/info/warn/error` hand it a record with a level, a message and fields. The sink
+// writes lines to stdout and records to stderr unless the embedder sets another output: the
+// debug adapter sends lines to the debug console, snapshot tests capture them to compare
+// with a `.out` file, a `Host` passes them to its HostApi and `LogCrate` to the `log` crate.
+// Records below the interpreter's log level are dropped before they reach the output.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::host::Host;
+use crate::value::Map;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Level> {
+        Level::ALL.into_iter().find(|l| l.name() == name)
+    }
+}
+
+#[derive(Clone)]
+pub struct Record {
+    pub level: Level,
+    pub message: String,
+    pub fields: Map,
+}
+
+impl Record {
+    // `warn: low on gold gold=3 quest="q1"`
+    pub fn text(&self) -> String {
+        let mut out = format!("{}: {}", self.level.name(), self.message);
+        for (k, v) in &self.fields {
+            out.push_str(&format!(" {}={}", k, v));
+        }
+        out
+    }
+}
+
+pub trait Output {
+    // One printed line, without its newline
+    fn print(&mut self, line: &str);
+
+    // A log record at or above the interpreter's level; printed as a line unless overridden
+    fn log(&mut self, record: &Record) {
+        self.print(&record.text())
+    }
+}
+
+// The interpreter's output and the lowest level it logs
+pub struct Sink {
+    output: Box<dyn Output>,
+    pub level: Level,
+}
+
+impl Sink {
+    pub fn new(output: impl Output + 'static) -> Self {
+        Sink {
+            output: Box::new(output),
+            level: Level::Info,
+        }
+    }
+
+    pub fn set_output(&mut self, output: impl Output + 'static) {
+        self.output = Box::new(output);
+    }
+
+    pub fn print(&mut self, line: &str) {
+        self.output.print(line)
+    }
+
+    pub fn log(&mut self, record: &Record) {
+        if record.level >= self.level {
+            self.output.log(record)
+        }
+    }
+}
+
+// Shared by the interpreter and the `print` and `log` builtins
+pub type OutputRef = Rc<RefCell<Sink>>;
+
+pub struct Stdout;
+
+impl Output for Stdout {
+    fn print(&mut self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn log(&mut self, record: &Record) {
+        eprintln!("{}", record.text());
+    }
+}
+
+// Lines and records go to the host's HostApi
+impl Output for Host {
+    fn print(&mut self, line: &str) {
+        match &self.api {
+            Some(api) => api.print(line),
+            None => Stdout.print(line),
+        }
+    }
+
+    fn log(&mut self, record: &Record) {
+        match &self.api {
+            Some(api) => api.log(record),
+            None => Stdout.log(record),
+        }
+    }
+}
+
+// Lines and records go to the `log` crate, under the `questicle::print` and
+// `questicle::script` targets, for whatever logger the embedder installed
+pub struct LogCrate;
+
+impl Output for LogCrate {
+    fn print(&mut self, line: &str) {
+        log::info!(target: "questicle::print", "{}", line);
+    }
+
+    fn log(&mut self, record: &Record) {
+        let level = match record.level {
+            Level::Debug => log::Level::Debug,
+            Level::Info => log::Level::Info,
+            Level::Warn => log::Level::Warn,
+            Level::Error => log::Level::Error,
+        };
+        let mut text = record.message.clone();
+        for (k, v) in &record.fields {
+            text.push_str(&format!(" {}={}", k, v));
+        }
+        log::log!(target: "questicle::script", level, "{}", text);
+    }
+}
+
+// Collects the printed lines; clones share the text
+#[derive(Clone, Default)]
+pub struct Capture {
+    text: Rc<RefCell<String>>,
+}
+
+impl Capture {
+    // Everything printed so far, each line ending in a newline
+    pub fn text(&self) -> String {
+        self.text.borrow().clone()
+    }
+}
+
+impl Output for Capture {
+    fn print(&mut self, line: &str) {
+        let mut text = self.text.borrow_mut();
+        text.push_str(line);
+        text.push('\n');
+    }
+}
+
+// Any closure taking a line is a sink
+impl<F: FnMut(&str)> Output for F {
+    fn print(&mut self, line: &str) {
+        self(line)
+    }
+}
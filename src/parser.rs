@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use crate::ast::*;
 use crate::lexer::Lexer;
 use crate::token::{Token, TokenKind};
@@ -22,103 +24,205 @@ pub struct Parser {
     pos: usize,
 }
 
-type FnSig = (Vec<(String, Option<TypeExpr>)>, Option<TypeExpr>, Vec<Stmt>);
-
 impl Parser {
     pub fn new(src: &str) -> Self {
         let tokens = Lexer::new(src).lex();
         Self { tokens, pos: 0 }
     }
 
-    pub fn parse_program(mut self) -> Result<Program, ParseError> {
+    // Parse and resolve a script, so it runs as is in any number of interpreters
+    pub fn parse_program(self) -> Result<Program, ParseError> {
+        let mut program = self.parse_unresolved()?;
+        crate::resolve::resolve(&mut program);
+        Ok(program)
+    }
+
+    // Parse without resolving, for code run in a scope other than the global one (such as
+    // a debugger's watch expressions): its variables are then all looked up by name
+    pub fn parse_unresolved(mut self) -> Result<Program, ParseError> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
+            let stmt = if self.at_test_block() {
+                self.test_block()?
+            } else {
+                self.declaration()?
+            };
+            statements.push(stmt);
         }
         Ok(Program { statements })
     }
 
+    // Parse a declaration file (.qkd):
+    //   host spawn(payload: Type) -> Type;   (op names may also be string literals)
+    //   fn distance(a: Type, b: Type) -> Type;
+    pub fn parse_declarations(mut self) -> Result<Vec<Decl>, ParseError> {
+        let mut decls = Vec::new();
+        while !self.is_at_end() {
+            let start = self.here();
+            let kind = if self.matches(&[TokenKind::Fn]) {
+                DeclKind::Native
+            } else if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Identifier(s)) if s == "host")
+            {
+                self.advance();
+                DeclKind::HostOp
+            } else {
+                return Err(self.error_expected("`host` or `fn` declaration"));
+            };
+            let name = if kind == DeclKind::HostOp && self.matches_strings() {
+                self.take_string().unwrap()
+            } else {
+                self.consume_ident("declaration name")?
+            };
+            let params = self.param_list()?;
+            self.consume(TokenKind::Arrow, "->")?;
+            let ret = self.parse_type()?;
+            self.optional(TokenKind::Semicolon);
+            decls.push(Decl {
+                kind,
+                name,
+                params,
+                ret,
+                span: self.since(start),
+            });
+        }
+        Ok(decls)
+    }
+
+    // Parse a lone type annotation such as `record { hp: number }`
+    pub fn parse_type_annotation(mut self) -> Result<TypeExpr, ParseError> {
+        let ty = self.parse_type()?;
+        if !self.is_at_end() {
+            return Err(self.error_expected("end of type"));
+        }
+        Ok(ty)
+    }
+
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.here();
         if self.check(&TokenKind::Let) {
             self.advance();
-            return self.let_decl();
+            return self.let_decl(start);
         }
         if self.check(&TokenKind::Fn) {
             // Treat as declaration only if followed by an identifier
             if self.peek_next_is_identifier() {
                 self.advance();
-                return self.fn_decl();
+                return self.fn_decl(start);
             }
         }
         self.statement()
     }
 
-    fn let_decl(&mut self) -> Result<Stmt, ParseError> {
+    // `test` is only a keyword before a string at the top level, so it stays usable as a name
+    fn at_test_block(&self) -> bool {
+        matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Identifier(s)) if s == "test")
+            && matches!(
+                self.tokens.get(self.pos + 1).map(|t| &t.kind),
+                Some(TokenKind::String(_))
+            )
+    }
+
+    fn test_block(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.here();
+        self.advance();
+        let name = self.take_string().unwrap();
+        self.consume(TokenKind::LeftBrace, "{")?;
+        let body = self.block()?;
+        Ok(Stmt::new(StmtKind::Test { name, body }, self.since(start)))
+    }
+
+    fn let_decl(&mut self, start: Span) -> Result<Stmt, ParseError> {
+        let name_span = self.here();
         let name = self.consume_ident("identifier")?;
         // Require type annotation: ": Type"
         if !self.check(&TokenKind::Colon) {
             return Err(self.error_expected(": type annotation"));
         }
         self.advance();
-        let ty = Some(self.parse_type()?);
+        let (ty, ty_span) = self.annotation()?;
         self.consume(TokenKind::Assign, "=")?;
         let init = self.expression()?;
         self.optional(TokenKind::Semicolon);
-        Ok(Stmt::Let { name, ty, init })
-    }
-
-    fn fn_decl(&mut self) -> Result<Stmt, ParseError> {
+        Ok(Stmt::new(
+            StmtKind::Let {
+                name,
+                name_span,
+                ty: Some(ty),
+                ty_span,
+                init,
+                slot: Slot::default(),
+            },
+            self.since(start),
+        ))
+    }
+
+    fn fn_decl(&mut self, start: Span) -> Result<Stmt, ParseError> {
         // function statement as: fn name(params){ body }
+        let name_span = self.here();
         let name = self.consume_ident("function name")?;
-        let (params, ret, body) = self.function_literal()?;
+        let signature = self.here();
+        let decl = self.function_literal()?;
         // Optional: infer a function type if all param types and ret are present
-        let ty = if params.iter().all(|(_, t)| t.is_some()) && ret.is_some() {
-            let args: Vec<TypeExpr> = params.iter().map(|(_, t)| t.clone().unwrap()).collect();
-            Some(TypeExpr::Func(args, Box::new(ret.clone().unwrap())))
+        let ty = if decl.params.iter().all(|p| p.ty.is_some()) && decl.ret.is_some() {
+            let args: Vec<TypeExpr> = decl.params.iter().map(|p| p.ty.clone().unwrap()).collect();
+            Some(TypeExpr::Func(args, Box::new(decl.ret.clone().unwrap())))
         } else {
             None
         };
-        Ok(Stmt::Let {
-            name,
-            ty,
-            init: Expr::Fn { params, ret, body },
-        })
+        let ty_span = signature.to(decl.ret_span);
+        let span = self.since(start);
+        Ok(Stmt::new(
+            StmtKind::Let {
+                name,
+                name_span,
+                ty,
+                ty_span,
+                init: Expr::new(ExprKind::Fn(Arc::new(decl)), span),
+                slot: Slot::default(),
+            },
+            span,
+        ))
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.here();
         if self.matches(&[TokenKind::If]) {
-            return self.if_stmt();
+            return self.if_stmt(start);
         }
         if self.matches(&[TokenKind::While]) {
-            return self.while_stmt();
+            return self.while_stmt(start);
         }
         if self.matches(&[TokenKind::For]) {
-            return self.for_stmt();
+            return self.for_stmt(start);
         }
         if self.check(&TokenKind::LeftBrace) && !self.looks_like_map_literal() {
             self.advance();
-            return Ok(Stmt::Block(self.block()?));
+            let body = self.block()?;
+            return Ok(Stmt::new(
+                StmtKind::Block(body, Frame::default()),
+                self.since(start),
+            ));
         }
         if self.matches(&[TokenKind::Return]) {
             if self.check(&TokenKind::Semicolon) {
                 self.advance();
-                return Ok(Stmt::Return(None));
+                return Ok(Stmt::new(StmtKind::Return(None), self.since(start)));
             }
             let v = self.expression()?;
             self.optional(TokenKind::Semicolon);
-            return Ok(Stmt::Return(Some(v)));
+            return Ok(Stmt::new(StmtKind::Return(Some(v)), self.since(start)));
         }
         if self.matches(&[TokenKind::Break]) {
             self.optional(TokenKind::Semicolon);
-            return Ok(Stmt::Break);
+            return Ok(Stmt::new(StmtKind::Break, self.since(start)));
         }
         if self.matches(&[TokenKind::Continue]) {
             self.optional(TokenKind::Semicolon);
-            return Ok(Stmt::Continue);
+            return Ok(Stmt::new(StmtKind::Continue, self.since(start)));
         }
         let expr = self.expression()?;
         self.optional(TokenKind::Semicolon);
-        Ok(Stmt::Expr(expr))
+        Ok(Stmt::new(StmtKind::Expr(expr), self.since(start)))
     }
 
     fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
@@ -146,7 +250,7 @@ impl Parser {
         }
     }
 
-    fn if_stmt(&mut self) -> Result<Stmt, ParseError> {
+    fn if_stmt(&mut self, start: Span) -> Result<Stmt, ParseError> {
         self.consume(TokenKind::LeftParen, "(")?;
         let cond = self.expression()?;
         self.consume(TokenKind::RightParen, ")")?;
@@ -156,29 +260,42 @@ impl Parser {
         } else {
             None
         };
-        Ok(Stmt::If {
-            cond,
-            then_branch,
-            else_branch,
-        })
+        Ok(Stmt::new(
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            },
+            self.since(start),
+        ))
     }
 
-    fn while_stmt(&mut self) -> Result<Stmt, ParseError> {
+    fn while_stmt(&mut self, start: Span) -> Result<Stmt, ParseError> {
         self.consume(TokenKind::LeftParen, "(")?;
         let cond = self.expression()?;
         self.consume(TokenKind::RightParen, ")")?;
         let body = Box::new(self.statement()?);
-        Ok(Stmt::While { cond, body })
+        Ok(Stmt::new(StmtKind::While { cond, body }, self.since(start)))
     }
 
-    fn for_stmt(&mut self) -> Result<Stmt, ParseError> {
+    fn for_stmt(&mut self, start: Span) -> Result<Stmt, ParseError> {
         self.consume(TokenKind::LeftParen, "(")?;
+        let name_span = self.here();
         let name = self.consume_ident("loop variable")?;
         self.consume(TokenKind::In, "in")?;
         let iter = self.expression()?;
         self.consume(TokenKind::RightParen, ")")?;
         let body = Box::new(self.statement()?);
-        Ok(Stmt::For { name, iter, body })
+        Ok(Stmt::new(
+            StmtKind::For {
+                name,
+                name_span,
+                iter,
+                body,
+                slots: SlotNames::default(),
+            },
+            self.since(start),
+        ))
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
@@ -189,11 +306,16 @@ impl Parser {
         let expr = self.or()?;
         if self.matches(&[TokenKind::Assign]) {
             let value = self.assignment()?;
-            if let Expr::Var(name) = expr {
-                return Ok(Expr::Assign {
-                    name,
-                    value: Box::new(value),
-                });
+            if let ExprKind::Var(name, _) = expr.kind {
+                let span = expr.span.to(value.span);
+                return Ok(Expr::new(
+                    ExprKind::Assign {
+                        name,
+                        slot: Slot::default(),
+                        value: Box::new(value),
+                    },
+                    span,
+                ));
             }
             return Err(self.error_expected("assignable expression"));
         }
@@ -204,11 +326,7 @@ impl Parser {
         let mut expr = self.and()?;
         while self.matches(&[TokenKind::OrOr]) {
             let right = self.and()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op: BinOp::Or,
-                right: Box::new(right),
-            };
+            expr = binary(expr, BinOp::Or, right);
         }
         Ok(expr)
     }
@@ -217,11 +335,7 @@ impl Parser {
         let mut expr = self.equality()?;
         while self.matches(&[TokenKind::AndAnd]) {
             let right = self.equality()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op: BinOp::And,
-                right: Box::new(right),
-            };
+            expr = binary(expr, BinOp::And, right);
         }
         Ok(expr)
     }
@@ -235,11 +349,7 @@ impl Parser {
                 BinOp::Ne
             };
             let right = self.comparison()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
+            expr = binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -260,11 +370,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             let right = self.term()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
+            expr = binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -278,11 +384,7 @@ impl Parser {
                 BinOp::Sub
             };
             let right = self.factor()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
+            expr = binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -297,29 +399,32 @@ impl Parser {
                 _ => unreachable!(),
             };
             let right = self.unary()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
+            expr = binary(expr, op, right);
         }
         Ok(expr)
     }
 
     fn unary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.here();
         if self.matches(&[TokenKind::Bang]) {
             let expr = self.unary()?;
-            return Ok(Expr::Unary {
-                op: UnOp::Not,
-                expr: Box::new(expr),
-            });
+            return Ok(Expr::new(
+                ExprKind::Unary {
+                    op: UnOp::Not,
+                    expr: Box::new(expr),
+                },
+                self.since(start),
+            ));
         }
         if self.matches(&[TokenKind::Minus]) {
             let expr = self.unary()?;
-            return Ok(Expr::Unary {
-                op: UnOp::Neg,
-                expr: Box::new(expr),
-            });
+            return Ok(Expr::new(
+                ExprKind::Unary {
+                    op: UnOp::Neg,
+                    expr: Box::new(expr),
+                },
+                self.since(start),
+            ));
         }
         self.call()
     }
@@ -327,6 +432,7 @@ impl Parser {
     fn call(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.primary()?;
         loop {
+            let start = expr.span;
             if self.matches(&[TokenKind::LeftParen]) {
                 let mut args = Vec::new();
                 if !self.check(&TokenKind::RightParen) {
@@ -338,23 +444,32 @@ impl Parser {
                     }
                 }
                 self.consume(TokenKind::RightParen, ")")?;
-                expr = Expr::Call {
-                    callee: Box::new(expr),
-                    args,
-                };
+                expr = Expr::new(
+                    ExprKind::Call {
+                        callee: Box::new(expr),
+                        args,
+                    },
+                    self.since(start),
+                );
             } else if self.matches(&[TokenKind::LeftBracket]) {
                 let idx = self.expression()?;
                 self.consume(TokenKind::RightBracket, "]")?;
-                expr = Expr::Index {
-                    target: Box::new(expr),
-                    index: Box::new(idx),
-                };
+                expr = Expr::new(
+                    ExprKind::Index {
+                        target: Box::new(expr),
+                        index: Box::new(idx),
+                    },
+                    self.since(start),
+                );
             } else if self.matches(&[TokenKind::Dot]) {
                 let name = self.consume_ident("field name")?;
-                expr = Expr::Field {
-                    target: Box::new(expr),
-                    name,
-                };
+                expr = Expr::new(
+                    ExprKind::Field {
+                        target: Box::new(expr),
+                        name,
+                    },
+                    self.since(start),
+                );
             } else {
                 break;
             }
@@ -363,20 +478,26 @@ impl Parser {
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.here();
+        let kind = self.primary_kind()?;
+        Ok(Expr::new(kind, self.since(start)))
+    }
+
+    fn primary_kind(&mut self) -> Result<ExprKind, ParseError> {
         if self.matches(&[TokenKind::True]) {
-            return Ok(Expr::Literal(Lit::Bool(true)));
+            return Ok(ExprKind::Literal(Lit::Bool(true)));
         }
         if self.matches(&[TokenKind::False]) {
-            return Ok(Expr::Literal(Lit::Bool(false)));
+            return Ok(ExprKind::Literal(Lit::Bool(false)));
         }
         if self.matches(&[TokenKind::Null]) {
-            return Ok(Expr::Literal(Lit::Null));
+            return Ok(ExprKind::Literal(Lit::Null));
         }
         if self.matches_numbers() {
-            return Ok(Expr::Literal(Lit::Number(self.take_number().unwrap())));
+            return Ok(ExprKind::Literal(Lit::Number(self.take_number().unwrap())));
         }
         if self.matches_strings() {
-            return Ok(Expr::Literal(Lit::String(self.take_string().unwrap())));
+            return Ok(ExprKind::Literal(Lit::String(self.take_string().unwrap())));
         }
         if let Some(Token {
             kind: TokenKind::Identifier(_),
@@ -384,12 +505,12 @@ impl Parser {
         }) = self.peek()
         {
             let name = self.consume_ident("identifier")?;
-            return Ok(Expr::Var(name));
+            return Ok(ExprKind::Var(name, Slot::default()));
         }
         if self.matches(&[TokenKind::LeftParen]) {
             let e = self.expression()?;
             self.consume(TokenKind::RightParen, ")")?;
-            return Ok(e);
+            return Ok(e.kind);
         }
         if self.matches(&[TokenKind::LeftBracket]) {
             let mut items = Vec::new();
@@ -402,7 +523,7 @@ impl Parser {
                 }
             }
             self.consume(TokenKind::RightBracket, "]")?;
-            return Ok(Expr::List(items));
+            return Ok(ExprKind::List(items));
         }
         if self.matches(&[TokenKind::LeftBrace]) {
             let mut props = Vec::new();
@@ -418,44 +539,70 @@ impl Parser {
                 }
             }
             self.consume(TokenKind::RightBrace, "}")?;
-            return Ok(Expr::Map(props));
+            return Ok(ExprKind::Map(props));
         }
         if self.matches(&[TokenKind::Fn]) {
-            let (params, ret, body) = self.function_literal()?;
-            return Ok(Expr::Fn { params, ret, body });
+            return Ok(ExprKind::Fn(Arc::new(self.function_literal()?)));
         }
         Err(self.error_unexpected())
     }
 
-    fn function_literal(&mut self) -> Result<FnSig, ParseError> {
+    fn function_literal(&mut self) -> Result<FnLiteral, ParseError> {
+        let params = self.param_list()?;
+        // Optional return type: -> Type
+        let (ret, ret_span) = if self.check(&TokenKind::Arrow) {
+            self.advance();
+            let (ty, span) = self.annotation()?;
+            (Some(ty), span)
+        } else {
+            (None, Span::default())
+        };
+        self.consume(TokenKind::LeftBrace, "{")?;
+        let body = self.block()?;
+        Ok(FnLiteral {
+            params,
+            ret,
+            ret_span,
+            body,
+            slots: SlotNames::default(),
+        })
+    }
+
+    // Parenthesized parameter list with optional annotations: (a: number, b)
+    fn param_list(&mut self) -> Result<Vec<Param>, ParseError> {
         self.consume(TokenKind::LeftParen, "(")?;
-        let mut params: Vec<(String, Option<TypeExpr>)> = Vec::new();
+        let mut params: Vec<Param> = Vec::new();
         if !self.check(&TokenKind::RightParen) {
             loop {
-                let pname = self.consume_ident("parameter name")?;
-                let pty = if self.check(&TokenKind::Colon) {
+                let span = self.here();
+                let name = self.consume_ident("parameter name")?;
+                let (ty, ty_span) = if self.check(&TokenKind::Colon) {
                     self.advance();
-                    Some(self.parse_type()?)
+                    let (ty, ty_span) = self.annotation()?;
+                    (Some(ty), ty_span)
                 } else {
-                    None
+                    (None, Span::default())
                 };
-                params.push((pname, pty));
+                params.push(Param {
+                    name,
+                    ty,
+                    span,
+                    ty_span,
+                });
                 if !self.matches(&[TokenKind::Comma]) {
                     break;
                 }
             }
         }
         self.consume(TokenKind::RightParen, ")")?;
-        // Optional return type: -> Type
-        let ret = if self.check(&TokenKind::Arrow) {
-            self.advance();
-            Some(self.parse_type()?)
-        } else {
-            None
-        };
-        self.consume(TokenKind::LeftBrace, "{")?;
-        let body = self.block()?;
-        Ok((params, ret, body))
+        Ok(params)
+    }
+
+    // A type annotation and its span
+    fn annotation(&mut self) -> Result<(TypeExpr, Span), ParseError> {
+        let start = self.here();
+        let ty = self.parse_type()?;
+        Ok((ty, self.since(start)))
     }
 
     // Parse a type annotation.
@@ -575,6 +722,20 @@ impl Parser {
     }
 
     // Utilities
+    // Span of the next token, or of the last one at end of input
+    fn here(&self) -> Span {
+        match self.peek().or_else(|| self.tokens.last()) {
+            Some(t) => Span::new(t.line, t.col, t.end_line, t.end_col),
+            None => Span::new(1, 1, 1, 1),
+        }
+    }
+    // Span from `start` to the end of the last consumed token
+    fn since(&self, start: Span) -> Span {
+        match self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(t) => Span::new(start.line, start.col, t.end_line, t.end_col),
+            None => start,
+        }
+    }
     fn is_at_end(&self) -> bool {
         self.pos >= self.tokens.len()
     }
@@ -708,6 +869,18 @@ impl Parser {
     }
 }
 
+fn binary(left: Expr, op: BinOp, right: Expr) -> Expr {
+    let span = left.span.to(right.span);
+    Expr::new(
+        ExprKind::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        },
+        span,
+    )
+}
+
 fn kind_eq(a: &TokenKind, b: &TokenKind) -> bool {
     use TokenKind::*;
     match (a, b) {
@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MIT
+// A profiler for script functions, built on interpreter hooks. For each function it counts
+// calls, inclusive time (with the functions it calls) and exclusive time (without), and its
+// collection clones: the lists and maps it cloned out of variables and collections. Time at
+// the top level of the script is reported as `<script>`. Reports are a table sorted by
+// exclusive time, or folded stacks (`<script>;quest;reward 1520`, in microseconds of
+// exclusive time) for flamegraph tools.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::ast::Span;
+use crate::eval::{Interpreter, RuntimeError};
+use crate::hooks::{Flow, Hooks};
+use crate::value::{collection_clones, Value};
+
+const SCRIPT: &str = "<script>";
+
+// Clones share the measurements, so the profiler can be installed and read back later
+#[derive(Clone)]
+pub struct Profiler {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    root: Open,
+    // Functions being run, innermost last
+    stack: Vec<Open>,
+    stats: HashMap<String, Totals>,
+    // Exclusive time per call stack, outermost first
+    stacks: HashMap<Vec<String>, Duration>,
+}
+
+struct Open {
+    name: String,
+    started: Instant,
+    clones_at_start: u64,
+    // Spent in the functions it called
+    child_time: Duration,
+    child_clones: u64,
+}
+
+impl Open {
+    fn new(name: &str) -> Self {
+        Open {
+            name: name.to_string(),
+            started: Instant::now(),
+            clones_at_start: collection_clones(),
+            child_time: Duration::ZERO,
+            child_clones: 0,
+        }
+    }
+
+    // Time and clones since the frame opened
+    fn measure(&self) -> (Duration, u64) {
+        (
+            self.started.elapsed(),
+            collection_clones() - self.clones_at_start,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    calls: u64,
+    inclusive: Duration,
+    exclusive: Duration,
+    clones: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+    // Lists and maps cloned by the function itself, not by the functions it called
+    pub collection_clones: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    // Time at the top level counts from here
+    pub fn new() -> Self {
+        Profiler {
+            state: Rc::new(RefCell::new(State {
+                root: Open::new(SCRIPT),
+                stack: Vec::new(),
+                stats: HashMap::new(),
+                stacks: HashMap::new(),
+            })),
+        }
+    }
+
+    // Per-function totals, most exclusive time first; `<script>` is the top level so far
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let state = self.state.borrow();
+        let (elapsed, clones) = state.root.measure();
+        let mut out: Vec<FunctionProfile> = state
+            .stats
+            .iter()
+            .map(|(name, t)| FunctionProfile {
+                name: name.clone(),
+                calls: t.calls,
+                inclusive: t.inclusive,
+                exclusive: t.exclusive,
+                collection_clones: t.clones,
+            })
+            .collect();
+        out.push(FunctionProfile {
+            name: SCRIPT.into(),
+            calls: 1,
+            inclusive: elapsed,
+            exclusive: elapsed.saturating_sub(state.root.child_time),
+            collection_clones: clones - state.root.child_clones,
+        });
+        out.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then(a.name.cmp(&b.name)));
+        out
+    }
+
+    pub fn to_table(&self) -> String {
+        let functions = self.functions();
+        let width = functions
+            .iter()
+            .map(|f| f.name.len())
+            .chain([8])
+            .max()
+            .unwrap_or(8);
+        let ms = |d: Duration| format!("{:.3}", d.as_secs_f64() * 1000.0);
+        let mut out = format!(
+            "{:<width$}  {:>8}  {:>12}  {:>12}  {:>17}\n",
+            "Function", "Calls", "Incl ms", "Excl ms", "Collection clones"
+        );
+        for f in &functions {
+            out.push_str(&format!(
+                "{:<width$}  {:>8}  {:>12}  {:>12}  {:>17}\n",
+                f.name,
+                f.calls,
+                ms(f.inclusive),
+                ms(f.exclusive),
+                f.collection_clones
+            ));
+        }
+        out
+    }
+
+    // One line per call stack: frames joined by `;`, then exclusive microseconds
+    pub fn to_folded(&self) -> String {
+        let state = self.state.borrow();
+        let (elapsed, _) = state.root.measure();
+        let mut lines: Vec<(String, u128)> = state
+            .stacks
+            .iter()
+            .map(|(stack, time)| {
+                let frames: Vec<&str> = std::iter::once(SCRIPT)
+                    .chain(stack.iter().map(|s| s.as_str()))
+                    .collect();
+                (frames.join(";"), time.as_micros())
+            })
+            .collect();
+        let top = elapsed.saturating_sub(state.root.child_time);
+        lines.push((SCRIPT.into(), top.as_micros()));
+        lines.sort();
+        lines
+            .into_iter()
+            .map(|(stack, us)| format!("{} {}\n", stack, us))
+            .collect()
+    }
+
+    fn close(&self) {
+        let mut state = self.state.borrow_mut();
+        let Some(frame) = state.stack.pop() else {
+            return;
+        };
+        let (elapsed, clones) = frame.measure();
+        let own_time = elapsed.saturating_sub(frame.child_time);
+        let own_clones = clones - frame.child_clones;
+        // A recursive call's time is already inside the outer call's
+        let recursive = state.stack.iter().any(|f| f.name == frame.name);
+        let mut path: Vec<String> = state.stack.iter().map(|f| f.name.clone()).collect();
+        path.push(frame.name.clone());
+        *state.stacks.entry(path).or_default() += own_time;
+        let totals = state.stats.entry(frame.name).or_default();
+        totals.calls += 1;
+        totals.exclusive += own_time;
+        totals.clones += own_clones;
+        if !recursive {
+            totals.inclusive += elapsed;
+        }
+        let parent = match state.stack.last_mut() {
+            Some(p) => p,
+            None => &mut state.root,
+        };
+        parent.child_time += elapsed;
+        parent.child_clones += clones;
+    }
+}
+
+impl Hooks for Profiler {
+    fn enter(&mut self, _: &mut Interpreter, name: &str, _: Span, _: &[Value]) -> Flow {
+        self.state.borrow_mut().stack.push(Open::new(name));
+        Flow::Continue
+    }
+
+    fn exit(&mut self, _: &mut Interpreter, _: &str, _: &Value) -> Flow {
+        self.close();
+        Flow::Continue
+    }
+
+    // Functions left by the error end here
+    fn error(&mut self, _: &mut Interpreter, _: &RuntimeError) {
+        while !self.state.borrow().stack.is_empty() {
+            self.close();
+        }
+    }
+}
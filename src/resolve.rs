@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: MIT
+// The resolver: a pass over a program, before it runs, that works out where each variable
+// lives so the interpreter can find it by position rather than by name. Every block, `for`
+// iteration and function call gets a frame at runtime; the resolver numbers the names each
+// of those scopes declares and marks each use with how many scopes out its binding is and
+// at which slot (see ast::Slot). Names no local scope declares are globals.
+//
+// A use is only given a slot when the binding is certain to exist by the time the use runs:
+// its `let` ran earlier in the same scope, or (for uses inside a function) before the
+// function was created. Uses that may see a binding made later, such as a function calling
+// itself through a local `let`, or a `let` that runs only under an `if`, stay `Dynamic` and
+// are looked up by name, exactly as before resolution.
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::ast::*;
+
+pub fn resolve(program: &mut Program) {
+    let mut r = Resolver { scopes: Vec::new() };
+    for s in &mut program.statements {
+        r.stmt(s);
+    }
+}
+
+struct Scope {
+    // Slot of each name the scope declares, in order of first declaration
+    slots: HashMap<String, usize>,
+    // The same, by slot
+    names: SlotNames,
+    // Names bound for certain at this point
+    bound: HashSet<String>,
+}
+
+struct Resolver {
+    // Local scopes, innermost last; the global scope is not among them
+    scopes: Vec<Scope>,
+}
+
+impl Resolver {
+    // A scope declaring `bound` (already bound, in order) and the lets of `body`
+    fn push(&mut self, bound: &[&str], body: &[Stmt]) {
+        let mut scope = Scope {
+            slots: HashMap::new(),
+            names: SlotNames::default(),
+            bound: HashSet::new(),
+        };
+        // Slot i holds bound name i; a repeated name is found at its last slot, as the last
+        // binding of a name wins
+        for (i, name) in bound.iter().enumerate() {
+            scope.slots.insert(name.to_string(), i);
+            scope.bound.insert(name.to_string());
+        }
+        let mut next = bound.len();
+        for s in body {
+            declared(s, &mut scope.slots, &mut next);
+        }
+        let mut names: Vec<Arc<str>> = vec![Arc::from(""); next];
+        for (i, name) in bound.iter().enumerate() {
+            names[i] = (*name).into();
+        }
+        for (name, &i) in scope.slots.iter().filter(|(_, &i)| i >= bound.len()) {
+            names[i] = name.as_str().into();
+        }
+        scope.names = names.into();
+        self.scopes.push(scope);
+    }
+
+    // Close the innermost scope, giving the names of its slots
+    fn pop(&mut self) -> SlotNames {
+        self.scopes.pop().map(|s| s.names).unwrap_or_default()
+    }
+
+    fn lookup(&self, name: &str) -> Slot {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(&index) = scope.slots.get(name) {
+                if scope.bound.contains(name) {
+                    return Slot::Local { depth, index };
+                }
+                return Slot::Dynamic;
+            }
+        }
+        Slot::Global
+    }
+
+    // A block that declares nothing has no frame of its own
+    fn block(&mut self, body: &mut [Stmt]) -> Frame {
+        if !declares(body) {
+            body.iter_mut().for_each(|s| self.stmt(s));
+            return Frame::Enclosing;
+        }
+        self.push(&[], body);
+        for s in body.iter_mut() {
+            self.stmt(s);
+        }
+        Frame::Own(self.pop())
+    }
+
+    fn stmt(&mut self, s: &mut Stmt) {
+        match &mut s.kind {
+            StmtKind::Let {
+                name, init, slot, ..
+            } => {
+                self.expr(init);
+                *slot = match self.scopes.last_mut() {
+                    None => Slot::Global,
+                    Some(scope) => {
+                        scope.bound.insert(name.clone());
+                        Slot::Local {
+                            depth: 0,
+                            index: scope.slots[name.as_str()],
+                        }
+                    }
+                };
+            }
+            StmtKind::Expr(e) | StmtKind::Return(Some(e)) => self.expr(e),
+            StmtKind::Block(body, frame) => *frame = self.block(body),
+            StmtKind::Test { body, .. } => {
+                self.block(body);
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.branch(then_branch);
+                if let Some(e) = else_branch {
+                    self.branch(e);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.branch(body);
+            }
+            StmtKind::For {
+                name,
+                iter,
+                body,
+                slots,
+                ..
+            } => {
+                self.expr(iter);
+                // Each iteration binds the loop variable in a scope of its own
+                self.push(&[name.as_str()], std::slice::from_ref(&**body));
+                self.stmt(body);
+                *slots = self.pop();
+            }
+            StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    // A statement that may not run: a `let` in it binds only maybe
+    fn branch(&mut self, s: &mut Stmt) {
+        let before = self.scopes.last().map(|scope| scope.bound.clone());
+        self.stmt(s);
+        if let (Some(scope), Some(before)) = (self.scopes.last_mut(), before) {
+            scope.bound = before;
+        }
+    }
+
+    fn expr(&mut self, e: &mut Expr) {
+        match &mut e.kind {
+            ExprKind::Var(name, slot) => *slot = self.lookup(name),
+            ExprKind::Assign { name, slot, value } => {
+                self.expr(value);
+                *slot = self.lookup(name);
+            }
+            ExprKind::Fn(literal) => {
+                // Unshared while the parser resolves, so this does not copy
+                let FnLiteral {
+                    params,
+                    body,
+                    slots,
+                    ..
+                } = Arc::make_mut(literal);
+                let names: Vec<&str> = params.iter().map(|p| p.name.as_str()).collect();
+                self.push(&names, body);
+                for s in body.iter_mut() {
+                    self.stmt(s);
+                }
+                *slots = self.pop();
+            }
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                args.iter_mut().for_each(|a| self.expr(a));
+            }
+            ExprKind::Unary { expr: e, .. } | ExprKind::Field { target: e, .. } => self.expr(e),
+            ExprKind::Binary { left, right, .. }
+            | ExprKind::Index {
+                target: left,
+                index: right,
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::List(items) => items.iter_mut().for_each(|i| self.expr(i)),
+            ExprKind::Map(entries) => entries.iter_mut().for_each(|(_, v)| self.expr(v)),
+            ExprKind::Literal(_) => {}
+        }
+    }
+}
+
+// The frame a block running `body` needs, with its slots numbered as the resolver numbers
+// them
+pub fn frame(body: &[Stmt]) -> Frame {
+    if !declares(body) {
+        return Frame::Enclosing;
+    }
+    let mut r = Resolver { scopes: Vec::new() };
+    r.push(&[], body);
+    Frame::Own(r.pop())
+}
+
+// Whether running `body` as a block binds anything in the block's scope
+pub fn declares(body: &[Stmt]) -> bool {
+    fn binds(s: &Stmt) -> bool {
+        match &s.kind {
+            StmtKind::Let { .. } => true,
+            StmtKind::If {
+                then_branch,
+                else_branch,
+                ..
+            } => binds(then_branch) || else_branch.as_deref().is_some_and(binds),
+            StmtKind::While { body, .. } => binds(body),
+            _ => false,
+        }
+    }
+    body.iter().any(binds)
+}
+
+// Number the lets that bind into the scope `s` runs in: its own, and those of an `if` or
+// `while` body that is not a block
+fn declared(s: &Stmt, slots: &mut HashMap<String, usize>, next: &mut usize) {
+    match &s.kind {
+        StmtKind::Let { name, .. } if !slots.contains_key(name) => {
+            slots.insert(name.clone(), *next);
+            *next += 1;
+        }
+        StmtKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            declared(then_branch, slots, next);
+            if let Some(e) = else_branch {
+                declared(e, slots, next);
+            }
+        }
+        StmtKind::While { body, .. } => declared(body, slots, next),
+        _ => {}
+    }
+}
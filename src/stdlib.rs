@@ -3,26 +3,27 @@ use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::host::{Host, HostApi};
-use crate::value::{EnvRef, Function, Value};
+use crate::output::{Level, OutputRef, Record};
+use crate::value::{EnvRef, Function, List, Map, Value};
 
-pub fn install_std(env: &EnvRef, host: Host) {
+// Names defined by install_std
+pub const BUILTINS: &[&str] = &[
+    "print", "clock", "random", "len", "keys", "push", "pop", "host", "on", "emit", "log", "set",
+];
+
+pub fn install_std(env: &EnvRef, host: Host, output: OutputRef) {
     let mut e = env.borrow_mut();
     e.define(
         "print".into(),
-        native("print", |args, _| {
-            for (i, a) in args.iter().enumerate() {
-                if i > 0 {
-                    print!(" ");
-                }
-                match a {
-                    Value::String(s) => print!("{}", s),
-                    v => print!("{}", v),
-                }
+        native("print", {
+            let output = output.clone();
+            move |args, _| {
+                output.borrow_mut().print(&print_text(&args));
+                Ok(Value::Null)
             }
-            println!();
-            Ok(Value::Null)
         }),
     );
+    e.define("log".into(), log_builtins(&output));
     e.define(
         "clock".into(),
         native("clock", |_args, _| {
@@ -58,7 +59,7 @@ pub fn install_std(env: &EnvRef, host: Host) {
             if let Some(Value::Map(m)) = args.first() {
                 Ok(Value::List(m.keys().cloned().map(Value::String).collect()))
             } else {
-                Ok(Value::List(vec![]))
+                Ok(Value::List(List::new()))
             }
         }),
     );
@@ -67,7 +68,7 @@ pub fn install_std(env: &EnvRef, host: Host) {
         native("push", |args, _| match (args.first(), args.get(1)) {
             (Some(Value::List(list)), Some(val)) => {
                 let mut new = list.clone();
-                new.push(val.clone());
+                new.push_back(val.clone());
                 Ok(Value::List(new))
             }
             _ => Err("push expects (list, value)".into()),
@@ -77,13 +78,28 @@ pub fn install_std(env: &EnvRef, host: Host) {
         "pop".into(),
         native("pop", |args, _| {
             if let Some(Value::List(list)) = args.first() {
-                let mut v = list.clone();
-                Ok(v.pop().unwrap_or(Value::Null))
+                Ok(list.last().cloned().unwrap_or(Value::Null))
             } else {
                 Ok(Value::Null)
             }
         }),
     );
+    e.define(
+        "set".into(),
+        native("set", |args, _| {
+            match (args.first(), args.get(1), args.get(2)) {
+                (Some(Value::Map(m)), Some(Value::String(k)), Some(val)) => {
+                    Ok(Value::Map(m.update(k.clone(), val.clone())))
+                }
+                (Some(Value::List(list)), Some(Value::Number(n)), Some(val))
+                    if *n >= 0.0 && (*n as usize) < list.len() =>
+                {
+                    Ok(Value::List(list.update(*n as usize, val.clone())))
+                }
+                _ => Err("set expects (map, key, value) or (list, index, value)".into()),
+            }
+        }),
+    );
 
     let host_clone = host.clone();
     e.define(
@@ -129,7 +145,52 @@ pub fn install_std(env: &EnvRef, host: Host) {
     );
 }
 
-fn native(name: &str, f: impl Fn(Vec<Value>, EnvRef) -> Result<Value, String> + 'static) -> Value {
+// `log.debug(message, fields)` and the other levels; fields is an optional map
+fn log_builtins(output: &OutputRef) -> Value {
+    let mut levels = Map::new();
+    for level in Level::ALL {
+        let output = output.clone();
+        let usage = format!("log.{}(message, fields)", level.name());
+        let f = native(level.name(), move |args, _| {
+            let message = match args.first() {
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+                None => return Err(usage.clone()),
+            };
+            let fields = match args.get(1) {
+                Some(Value::Map(m)) => m.clone(),
+                None | Some(Value::Null) => Map::new(),
+                Some(_) => return Err(usage.clone()),
+            };
+            output.borrow_mut().log(&Record {
+                level,
+                message,
+                fields,
+            });
+            Ok(Value::Null)
+        });
+        levels.insert(level.name().to_string(), f);
+    }
+    Value::Map(levels)
+}
+
+// The line print writes for its arguments: strings unquoted, separated by spaces
+pub fn print_text(args: &[Value]) -> String {
+    let parts: Vec<String> = args
+        .iter()
+        .map(|a| match a {
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        })
+        .collect();
+    parts.join(" ")
+}
+
+// Wrap a Rust closure as a callable value; engines use this to provide declared natives
+pub fn native(
+    name: &str,
+    f: impl Fn(Vec<Value>, EnvRef) -> Result<Value, String> + 'static,
+) -> Value {
     Value::Function(Rc::new(Function::Native {
         name: name.to_string(),
         fun: Rc::new(f),
@@ -0,0 +1,432 @@
+// SPDX-License-Identifier: MIT
+// Scope analysis over the spanned AST for editor features: every binding and every use of
+// a name, resolved the way the interpreter resolves it (blocks, loop bodies and function
+// bodies open scopes; a `let` of a function is visible inside its own body; a function body
+// may use names declared later in an enclosing scope, since it only runs once called).
+use crate::ast::*;
+use crate::lexer::KEYWORDS;
+use crate::stdlib::BUILTINS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Parameter,
+    LoopVariable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // The name in the declaration
+    pub span: Span,
+    pub scope: usize,
+    pub ty: Option<TypeExpr>,
+    // Parameter names, for functions
+    pub params: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    Decl,
+    Read,
+    Write,
+}
+
+// One occurrence of a name; `symbol` is None for builtins, host natives and undefined names
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub kind: RefKind,
+    pub scope: usize,
+    pub symbol: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub parent: Option<usize>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    pub scopes: Vec<Scope>,
+    pub symbols: Vec<Symbol>,
+    // In source order
+    pub refs: Vec<Reference>,
+}
+
+pub fn analyze(program: &Program) -> SymbolTable {
+    let mut a = Analyzer::default();
+    a.push_scope(Span::new(1, 1, usize::MAX, usize::MAX));
+    for s in &program.statements {
+        a.stmt(s);
+    }
+    a.pop_scope();
+    a.table
+        .refs
+        .sort_by_key(|r| (r.span.line, r.span.col, r.kind != RefKind::Decl));
+    a.table
+}
+
+impl SymbolTable {
+    // The reference under a 1-based position; a cursor just past the end of a name counts
+    pub fn reference_at(&self, line: usize, col: usize) -> Option<&Reference> {
+        let on = |r: &&Reference| r.span.line == line && r.span.col <= col && col < r.span.end_col;
+        let after = |r: &&Reference| r.span.line == line && r.span.end_col == col;
+        self.refs
+            .iter()
+            .find(on)
+            .or_else(|| self.refs.iter().find(after))
+    }
+
+    pub fn definition(&self, r: &Reference) -> Option<&Symbol> {
+        r.symbol.map(|id| &self.symbols[id])
+    }
+
+    // Every occurrence of what `r` refers to; unresolved names match by name
+    pub fn references_to(&self, r: &Reference) -> Vec<&Reference> {
+        self.refs
+            .iter()
+            .filter(|o| match r.symbol {
+                Some(id) => o.symbol == Some(id),
+                None => o.symbol.is_none() && o.name == r.name,
+            })
+            .collect()
+    }
+
+    // Whether scope `a` is `b` or encloses it
+    pub fn encloses(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.scopes[b].parent {
+                Some(p) => b = p,
+                None => return false,
+            }
+        }
+    }
+
+    // Innermost scope containing a 1-based position
+    pub fn scope_at(&self, line: usize, col: usize) -> usize {
+        let mut best = 0;
+        for (id, s) in self.scopes.iter().enumerate() {
+            let starts = (s.span.line, s.span.col) <= (line, col);
+            let ends = (line, col) <= (s.span.end_line, s.span.end_col);
+            if starts && ends && self.encloses(best, id) {
+                best = id;
+            }
+        }
+        best
+    }
+
+    // Why renaming `symbol` to `new_name` would be wrong, if it would: not an identifier, a
+    // keyword or builtin, a clash in the same scope, or a change in what some use refers to
+    pub fn rename_conflict(&self, symbol: usize, new_name: &str) -> Option<String> {
+        let mut chars = new_name.chars();
+        let valid = chars
+            .next()
+            .map(|c| c.is_alphabetic() || c == '_')
+            .unwrap_or(false)
+            && chars.all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            return Some(format!("'{}' is not a valid identifier", new_name));
+        }
+        if KEYWORDS.contains(&new_name) {
+            return Some(format!("'{}' is a keyword", new_name));
+        }
+        if BUILTINS.contains(&new_name) {
+            return Some(format!("'{}' is a builtin function", new_name));
+        }
+        let sym = &self.symbols[symbol];
+        let named = |s: &&Symbol| s.name == new_name;
+        if self
+            .symbols
+            .iter()
+            .filter(named)
+            .any(|s| s.scope == sym.scope)
+        {
+            return Some(format!("'{}' is already declared in this scope", new_name));
+        }
+        // A use of the symbol would be shadowed by an inner `new_name`
+        for r in self.refs.iter().filter(|r| r.symbol == Some(symbol)) {
+            let shadowed = self.symbols.iter().filter(named).any(|s| {
+                s.scope != sym.scope
+                    && self.encloses(sym.scope, s.scope)
+                    && self.encloses(s.scope, r.scope)
+            });
+            if shadowed {
+                return Some(format!(
+                    "'{}' would be shadowed by another '{}' at line {}",
+                    sym.name, new_name, r.span.line
+                ));
+            }
+        }
+        // A use of an outer `new_name` would start referring to the renamed symbol
+        for r in self.refs.iter().filter(|r| r.name == new_name) {
+            if !self.encloses(sym.scope, r.scope) {
+                continue;
+            }
+            let outer = match r.symbol {
+                Some(id) => !self.encloses(sym.scope, self.symbols[id].scope),
+                None => true,
+            };
+            if outer {
+                return Some(format!(
+                    "the use of '{}' at line {} would refer to the renamed variable",
+                    new_name, r.span.line
+                ));
+            }
+        }
+        None
+    }
+
+    // Why renaming the top-level `name`, shared with other scripts, to `new_name` would
+    // change what this script means, if it would
+    pub fn global_rename_conflict(&self, name: &str, new_name: &str) -> Option<String> {
+        if self
+            .symbols
+            .iter()
+            .any(|s| s.scope == 0 && s.name == new_name)
+        {
+            return Some(format!(
+                "'{}' is already declared at the top level",
+                new_name
+            ));
+        }
+        let global = |r: &Reference| match r.symbol {
+            Some(id) => self.symbols[id].scope == 0,
+            None => true,
+        };
+        for r in self.refs.iter().filter(|r| r.name == name && global(r)) {
+            let shadowed = self
+                .symbols
+                .iter()
+                .any(|s| s.name == new_name && s.scope != 0 && self.encloses(s.scope, r.scope));
+            if shadowed {
+                return Some(format!(
+                    "'{}' would be shadowed by another '{}' at line {}",
+                    name, new_name, r.span.line
+                ));
+            }
+        }
+        if let Some(r) = self
+            .refs
+            .iter()
+            .find(|r| r.name == new_name && r.symbol.is_none())
+        {
+            return Some(format!(
+                "the use of '{}' at line {} would refer to the renamed variable",
+                new_name, r.span.line
+            ));
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Analyzer {
+    table: SymbolTable,
+    stack: Vec<usize>,
+    // Per open scope: references not resolved when seen, retried when the scope closes
+    pending: Vec<Vec<usize>>,
+}
+
+impl Analyzer {
+    fn push_scope(&mut self, span: Span) {
+        let id = self.table.scopes.len();
+        self.table.scopes.push(Scope {
+            parent: self.stack.last().copied(),
+            span,
+        });
+        self.stack.push(id);
+        self.pending.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        let (Some(scope), Some(pending)) = (self.stack.pop(), self.pending.pop()) else {
+            return;
+        };
+        for r in pending {
+            let name = &self.table.refs[r].name;
+            let found = self
+                .table
+                .symbols
+                .iter()
+                .rposition(|s| s.scope == scope && &s.name == name);
+            match (found, self.pending.last_mut()) {
+                (Some(id), _) => self.table.refs[r].symbol = Some(id),
+                (None, Some(parent)) => parent.push(r),
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn current(&self) -> usize {
+        self.stack.last().copied().unwrap_or(0)
+    }
+
+    fn declare(&mut self, name: &str, span: Span, kind: SymbolKind, ty: Option<TypeExpr>) {
+        let id = self.table.symbols.len();
+        let scope = self.current();
+        self.table.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            scope,
+            ty,
+            params: Vec::new(),
+        });
+        self.table.refs.push(Reference {
+            name: name.to_string(),
+            span,
+            kind: RefKind::Decl,
+            scope,
+            symbol: Some(id),
+        });
+    }
+
+    fn use_name(&mut self, name: &str, span: Span, kind: RefKind) {
+        let mut symbol = None;
+        for scope in self.stack.iter().rev() {
+            symbol = self
+                .table
+                .symbols
+                .iter()
+                .rposition(|s| s.scope == *scope && s.name == name);
+            if symbol.is_some() {
+                break;
+            }
+        }
+        let id = self.table.refs.len();
+        self.table.refs.push(Reference {
+            name: name.to_string(),
+            span,
+            kind,
+            scope: self.current(),
+            symbol,
+        });
+        if symbol.is_none() {
+            if let Some(p) = self.pending.last_mut() {
+                p.push(id);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let {
+                name,
+                name_span,
+                ty,
+                init,
+                ..
+            } => {
+                if let ExprKind::Fn(f) = &init.kind {
+                    self.declare(name, *name_span, SymbolKind::Function, ty.clone());
+                    if let Some(sym) = self.table.symbols.last_mut() {
+                        sym.params = f.params.iter().map(|p| p.name.clone()).collect();
+                    }
+                    self.expr(init);
+                } else {
+                    self.expr(init);
+                    self.declare(name, *name_span, SymbolKind::Variable, ty.clone());
+                }
+            }
+            StmtKind::Expr(e) => self.expr(e),
+            StmtKind::Block(b, _) | StmtKind::Test { body: b, .. } => {
+                self.push_scope(stmt.span);
+                for s in b {
+                    self.stmt(s);
+                }
+                self.pop_scope();
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            StmtKind::For {
+                name,
+                name_span,
+                iter,
+                body,
+                ..
+            } => {
+                self.expr(iter);
+                self.push_scope(stmt.span);
+                self.declare(name, *name_span, SymbolKind::LoopVariable, None);
+                self.stmt(body);
+                self.pop_scope();
+            }
+            StmtKind::Return(v) => {
+                if let Some(e) = v {
+                    self.expr(e);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Var(name, _) => self.use_name(name, expr.span, RefKind::Read),
+            ExprKind::Assign { name, value, .. } => {
+                self.expr(value);
+                let s = expr.span;
+                let span = Span::new(s.line, s.col, s.line, s.col + name.chars().count());
+                self.use_name(name, span, RefKind::Write);
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { expr, .. } => self.expr(expr),
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                for a in args {
+                    self.expr(a);
+                }
+            }
+            ExprKind::Fn(f) => {
+                self.push_scope(expr.span);
+                for p in &f.params {
+                    self.declare(&p.name, p.span, SymbolKind::Parameter, p.ty.clone());
+                }
+                for s in &f.body {
+                    self.stmt(s);
+                }
+                self.pop_scope();
+            }
+            ExprKind::List(items) => {
+                for e in items {
+                    self.expr(e);
+                }
+            }
+            ExprKind::Map(props) => {
+                for (_, e) in props {
+                    self.expr(e);
+                }
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Field { target, .. } => self.expr(target),
+        }
+    }
+}
@@ -0,0 +1,555 @@
+// SPDX-License-Identifier: MIT
+// The test framework behind `qk test`. A script declares `test "name" { ... }` blocks at
+// its top level; each test runs in a fresh interpreter with its own MockHost that first
+// runs the script's other top-level statements, then the test body. Tests use `assert`,
+// `assert_eq` (which lists every differing list item and map field) and `assert_throws`,
+// answer host ops with `mock_host` and look at what the script did with `host_calls` and
+// `emitted`. Results are reported as text or JUnit XML.
+//
+// In snapshot mode a script is also run as a whole, and what it prints must match the
+// sibling `.out` file (`quest.qk` -> `quest.out`); a runtime error adds a last
+// `Runtime error: ...` line. Scripts without a `.out` are not snapshot-tested. Blessing
+// writes the output to the `.out` instead of comparing, creating it if needed.
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::ast::{Program, Span, Stmt, StmtKind};
+use crate::coverage::{Coverage, FileCoverage};
+use crate::eval::{Interpreter, RuntimeError};
+use crate::host::{Host, MockHost, Recorded, Reply};
+use crate::output::Capture;
+use crate::parser::Parser;
+use crate::stdlib::native;
+use crate::value::{EnvRef, Function, Value};
+
+// Builtins defined only while tests run
+pub const TEST_BUILTINS: &[&str] = &[
+    "assert",
+    "assert_eq",
+    "assert_throws",
+    "mock_host",
+    "host_calls",
+    "emitted",
+];
+
+// Messages of failed assertions start with this; other errors count as test errors
+const FAILED: &str = "Assertion failed";
+
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    // Run only tests whose name contains this
+    pub filter: Option<String>,
+    // Count which statements and branches of the scripts the tests ran
+    pub coverage: bool,
+    // Compare each script's printed output with its `.out` file
+    pub snapshot: bool,
+    // Write the `.out` files instead of comparing with them
+    pub bless: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    // An assertion did not hold
+    Failed(String),
+    // The test (or the script's top level before it) stopped with another error
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub span: Span,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub results: Vec<TestResult>,
+    // The file could not be read or parsed
+    pub error: Option<String>,
+    // With TestOptions::coverage
+    pub coverage: Option<FileCoverage>,
+}
+
+impl FileReport {
+    pub fn count(&self, want: fn(&Outcome) -> bool) -> usize {
+        self.results.iter().filter(|r| want(&r.outcome)).count()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.results.iter().all(|r| r.outcome == Outcome::Passed)
+    }
+}
+
+// The test blocks of a program, in order
+pub fn tests(program: &Program) -> Vec<(&str, Span, &[Stmt])> {
+    program
+        .statements
+        .iter()
+        .filter_map(|s| match &s.kind {
+            StmtKind::Test { name, body } => Some((name.as_str(), s.span, body.as_slice())),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn run_file(path: &Path, opts: &TestOptions) -> FileReport {
+    let mut report = match std::fs::read_to_string(path) {
+        Ok(src) => {
+            let mut report = run_source(&src, opts);
+            if (opts.snapshot || opts.bless) && report.error.is_none() {
+                report.results.extend(snapshot(path, &src, opts.bless));
+            }
+            report
+        }
+        Err(e) => FileReport {
+            error: Some(e.to_string()),
+            ..Default::default()
+        },
+    };
+    report.path = path.to_path_buf();
+    report
+}
+
+// Everything a script prints when run on its own, with a last line for a runtime error
+pub fn script_output(program: Program) -> String {
+    let capture = Capture::default();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.set_output(capture.clone());
+    let result = interp.eval(program);
+    let mut text = capture.text();
+    if let Err(e) = result {
+        text.push_str(&format!("Runtime error: {}\n", e));
+    }
+    text
+}
+
+// The snapshot result for a script: None when it has no `.out` and is not being blessed
+fn snapshot(path: &Path, src: &str, bless: bool) -> Option<TestResult> {
+    let expected_path = path.with_extension("out");
+    let expected = std::fs::read_to_string(&expected_path).ok();
+    if expected.is_none() && !bless {
+        return None;
+    }
+    let started = Instant::now();
+    let program = Parser::new(src).parse_program().ok()?;
+    let actual = script_output(program);
+    let outcome = if bless {
+        match std::fs::write(&expected_path, &actual) {
+            Ok(()) => Outcome::Passed,
+            Err(e) => Outcome::Error(format!("{}: {}", expected_path.display(), e)),
+        }
+    } else {
+        let expected = expected.unwrap_or_default();
+        match output_diff(&expected, &actual) {
+            None => Outcome::Passed,
+            Some(diff) => Outcome::Failed(format!(
+                "{}: output differs from {}\n{}",
+                FAILED,
+                expected_path.display(),
+                diff
+            )),
+        }
+    };
+    Some(TestResult {
+        name: "snapshot".into(),
+        span: Span::default(),
+        outcome,
+        duration: started.elapsed(),
+    })
+}
+
+// The differing lines of two outputs, or None when they match
+pub fn output_diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let (expected, actual): (Vec<&str>, Vec<&str>) =
+        (expected.lines().collect(), actual.lines().collect());
+    let mut out = Vec::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {}
+            (Some(e), Some(a)) => out.push(format!("line {}:\n  - {}\n  + {}", i + 1, e, a)),
+            (Some(e), None) => out.push(format!("line {}: missing\n  - {}", i + 1, e)),
+            (None, Some(a)) => out.push(format!("line {}: extra\n  + {}", i + 1, a)),
+            (None, None) => {}
+        }
+    }
+    if out.is_empty() {
+        out.push("the last line ends differently".into());
+    }
+    Some(out.join("\n"))
+}
+
+pub fn run_source(src: &str, opts: &TestOptions) -> FileReport {
+    match Parser::new(src).parse_program() {
+        Ok(program) => {
+            let coverage = opts.coverage.then(Coverage::default);
+            FileReport {
+                results: run_tests(&program, opts, coverage.as_ref()),
+                coverage: coverage.map(|c| c.report(&program)),
+                ..Default::default()
+            }
+        }
+        Err(e) => FileReport {
+            error: Some(format!("Parse error: {}", e)),
+            ..Default::default()
+        },
+    }
+}
+
+pub fn run_program(program: &Program, opts: &TestOptions) -> Vec<TestResult> {
+    run_tests(program, opts, None)
+}
+
+fn run_tests(
+    program: &Program,
+    opts: &TestOptions,
+    coverage: Option<&Coverage>,
+) -> Vec<TestResult> {
+    let setup: Vec<Stmt> = program
+        .statements
+        .iter()
+        .filter(|s| !matches!(s.kind, StmtKind::Test { .. }))
+        .cloned()
+        .collect();
+    tests(program)
+        .into_iter()
+        .filter(|(name, ..)| opts.filter.as_deref().is_none_or(|f| name.contains(f)))
+        .map(|(name, span, body)| {
+            let started = Instant::now();
+            let outcome = run_test(&setup, span, body, coverage);
+            TestResult {
+                name: name.to_string(),
+                span,
+                outcome,
+                duration: started.elapsed(),
+            }
+        })
+        .collect()
+}
+
+fn run_test(setup: &[Stmt], span: Span, body: &[Stmt], coverage: Option<&Coverage>) -> Outcome {
+    let mock = MockHost::default();
+    let mut interp = Interpreter::with_host(mock.host());
+    install_test_builtins(&interp.env, &mock);
+    if let Some(c) = coverage {
+        interp.hooks = Some(Box::new(c.clone()));
+    }
+    let mut statements = setup.to_vec();
+    // The resolver gave the test body the frame a block with these statements gets
+    let frame = crate::resolve::frame(body);
+    statements.push(Stmt::new(StmtKind::Block(body.to_vec(), frame), span));
+    match interp.eval(Program { statements }) {
+        Ok(_) => Outcome::Passed,
+        Err(e) => {
+            let message = e.to_string();
+            if message.starts_with(FAILED) {
+                Outcome::Failed(message)
+            } else {
+                Outcome::Error(message)
+            }
+        }
+    }
+}
+
+pub fn install_test_builtins(env: &EnvRef, mock: &MockHost) {
+    let mut e = env.borrow_mut();
+    e.define(
+        "assert".into(),
+        native("assert", |args, _| {
+            let value = args.first().cloned().unwrap_or(Value::Null);
+            if value.truthy() {
+                return Ok(Value::Null);
+            }
+            Err(match args.get(1) {
+                Some(Value::String(m)) => format!("{}: {}", FAILED, m),
+                _ => format!("{}: expected a truthy value, got {}", FAILED, value),
+            })
+        }),
+    );
+    e.define(
+        "assert_eq".into(),
+        native("assert_eq", |args, _| {
+            let actual = args.first().cloned().unwrap_or(Value::Null);
+            let expected = args.get(1).cloned().unwrap_or(Value::Null);
+            let mut diffs = Vec::new();
+            diff("", &actual, &expected, &mut diffs);
+            if diffs.is_empty() {
+                return Ok(Value::Null);
+            }
+            let head = match args.get(2) {
+                Some(Value::String(m)) => format!("{}: {}", FAILED, m),
+                _ => FAILED.to_string(),
+            };
+            Err(match diffs.as_slice() {
+                [(path, d)] if path.is_empty() => format!("{}: {}", head, d),
+                _ => {
+                    let lines: Vec<String> = diffs
+                        .iter()
+                        .map(|(p, d)| format!("\n  {}: {}", p, d))
+                        .collect();
+                    format!("{}: values differ{}", head, lines.concat())
+                }
+            })
+        }),
+    );
+    // The interpreter calls the function itself (see assert_throws below)
+    e.define(
+        "assert_throws".into(),
+        native("assert_throws", |_, _| {
+            Err("assert_throws(fn, message?)".into())
+        }),
+    );
+
+    // mock_host(op, reply): answer `op` with a value, or with what a function returns for
+    // the payload
+    let m = mock.clone();
+    e.define(
+        "mock_host".into(),
+        native("mock_host", move |args, env| {
+            let (Some(Value::String(op)), Some(reply)) = (args.first(), args.get(1)) else {
+                return Err("mock_host(op, reply)".into());
+            };
+            let reply = match reply {
+                Value::Function(f) => match f.as_ref() {
+                    Function::User { .. } => Reply::Script(f.clone()),
+                    Function::Native { fun, .. } => {
+                        let fun = fun.clone();
+                        Reply::Native(Rc::new(move |payload| fun(vec![payload], env.clone())))
+                    }
+                },
+                v => Reply::Value(v.clone()),
+            };
+            m.reply(op, reply);
+            Ok(Value::Null)
+        }),
+    );
+    // host_calls(op?): the host calls so far as { op, payload }, optionally of one op
+    let m = mock.clone();
+    e.define(
+        "host_calls".into(),
+        native("host_calls", move |args, _| {
+            let only = match args.first() {
+                Some(Value::String(op)) => Some(op.as_str()),
+                _ => None,
+            };
+            let calls = m.recorded().into_iter().filter_map(|r| match r {
+                Recorded::Call { op, payload } if only.is_none_or(|o| o == op) => {
+                    Some(entry("op", op, payload))
+                }
+                _ => None,
+            });
+            Ok(Value::List(calls.collect()))
+        }),
+    );
+    // emitted(): the emitted events so far as { event, payload }, in order
+    let m = mock.clone();
+    e.define(
+        "emitted".into(),
+        native("emitted", move |_, _| {
+            let events = m.recorded().into_iter().filter_map(|r| match r {
+                Recorded::Event { event, payload } => Some(entry("event", event, payload)),
+                _ => None,
+            });
+            Ok(Value::List(events.collect()))
+        }),
+    );
+}
+
+fn entry(key: &str, name: String, payload: Value) -> Value {
+    let mut m = crate::value::Map::new();
+    m.insert(key.to_string(), Value::String(name));
+    m.insert("payload".to_string(), payload);
+    Value::Map(m)
+}
+
+// assert_throws(fn, message?): calls `fn` with no arguments and expects it to fail, with
+// an error containing `message` if given; returns the error message
+pub(crate) fn assert_throws(
+    interp: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let Some(f @ Value::Function(_)) = args.first().cloned() else {
+        return Err(RuntimeError::Msg("assert_throws(fn, message?)".into()));
+    };
+    // Errors unwind without restoring the environment
+    let saved = interp.env.clone();
+    let result = interp.call_function(f, Vec::new());
+    interp.env = saved;
+    let message = match result {
+        Ok(v) => {
+            return Err(RuntimeError::Msg(format!(
+                "{}: expected an error, got {}",
+                FAILED, v
+            )))
+        }
+        Err(e @ RuntimeError::Aborted(_)) => return Err(e),
+        Err(e) => e.to_string(),
+    };
+    match args.get(1) {
+        Some(Value::String(want)) if !message.contains(want.as_str()) => {
+            Err(RuntimeError::Msg(format!(
+                "{}: expected an error containing \"{}\", got \"{}\"",
+                FAILED, want, message
+            )))
+        }
+        _ => Ok(Value::String(message)),
+    }
+}
+
+// Differences between two values as (path, description), e.g. `[1].gold`
+fn diff(path: &str, actual: &Value, expected: &Value, out: &mut Vec<(String, String)>) {
+    match (actual, expected) {
+        (Value::List(a), Value::List(e)) => {
+            for i in 0..a.len().max(e.len()) {
+                let at = format!("{}[{}]", path, i);
+                match (a.get(i), e.get(i)) {
+                    (Some(a), Some(e)) => diff(&at, a, e, out),
+                    (None, Some(e)) => out.push((at, format!("missing, expected {}", e))),
+                    (Some(a), None) => out.push((at, format!("unexpected {}", a))),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Map(a), Value::Map(e)) => {
+            let keys: std::collections::BTreeSet<&String> = a.keys().chain(e.keys()).collect();
+            for k in keys {
+                let at = format!("{}.{}", path, k);
+                match (a.get(k), e.get(k)) {
+                    (Some(a), Some(e)) => diff(&at, a, e, out),
+                    (None, Some(e)) => out.push((at, format!("missing, expected {}", e))),
+                    (Some(a), None) => out.push((at, format!("unexpected {}", a))),
+                    (None, None) => {}
+                }
+            }
+        }
+        (a, e) if same(a, e) => {}
+        (a, e) => out.push((path.to_string(), format!("expected {}, got {}", e, a))),
+    }
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::String(x), Value::String(y)) => x == y,
+        (Value::Null, Value::Null) => true,
+        (Value::Function(f), Value::Function(g)) => Rc::ptr_eq(f, g),
+        _ => false,
+    }
+}
+
+pub fn to_text(reports: &[FileReport]) -> String {
+    let mut out = String::new();
+    let (mut passed, mut failed, mut errors) = (0, 0, 0);
+    for report in reports {
+        out.push_str(&format!("{}\n", report.path.display()));
+        if let Some(e) = &report.error {
+            errors += 1;
+            out.push_str(&format!("  ERROR {}\n", e));
+        }
+        for r in &report.results {
+            let ms = r.duration.as_secs_f64() * 1000.0;
+            let (label, detail) = match &r.outcome {
+                Outcome::Passed => {
+                    passed += 1;
+                    ("ok   ", None)
+                }
+                Outcome::Failed(m) => {
+                    failed += 1;
+                    ("FAIL ", Some(m))
+                }
+                Outcome::Error(m) => {
+                    errors += 1;
+                    ("ERROR", Some(m))
+                }
+            };
+            out.push_str(&format!("  {} {} ({:.1} ms)\n", label, r.name, ms));
+            if let Some(m) = detail {
+                for line in m.lines() {
+                    out.push_str(&format!("        {}\n", line));
+                }
+            }
+        }
+    }
+    out.push_str(&format!(
+        "\n{} passed, {} failed, {} errors in {} files\n",
+        passed,
+        failed,
+        errors,
+        reports.len()
+    ));
+    out
+}
+
+pub fn to_junit(reports: &[FileReport]) -> String {
+    let mut suites = String::new();
+    let (mut tests, mut failures, mut errors, mut total) = (0, 0, 0, 0.0);
+    for report in reports {
+        let name = xml_escape(&report.path.display().to_string());
+        let time: f64 = report
+            .results
+            .iter()
+            .map(|r| r.duration.as_secs_f64())
+            .sum();
+        let failed = report.count(|o| matches!(o, Outcome::Failed(_)));
+        let errored =
+            report.count(|o| matches!(o, Outcome::Error(_))) + report.error.iter().count();
+        let count = report.results.len() + report.error.iter().count();
+        suites.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.6}\">\n",
+            name, count, failed, errored, time
+        ));
+        if let Some(e) = &report.error {
+            suites.push_str(&format!(
+                "    <testcase name=\"(load)\" classname=\"{}\">\n      <error message=\"{}\"/>\n    </testcase>\n",
+                name,
+                xml_escape(e)
+            ));
+        }
+        for r in &report.results {
+            suites.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.6}\"",
+                xml_escape(&r.name),
+                name,
+                r.duration.as_secs_f64()
+            ));
+            let (tag, message) = match &r.outcome {
+                Outcome::Passed => {
+                    suites.push_str("/>\n");
+                    continue;
+                }
+                Outcome::Failed(m) => ("failure", m),
+                Outcome::Error(m) => ("error", m),
+            };
+            let first = message.lines().next().unwrap_or_default();
+            suites.push_str(&format!(
+                ">\n      <{tag} message=\"{}\">{}</{tag}>\n    </testcase>\n",
+                xml_escape(first),
+                xml_escape(message)
+            ));
+        }
+        suites.push_str("  </testsuite>\n");
+        tests += count;
+        failures += failed;
+        errors += errored;
+        total += time;
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.6}\">\n{}</testsuites>\n",
+        tests, failures, errors, total, suites
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
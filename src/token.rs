@@ -59,10 +59,25 @@ pub struct Token {
     pub kind: TokenKind,
     pub line: usize,
     pub col: usize,
+    // Position just past the last character of the token
+    pub end_line: usize,
+    pub end_col: usize,
 }
 
 impl Token {
     pub fn new(kind: TokenKind, line: usize, col: usize) -> Self {
-        Self { kind, line, col }
+        Self {
+            kind,
+            line,
+            col,
+            end_line: line,
+            end_col: col,
+        }
+    }
+
+    pub fn with_end(mut self, end_line: usize, end_col: usize) -> Self {
+        self.end_line = end_line;
+        self.end_col = end_col;
+        self
     }
 }
@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use crate::ast::*;
+use crate::decl::Declarations;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
@@ -46,6 +48,8 @@ pub struct TypeError {
     pub message: String,
     pub subject: Option<String>,
     pub hint: Option<String>,
+    // Where the problem is; spans use 1-based lines and columns
+    pub span: Span,
 }
 
 #[derive(Default)]
@@ -53,20 +57,125 @@ pub struct TypeEnv {
     pub vars: BTreeMap<String, Type>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    // Strict mode: no implicit any, annotated top-level functions, bool-only conditions
+    pub strict: bool,
+    // Host ops and native functions loaded from .qkd declaration files
+    pub decls: Option<Arc<Declarations>>,
+    // Top-level names declared by other scripts loaded into the same interpreter
+    pub globals: Option<Arc<BTreeMap<String, Type>>>,
+}
+
 pub struct TypeCheckResult {
     pub errors: Vec<TypeError>,
     pub env: TypeEnv,
+    // Diagnostics from a strict check are errors; otherwise they are warnings
+    pub strict: bool,
+    // Types inferred for `let` and loop variable names, function literals, `if`/`while`
+    // conditions and `emit` payloads, keyed by the span of the name or expression
+    pub inferred: Vec<(Span, Type)>,
+}
+
+struct Ctx<'a> {
+    opts: &'a CheckOptions,
+    errors: Vec<TypeError>,
+    inferred: Vec<(Span, Type)>,
+    // Types returned so far by each enclosing function literal being checked
+    returns: Vec<Vec<Type>>,
 }
 
 pub fn check_program(p: &Program) -> TypeCheckResult {
+    check_program_with_options(p, &CheckOptions::default())
+}
+
+pub fn check_program_with_options(p: &Program, opts: &CheckOptions) -> TypeCheckResult {
     let mut env = TypeEnv::default();
     // Builtins
     prelude(&mut env);
-    let mut errors = Vec::new();
+    if let Some(decls) = &opts.decls {
+        for (name, d) in &decls.natives {
+            env.vars.insert(
+                name.clone(),
+                Type::Func(d.param_types(), Box::new(d.ret_type())),
+            );
+        }
+    }
+    if let Some(globals) = &opts.globals {
+        for (name, t) in globals.iter() {
+            env.vars.insert(name.clone(), t.clone());
+        }
+    }
+    let mut cx = Ctx {
+        opts,
+        errors: Vec::new(),
+        inferred: Vec::new(),
+        returns: Vec::new(),
+    };
     for s in &p.statements {
-        check_stmt(s, &mut env, None, &mut errors);
+        if opts.strict {
+            require_fn_annotations(s, &mut cx);
+        }
+        check_stmt(s, &mut env, None, &mut cx);
+    }
+    TypeCheckResult {
+        errors: cx.errors,
+        env,
+        strict: opts.strict,
+        inferred: cx.inferred,
+    }
+}
+
+// Returns true when the file opts into strict checking with a `// qk: strict`
+// comment before its first line of code.
+pub fn has_strict_pragma(src: &str) -> bool {
+    for line in src.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        match trimmed.strip_prefix("//") {
+            Some(rest) => {
+                let directive: String = rest.chars().filter(|c| !c.is_whitespace()).collect();
+                if directive == "qk:strict" {
+                    return true;
+                }
+            }
+            None => return false,
+        }
+    }
+    false
+}
+
+// Strict mode: top-level functions must declare their return type (parameters are covered
+// by the implicit-any rule for every function literal).
+fn require_fn_annotations(stmt: &Stmt, cx: &mut Ctx) {
+    if let StmtKind::Let {
+        name,
+        name_span,
+        init,
+        ..
+    } = &stmt.kind
+    {
+        if !matches!(&init.kind, ExprKind::Fn(f) if f.ret.is_none()) {
+            return;
+        }
+        cx.errors.push(TypeError {
+            message: format!("Function '{}' is missing a return type annotation", name),
+            subject: Some(name.clone()),
+            span: *name_span,
+            hint: Some("Strict mode requires top-level functions to declare `-> Type`.".into()),
+        });
+    }
+}
+
+// Whether a value of type `t` may be used where a condition is expected.
+fn is_condition(t: &Type, cx: &Ctx) -> bool {
+    if cx.opts.strict {
+        *t == Type::Bool
+    } else {
+        is_compatible(t, &Type::Bool)
     }
-    TypeCheckResult { errors, env }
 }
 
 fn prelude(env: &mut TypeEnv) {
@@ -101,6 +210,10 @@ fn prelude(env: &mut TypeEnv) {
         "pop".into(),
         Type::Func(vec![Type::List(Box::new(Type::Any))], Box::new(Type::Any)),
     );
+    env.vars.insert(
+        "set".into(),
+        Type::Func(vec![Type::Any, Type::Any, Type::Any], Box::new(Type::Any)),
+    );
     env.vars.insert(
         "on".into(),
         Type::Func(vec![Type::String, Type::Any], Box::new(Type::Null)),
@@ -113,26 +226,55 @@ fn prelude(env: &mut TypeEnv) {
         "host".into(),
         Type::Func(vec![Type::String, Type::Any], Box::new(Type::Any)),
     );
+    // log.info(message, fields?) and the other levels; the fields are optional
+    env.vars.insert(
+        "log".into(),
+        Type::Record(
+            ["debug", "info", "warn", "error"]
+                .into_iter()
+                .map(|l| (l.to_string(), Type::Any))
+                .collect(),
+        ),
+    );
 }
 
-fn check_stmt(
-    stmt: &Stmt,
-    env: &mut TypeEnv,
-    expected_ret: Option<&Type>,
-    errors: &mut Vec<TypeError>,
-) {
-    match stmt {
-        Stmt::Let { name, ty, init } => {
-            let t_init = infer_expr(init, env, errors);
+// Builtins available inside test blocks (see testing.rs); several take optional trailing
+// arguments, which function types cannot express
+fn test_prelude(env: &mut TypeEnv) {
+    for name in crate::testing::TEST_BUILTINS {
+        env.vars.insert(name.to_string(), Type::Any);
+    }
+}
+
+fn check_stmt(stmt: &Stmt, env: &mut TypeEnv, expected_ret: Option<&Type>, cx: &mut Ctx) {
+    match &stmt.kind {
+        StmtKind::Let {
+            name,
+            name_span,
+            ty,
+            init,
+            ..
+        } => {
+            // Declare functions before checking their bodies so they can recurse
+            if let ExprKind::Fn(f) = &init.kind {
+                let sig = match ty {
+                    Some(ann) => Type::from_expr(ann),
+                    None => fn_signature(&f.params, &f.ret),
+                };
+                env.vars.insert(name.clone(), sig);
+            }
+            let t_init = infer_expr(init, env, cx);
+            cx.inferred.push((*name_span, t_init.clone()));
             if let Some(ann) = ty {
                 let ann_t = Type::from_expr(ann);
                 if !is_compatible(&t_init, &ann_t) {
-                    errors.push(TypeError {
+                    cx.errors.push(TypeError {
                         message: format!(
                             "Type mismatch: variable '{}' initialized with {} but annotated as {}",
                             name, t_init, ann_t
                         ),
                         subject: Some(name.clone()),
+                        span: init.span,
                         hint: Some(format!(
                             "Change the annotation to {} or convert the initializer to {}",
                             t_init, ann_t
@@ -150,114 +292,153 @@ fn check_stmt(
                 env.vars.insert(name.clone(), t_init);
             }
         }
-        Stmt::Expr(e) => {
-            let _ = infer_expr(e, env, errors);
+        StmtKind::Expr(e) => {
+            let _ = infer_expr(e, env, cx);
         }
-        Stmt::Block(b) => {
+        StmtKind::Block(b, _) | StmtKind::Test { body: b, .. } => {
             let mut child = TypeEnv {
                 vars: env.vars.clone(),
             };
+            if matches!(stmt.kind, StmtKind::Test { .. }) {
+                test_prelude(&mut child);
+            }
             for s in b {
-                check_stmt(s, &mut child, expected_ret, errors);
+                check_stmt(s, &mut child, expected_ret, cx);
             }
         }
-        Stmt::If {
+        StmtKind::If {
             cond,
             then_branch,
             else_branch,
         } => {
-            let t = infer_expr(cond, env, errors);
-            if !is_compatible(&t, &Type::Bool) {
-                errors.push(TypeError {
+            let t = infer_expr(cond, env, cx);
+            cx.inferred.push((cond.span, t.clone()));
+            if !is_condition(&t, cx) {
+                cx.errors.push(TypeError {
                     message: format!("If condition must be bool, got {}", t),
                     subject: None,
+                    span: cond.span,
                     hint: Some(
                         "Make the condition a bool, e.g., compare explicitly: x != 0 or s != \"\""
                             .into(),
                     ),
                 });
             }
-            check_stmt(then_branch, env, expected_ret, errors);
+            check_stmt(then_branch, env, expected_ret, cx);
             if let Some(e) = else_branch {
-                check_stmt(e, env, expected_ret, errors);
+                check_stmt(e, env, expected_ret, cx);
             }
         }
-        Stmt::While { cond, body } => {
-            let t = infer_expr(cond, env, errors);
-            if !is_compatible(&t, &Type::Bool) {
-                errors.push(TypeError {
+        StmtKind::While { cond, body } => {
+            let t = infer_expr(cond, env, cx);
+            cx.inferred.push((cond.span, t.clone()));
+            if !is_condition(&t, cx) {
+                cx.errors.push(TypeError {
                     message: format!("While condition must be bool, got {}", t),
                     subject: None,
+                    span: cond.span,
                     hint: Some(
                         "Make the condition a bool, e.g., compare explicitly: x != 0 or s != \"\""
                             .into(),
                     ),
                 });
             }
-            check_stmt(body, env, expected_ret, errors);
+            check_stmt(body, env, expected_ret, cx);
         }
-        Stmt::For { name, iter, body } => {
-            let it = infer_expr(iter, env, errors);
+        StmtKind::For {
+            name,
+            name_span,
+            iter,
+            body,
+            ..
+        } => {
+            let it = infer_expr(iter, env, cx);
             match it {
                 Type::List(inner) => {
                     let mut child = TypeEnv {
                         vars: env.vars.clone(),
                     };
+                    cx.inferred.push((*name_span, (*inner).clone()));
                     child.vars.insert(name.clone(), *inner.clone());
-                    check_stmt(body, &mut child, expected_ret, errors);
+                    check_stmt(body, &mut child, expected_ret, cx);
                 }
-                _ => errors.push(TypeError {
+                _ => cx.errors.push(TypeError {
                     message: format!("For expects list, got {}", it),
                     subject: Some(name.clone()),
+                    span: iter.span,
                     hint: Some(
                         "Iterate a list value. For maps, use keys(map) to iterate keys.".into(),
                     ),
                 }),
             }
         }
-        Stmt::Return(v) => {
+        StmtKind::Return(v) => {
+            if let Some(top) = cx.returns.last_mut() {
+                if v.is_none() {
+                    top.push(Type::Null);
+                }
+            }
             if let Some(e) = v {
-                let t = infer_expr(e, env, errors);
+                let t = infer_expr(e, env, cx);
+                if let Some(top) = cx.returns.last_mut() {
+                    top.push(t.clone());
+                }
                 if let Some(exp) = expected_ret {
                     if !is_compatible(&t, exp) {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!("Return type {} does not match expected {}", t, exp),
                             subject: None,
+                            span: e.span,
                             hint: Some("Change the return expression or update the function's return type annotation.".into()),
                         });
                     }
                 }
             } else if let Some(exp) = expected_ret {
                 if !is_compatible(&Type::Null, exp) {
-                    errors.push(TypeError {
+                    cx.errors.push(TypeError {
                         message: format!("Return type null does not match expected {}", exp),
                         subject: None,
+                        span: stmt.span,
                         hint: Some("Return a value of the expected type or change the function's return type.".into()),
                     });
                 }
             }
         }
-        Stmt::Break | Stmt::Continue => {}
+        StmtKind::Break | StmtKind::Continue => {}
     }
 }
 
-fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Type {
-    match expr {
-        Expr::Literal(Lit::Number(_)) => Type::Number,
-        Expr::Literal(Lit::Bool(_)) => Type::Bool,
-        Expr::Literal(Lit::String(_)) => Type::String,
-        Expr::Literal(Lit::Null) => Type::Null,
-        Expr::Var(name) => env.vars.get(name).cloned().unwrap_or(Type::Any),
-        Expr::Assign { name, value } => {
-            let vt = infer_expr(value, env, errors);
+fn infer_expr(expr: &Expr, env: &mut TypeEnv, cx: &mut Ctx) -> Type {
+    match &expr.kind {
+        ExprKind::Literal(Lit::Number(_)) => Type::Number,
+        ExprKind::Literal(Lit::Bool(_)) => Type::Bool,
+        ExprKind::Literal(Lit::String(_)) => Type::String,
+        ExprKind::Literal(Lit::Null) => Type::Null,
+        ExprKind::Var(name, _) => match env.vars.get(name) {
+            Some(t) => t.clone(),
+            None => {
+                if cx.opts.strict {
+                    cx.errors.push(TypeError {
+                        message: format!("Unknown variable '{}' has an implicit any type", name),
+                        subject: Some(name.clone()),
+                        span: expr.span,
+                        hint: Some("Declare the variable with `let` before using it.".into()),
+                    });
+                }
+                Type::Any
+            }
+        },
+        ExprKind::Assign { name, value, .. } => {
+            let vt = infer_expr(value, env, cx);
             if let Some(existing) = env.vars.get(name) {
                 if !is_compatible(&vt, existing) {
-                    errors.push(TypeError {
+                    cx.errors.push(TypeError {
                         message: format!(
                             "Cannot assign {} to variable '{}' of type {}",
                             vt, name, existing
                         ),
                         subject: Some(name.clone()),
+                        span: value.span,
                         hint: Some("Change the variable's type annotation or the assigned expression to match.".into()),
                     });
                 }
@@ -265,9 +446,9 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
             env.vars.insert(name.clone(), vt.clone());
             vt
         }
-        Expr::Binary { left, op, right } => {
-            let l = infer_expr(left, env, errors);
-            let r = infer_expr(right, env, errors);
+        ExprKind::Binary { left, op, right } => {
+            let l = infer_expr(left, env, cx);
+            let r = infer_expr(right, env, cx);
             match op {
                 BinOp::Add => {
                     // Runtime allows number+number => number, string concatenation when either side is string
@@ -276,9 +457,10 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                     } else if l == Type::String || r == Type::String {
                         Type::String
                     } else {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!("Invalid types for +: {} and {}", l, r),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Use + for numbers or for string concatenation. Convert values to string first if needed.".into()),
                         });
                         Type::Any
@@ -291,9 +473,10 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                         // be permissive when dynamic types are involved
                         Type::Number
                     } else {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!("Number operands required, got {} and {}", l, r),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Ensure both operands are numbers (e.g., use len(x) for list/string length).".into()),
                         });
                         Type::Any
@@ -306,12 +489,13 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                     {
                         Type::Bool
                     } else {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!(
                                 "Number operands required for comparison, got {} and {}",
                                 l, r
                             ),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Use <, <=, >, >= only with numbers. For other types, use == or !=.".into()),
                         });
                         Type::Bool
@@ -319,15 +503,17 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                 }
                 BinOp::And | BinOp::Or => {
                     // Allow 'any' to flow, assume bool result for control-flow typing
-                    if l == Type::Any || r == Type::Any || (l == Type::Bool && r == Type::Bool) {
+                    let any_ok = !cx.opts.strict && (l == Type::Any || r == Type::Any);
+                    if any_ok || (l == Type::Bool && r == Type::Bool) {
                         Type::Bool
                     } else {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!(
                                 "Boolean operands required for logical operation, got {} and {}",
                                 l, r
                             ),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Use && and || with booleans. Compare values to produce booleans if needed.".into()),
                         });
                         Type::Bool
@@ -335,16 +521,17 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                 }
             }
         }
-        Expr::Unary { op, expr } => {
-            let t = infer_expr(expr, env, errors);
+        ExprKind::Unary { op, expr } => {
+            let t = infer_expr(expr, env, cx);
             match op {
                 UnOp::Neg => {
                     if t == Type::Number {
                         Type::Number
                     } else {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!("Unary - expects number, got {}", t),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Negation (-) applies only to numbers.".into()),
                         });
                         Type::Any
@@ -352,12 +539,13 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                 }
                 UnOp::Not => {
                     // Allow 'any' (runtime truthiness). Treat as bool result.
-                    if t == Type::Bool || t == Type::Any {
+                    if t == Type::Bool || (t == Type::Any && !cx.opts.strict) {
                         Type::Bool
                     } else {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!("Unary ! expects bool, got {}", t),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Logical not (!) applies to booleans. Compare values to make a bool.".into()),
                         });
                         Type::Bool
@@ -365,25 +553,32 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                 }
             }
         }
-        Expr::Call { callee, args } => {
-            let ct = infer_expr(callee, env, errors);
-            let arg_ts: Vec<Type> = args.iter().map(|a| infer_expr(a, env, errors)).collect();
+        ExprKind::Call { callee, args } => {
+            if let Some(t) = infer_host_call(callee, args, expr.span, env, cx) {
+                return t;
+            }
+            let ct = infer_expr(callee, env, cx);
+            let arg_ts: Vec<Type> = args.iter().map(|a| infer_expr(a, env, cx)).collect();
+            if matches!(&callee.kind, ExprKind::Var(f, _) if f == "emit") && args.len() == 2 {
+                cx.inferred.push((args[1].span, arg_ts[1].clone()));
+            }
             match ct {
                 Type::Func(params, ret) => {
                     if params.len() != arg_ts.len() {
-                        errors.push(TypeError {
+                        cx.errors.push(TypeError {
                             message: format!(
                                 "Function expects {} args, got {}",
                                 params.len(),
                                 arg_ts.len()
                             ),
                             subject: None,
+                            span: expr.span,
                             hint: Some("Add, remove, or reorder arguments to match the function signature.".into()),
                         });
                     } else {
                         for (i, (p, a)) in params.iter().zip(arg_ts.iter()).enumerate() {
                             if !is_compatible(a, p) {
-                                errors.push(TypeError {
+                                cx.errors.push(TypeError {
                                     message: format!(
                                         "Argument {} type {} incompatible with parameter type {}",
                                         i + 1,
@@ -391,6 +586,7 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                                         p
                                     ),
                                     subject: None,
+                                    span: args[i].span,
                                     hint: Some(format!(
                                         "Change argument {} or update the parameter type to {}.",
                                         i + 1,
@@ -405,33 +601,71 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
                 _ => Type::Any,
             }
         }
-        Expr::Fn { params, ret, body } => {
+        ExprKind::Fn(f) => {
+            let FnLiteral {
+                params, ret, body, ..
+            } = &**f;
             // Create child env
             let mut child = TypeEnv {
                 vars: env.vars.clone(),
             };
             let param_types: Vec<Type> = params
                 .iter()
-                .map(|(n, t)| {
-                    let ty = t.as_ref().map(Type::from_expr).unwrap_or(Type::Any);
-                    child.vars.insert(n.clone(), ty.clone());
-                    ty
-                })
+                .map(
+                    |Param {
+                         name: n,
+                         ty: t,
+                         span,
+                         ..
+                     }| {
+                        if t.is_none() && cx.opts.strict {
+                            cx.errors.push(TypeError {
+                                message: format!("Parameter '{}' has an implicit any type", n),
+                                subject: Some(n.clone()),
+                                span: *span,
+                                hint: Some(format!(
+                                "Annotate the parameter, e.g. {}: number, or {}: any to opt out.",
+                                n, n
+                            )),
+                            });
+                        }
+                        let ty = t.as_ref().map(Type::from_expr).unwrap_or(Type::Any);
+                        child.vars.insert(n.clone(), ty.clone());
+                        ty
+                    },
+                )
                 .collect();
-            // If return type is not annotated, attempt to infer from last statement expression returns; else default Any
+            // Without a return annotation the result type is inferred from the `return`s and,
+            // as at runtime, the value of a trailing expression statement
             let annotated = ret.as_ref().map(Type::from_expr);
-            let inferred: Option<Type> = None;
-            for s in body {
-                check_stmt(s, &mut child, annotated.as_ref(), errors);
-                // Crude inference: look for explicit returns in body isn't tracked; fallback None
+            cx.returns.push(Vec::new());
+            let mut fallthrough = Type::Null;
+            for (i, s) in body.iter().enumerate() {
+                match &s.kind {
+                    StmtKind::Expr(e) if i + 1 == body.len() => {
+                        fallthrough = infer_expr(e, &mut child, cx);
+                    }
+                    _ => check_stmt(s, &mut child, annotated.as_ref(), cx),
+                }
             }
-            let ret_t = annotated.or(inferred).unwrap_or(Type::Any);
-            Type::Func(param_types, Box::new(ret_t))
+            let mut returned = cx.returns.pop().unwrap_or_default();
+            let ret_t = match annotated {
+                Some(t) => t,
+                None => {
+                    if !matches!(body.last().map(|s| &s.kind), Some(StmtKind::Return(_))) {
+                        returned.push(fallthrough);
+                    }
+                    returned.into_iter().reduce(unify).unwrap_or(Type::Null)
+                }
+            };
+            let t = Type::Func(param_types, Box::new(ret_t));
+            cx.inferred.push((expr.span, t.clone()));
+            t
         }
-        Expr::List(items) => {
+        ExprKind::List(items) => {
             let mut t: Option<Type> = None;
             for e in items {
-                let et = infer_expr(e, env, errors);
+                let et = infer_expr(e, env, cx);
                 t = Some(match t {
                     None => et,
                     Some(prev) => unify(prev, et),
@@ -439,35 +673,56 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
             }
             Type::List(Box::new(t.unwrap_or(Type::Any)))
         }
-        Expr::Map(props) => {
+        ExprKind::Map(props) => {
             // Infer precise record type with field names
             let mut fields: BTreeMap<String, Type> = BTreeMap::new();
             for (k, e) in props {
-                let et = infer_expr(e, env, errors);
+                let et = infer_expr(e, env, cx);
                 fields.insert(k.clone(), et);
             }
             Type::Record(fields)
         }
-        Expr::Index { target, index } => {
-            let tt = infer_expr(target, env, errors);
-            let it = infer_expr(index, env, errors);
+        ExprKind::Index { target, index } => {
+            let tt = infer_expr(target, env, cx);
+            let it = infer_expr(index, env, cx);
             match (tt, it) {
                 (Type::List(inner), Type::Number) => *inner,
                 (Type::Map(inner), Type::String) => *inner,
                 (t, i) => {
-                    errors.push(TypeError {
+                    cx.errors.push(TypeError {
                         message: format!("Invalid index types: target {} indexed by {}", t, i),
                         subject: None,
+                        span: expr.span,
                         hint: Some("Lists use numeric indexes; maps/records use string keys or .field access.".into()),
                     });
                     Type::Any
                 }
             }
         }
-        Expr::Field { target, name } => {
-            let tt = infer_expr(target, env, errors);
+        ExprKind::Field { target, name } => {
+            let tt = infer_expr(target, env, cx);
             match tt {
-                Type::Record(fields) => fields.get(name).cloned().unwrap_or(Type::Any),
+                Type::Record(fields) => match fields.get(name) {
+                    Some(t) => t.clone(),
+                    None => {
+                        if cx.opts.strict {
+                            cx.errors.push(TypeError {
+                                message: format!(
+                                    "Field '{}' does not exist on {}",
+                                    name,
+                                    Type::Record(fields.clone())
+                                ),
+                                subject: Some(name.clone()),
+                                span: expr.span,
+                                hint: Some(
+                                    "Add the field to the record type or fix the field name."
+                                        .into(),
+                                ),
+                            });
+                        }
+                        Type::Any
+                    }
+                },
                 Type::Map(inner) => *inner,
                 _ => Type::Any,
             }
@@ -475,6 +730,77 @@ fn infer_expr(expr: &Expr, env: &mut TypeEnv, errors: &mut Vec<TypeError>) -> Ty
     }
 }
 
+// host("op", payload) with a declared op: check the payload and return the declared result.
+// Returns None when the call is not a host call with a literal op name.
+fn infer_host_call(
+    callee: &Expr,
+    args: &[Expr],
+    span: Span,
+    env: &mut TypeEnv,
+    cx: &mut Ctx,
+) -> Option<Type> {
+    let decls = cx.opts.decls.clone()?;
+    if decls.is_empty() || !matches!(&callee.kind, ExprKind::Var(n, _) if n == "host") {
+        return None;
+    }
+    let op = match args.first().map(|a| &a.kind) {
+        Some(ExprKind::Literal(Lit::String(op))) => op.clone(),
+        _ => return None,
+    };
+    let payload = match args.get(1) {
+        Some(e) => infer_expr(e, env, cx),
+        None => Type::Null,
+    };
+    for extra in args.iter().skip(2) {
+        infer_expr(extra, env, cx);
+    }
+    let Some(decl) = decls.host_ops.get(&op) else {
+        cx.errors.push(TypeError {
+            message: format!("Unknown host op '{}'", op),
+            subject: Some(op.clone()),
+            span: args[0].span,
+            hint: Some("Check the op name or declare it in a .qkd file.".into()),
+        });
+        return Some(Type::Any);
+    };
+    let expected = decl.param_types().into_iter().next().unwrap_or(Type::Null);
+    if args.len() > 2 || !is_compatible(&payload, &expected) {
+        let missing: Vec<&String> = match (&payload, &expected) {
+            (Type::Record(have), Type::Record(want)) => {
+                want.keys().filter(|k| !have.contains_key(*k)).collect()
+            }
+            _ => Vec::new(),
+        };
+        let hint = if missing.is_empty() {
+            format!("Declared as {}", decl.signature(DeclKind::HostOp))
+        } else {
+            let names: Vec<&str> = missing.iter().map(|s| s.as_str()).collect();
+            format!("Missing payload field(s): {}", names.join(", "))
+        };
+        cx.errors.push(TypeError {
+            message: format!(
+                "Host op '{}' expects payload {}, got {}",
+                op, expected, payload
+            ),
+            subject: Some(op.clone()),
+            span: args.get(1).map(|a| a.span).unwrap_or(span),
+            hint: Some(hint),
+        });
+    }
+    Some(decl.ret_type())
+}
+
+// Signature of a function literal from its annotations; missing ones are any.
+fn fn_signature(params: &[Param], ret: &Option<TypeExpr>) -> Type {
+    Type::Func(
+        params
+            .iter()
+            .map(|p| p.ty.as_ref().map(Type::from_expr).unwrap_or(Type::Any))
+            .collect(),
+        Box::new(ret.as_ref().map(Type::from_expr).unwrap_or(Type::Any)),
+    )
+}
+
 fn unify(a: Type, b: Type) -> Type {
     if a == b {
         return a;
@@ -1,7 +1,8 @@
-use crate::ast::{Stmt, TypeExpr};
-use std::cell::RefCell;
+use crate::ast::{FnLiteral, Span, TypeExpr};
+use std::cell::{Cell, RefCell};
 use std::fmt::{Display, Formatter};
 use std::rc::Rc;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub enum Value {
@@ -9,18 +10,39 @@ pub enum Value {
     Bool(bool),
     String(String),
     Null,
-    List(Vec<Value>),
-    Map(std::collections::BTreeMap<String, Value>),
+    List(List),
+    Map(Map),
     Function(Rc<Function>),
 }
 
+// Persistent collections: a clone shares its structure with the original, and pushing,
+// setting or removing copies only the path to the changed entry, so value semantics cost
+// O(log n) per update instead of a copy of the whole collection
+pub type List = im_rc::Vector<Value>;
+pub type Map = im_rc::OrdMap<String, Value>;
+
+thread_local! {
+    static COLLECTION_CLONES: Cell<u64> = const { Cell::new(0) };
+}
+
+// Lists and maps cloned on this thread so far. A clone shares structure with the original,
+// so it bumps a reference count rather than allocating. The interpreter only counts clones
+// while hooks, such as a profiler, are installed.
+pub fn collection_clones() -> u64 {
+    COLLECTION_CLONES.with(|c| c.get())
+}
+
+pub(crate) fn count_collection_clone() {
+    COLLECTION_CLONES.with(|c| c.set(c.get() + 1));
+}
+
 #[derive(Clone)]
 pub enum Function {
     User {
-        params: Vec<(String, Option<TypeExpr>)>,
-        ret: Option<TypeExpr>,
-        body: Vec<Stmt>,
+        // The literal's parameters and body, shared with the AST rather than copied
+        literal: Arc<FnLiteral>,
         env: EnvRef,
+        span: Span,
     },
     Native {
         name: String,
@@ -43,6 +65,38 @@ impl Value {
             Value::Function(_) => true,
         }
     }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::Null => "null",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Function(_) => "fn",
+        }
+    }
+
+    // Whether the value fits a type annotation. Mirrors the checker: null is accepted
+    // everywhere, records need their fields present and function signatures are not inspected.
+    pub fn conforms_to(&self, t: &TypeExpr) -> bool {
+        match (self, t) {
+            (_, TypeExpr::Any) | (Value::Null, _) => true,
+            (Value::Number(_), TypeExpr::Number)
+            | (Value::String(_), TypeExpr::String)
+            | (Value::Bool(_), TypeExpr::Bool)
+            | (Value::Function(_), TypeExpr::Func(..)) => true,
+            (Value::List(items), TypeExpr::List(inner)) => {
+                items.iter().all(|v| v.conforms_to(inner))
+            }
+            (Value::Map(m), TypeExpr::Map(inner)) => m.values().all(|v| v.conforms_to(inner)),
+            (Value::Map(m), TypeExpr::Record(fields)) => fields
+                .iter()
+                .all(|(k, ft)| m.get(k).map(|v| v.conforms_to(ft)).unwrap_or(false)),
+            _ => false,
+        }
+    }
 }
 
 impl Display for Value {
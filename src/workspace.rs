@@ -0,0 +1,388 @@
+// SPDX-License-Identifier: MIT
+// Workspace index for the language server: every script under the workspace roots with its
+// parse, scope analysis and type-check result cached. Scripts loaded into one interpreter
+// share top-level names, so a name a script uses without declaring it makes the script
+// depend on the scripts that declare it; the checker sees those scripts' types, and a
+// change to a script re-checks everything that depends on it. The `on`/`emit` sites found
+// in each script make up the workspace's event graph.
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use walkdir::WalkDir;
+
+use crate::ast::Program;
+use crate::check::{self, Diagnostic};
+use crate::events::{self, CallSite, EventGraph, EventSite, FileEvents, SiteKind};
+use crate::parser::{ParseError, Parser};
+use crate::stdlib::BUILTINS;
+use crate::symbols::{self, Reference, Symbol, SymbolTable};
+use crate::typecheck::{self, CheckOptions, Type, TypeCheckResult};
+
+pub struct IndexedFile {
+    pub source: String,
+    pub parsed: Result<Program, ParseError>,
+    // Empty when the file does not parse
+    pub symbols: SymbolTable,
+    pub types: Option<TypeCheckResult>,
+    // Names used without being declared in the file, the builtins or the host natives
+    pub free: BTreeSet<String>,
+    // Event and call sites, with the payload types from the last check
+    pub events: FileEvents,
+}
+
+impl IndexedFile {
+    pub fn program(&self) -> Option<&Program> {
+        self.parsed.as_ref().ok()
+    }
+
+    // Top-level declarations
+    pub fn globals(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.symbols.iter().filter(|s| s.scope == 0)
+    }
+
+    // The parse error, or the type checker's findings
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match (&self.parsed, &self.types) {
+            (Err(e), _) => vec![check::parse_diagnostic(&self.source, e)],
+            (Ok(_), Some(tc)) => check::type_diagnostics(tc),
+            (Ok(_), None) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WorkspaceIndex {
+    options: CheckOptions,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+// Every .qk file under the given directories, skipping hidden directories and `target`
+pub fn find_script_files(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for root in roots {
+        let walker = WalkDir::new(root)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| {
+                let name = e.file_name().to_string_lossy();
+                e.depth() == 0 || !(name.starts_with('.') || name == "target")
+            });
+        for entry in walker.filter_map(Result::ok) {
+            if entry.file_type().is_file() && entry.path().extension().is_some_and(|e| e == "qk") {
+                files.push(entry.path().to_path_buf());
+            }
+        }
+    }
+    files
+}
+
+impl WorkspaceIndex {
+    pub fn new(options: CheckOptions) -> Self {
+        Self {
+            options,
+            files: BTreeMap::new(),
+        }
+    }
+
+    // Index every script under the roots, replacing what was indexed before; returns the
+    // number of scripts read
+    pub fn scan(&mut self, roots: &[PathBuf]) -> usize {
+        self.load_files(find_script_files(roots))
+    }
+
+    // Index exactly the given scripts, replacing what was indexed before; returns the
+    // number of scripts read
+    pub fn load_files(&mut self, paths: Vec<PathBuf>) -> usize {
+        self.files.clear();
+        for path in paths {
+            if let Ok(source) = fs::read_to_string(&path) {
+                let file = self.parse(source);
+                self.files.insert(path, file);
+            }
+        }
+        self.recheck_all();
+        self.files.len()
+    }
+
+    // Re-check every script, e.g. after the declarations or strict setting changed
+    pub fn set_options(&mut self, options: CheckOptions) {
+        self.options = options;
+        let all: Vec<PathBuf> = self.files.keys().cloned().collect();
+        for path in all {
+            let source = self.files[&path].source.clone();
+            let file = self.parse(source);
+            self.files.insert(path, file);
+        }
+        self.recheck_all();
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&IndexedFile> {
+        self.files.get(path)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&PathBuf, &IndexedFile)> {
+        self.files.iter()
+    }
+
+    // Set the text of a script (an edit in the editor, or the file on disk); returns the
+    // scripts re-checked, plus those emitting or handling an event whose `on`/`emit` sites
+    // in this script changed. None when the text did not change.
+    pub fn update(&mut self, path: &Path, source: String) -> Vec<PathBuf> {
+        if self.files.get(path).is_some_and(|f| f.source == source) {
+            return Vec::new();
+        }
+        // Scripts that used what the old text declared may lose it
+        let before = self.dependents(path);
+        let old_events = self.event_sites_of(path);
+        let file = self.parse(source);
+        self.files.insert(path.to_path_buf(), file);
+        let mut checked = self.recheck_from(path, before);
+        let changed = old_events
+            .symmetric_difference(&self.event_sites_of(path))
+            .map(|(event, _)| event.clone())
+            .collect();
+        checked.extend(self.event_peers(&changed));
+        checked.sort();
+        checked.dedup();
+        checked
+    }
+
+    // Re-read a script from disk, dropping it when it no longer exists
+    pub fn reload(&mut self, path: &Path) -> Vec<PathBuf> {
+        match fs::read_to_string(path) {
+            Ok(source) => self.update(path, source),
+            Err(_) => self.remove(path),
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Vec<PathBuf> {
+        let before = self.dependents(path);
+        let old_events = self.event_sites_of(path);
+        if self.files.remove(path).is_none() {
+            return Vec::new();
+        }
+        let mut checked = Vec::new();
+        for dep in before {
+            checked.extend(self.recheck_from(&dep, BTreeSet::new()));
+        }
+        let changed = old_events.into_iter().map(|(event, _)| event).collect();
+        checked.extend(self.event_peers(&changed));
+        checked.sort();
+        checked.dedup();
+        checked
+    }
+
+    // The events a script emits and handles
+    fn event_sites_of(&self, path: &Path) -> BTreeSet<(String, bool)> {
+        self.files
+            .get(path)
+            .map(|f| {
+                f.events
+                    .sites
+                    .iter()
+                    .map(|s| (s.event.clone(), s.kind == SiteKind::Handler))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Scripts that emit or handle any of `events`
+    fn event_peers(&self, events: &BTreeSet<String>) -> Vec<PathBuf> {
+        if events.is_empty() {
+            return Vec::new();
+        }
+        self.files
+            .iter()
+            .filter(|(_, f)| f.events.sites.iter().any(|s| events.contains(&s.event)))
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    // Scripts declaring a top-level name this script uses without declaring it
+    pub fn dependencies(&self, path: &Path) -> BTreeSet<PathBuf> {
+        let Some(file) = self.files.get(path) else {
+            return BTreeSet::new();
+        };
+        self.files
+            .iter()
+            .filter(|(p, other)| {
+                p.as_path() != path && other.globals().any(|g| file.free.contains(&g.name))
+            })
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    // Scripts that use a top-level name this script declares
+    pub fn dependents(&self, path: &Path) -> BTreeSet<PathBuf> {
+        let Some(file) = self.files.get(path) else {
+            return BTreeSet::new();
+        };
+        let declared: BTreeSet<&str> = file.globals().map(|g| g.name.as_str()).collect();
+        self.files
+            .iter()
+            .filter(|(p, other)| {
+                p.as_path() != path && other.free.iter().any(|n| declared.contains(n.as_str()))
+            })
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    // Top-level declarations whose names contain `query`, ignoring case
+    pub fn symbols(&self, query: &str) -> Vec<(&Path, &Symbol)> {
+        let query = query.to_lowercase();
+        self.files
+            .iter()
+            .flat_map(|(p, f)| f.globals().map(move |g| (p.as_path(), g)))
+            .filter(|(_, g)| g.name.to_lowercase().contains(&query))
+            .collect()
+    }
+
+    // Top-level declarations of a name in any script
+    pub fn definitions(&self, name: &str) -> Vec<(&Path, &Symbol)> {
+        self.files
+            .iter()
+            .flat_map(|(p, f)| f.globals().map(move |g| (p.as_path(), g)))
+            .filter(|(_, g)| g.name == name)
+            .collect()
+    }
+
+    // Every occurrence of a top-level name: its declarations and the uses that resolve to
+    // them or to nothing in their own script
+    pub fn references(&self, name: &str) -> Vec<(&Path, &Reference)> {
+        let mut out = Vec::new();
+        for (p, f) in &self.files {
+            for r in f.symbols.refs.iter().filter(|r| r.name == name) {
+                let global = match r.symbol {
+                    Some(id) => f.symbols.symbols[id].scope == 0,
+                    None => true,
+                };
+                if global {
+                    out.push((p.as_path(), r));
+                }
+            }
+        }
+        out
+    }
+
+    // Every site of an event in any script
+    pub fn event_sites(&self, event: &str) -> Vec<(&Path, &EventSite)> {
+        self.files
+            .iter()
+            .flat_map(|(p, f)| f.events.sites.iter().map(move |s| (p.as_path(), s)))
+            .filter(|(_, s)| s.event == event)
+            .collect()
+    }
+
+    // Every call of a function by name in any script
+    pub fn calls_to(&self, name: &str) -> Vec<(&Path, &CallSite)> {
+        self.files
+            .iter()
+            .flat_map(|(p, f)| f.events.calls.iter().map(move |c| (p.as_path(), c)))
+            .filter(|(_, c)| c.callee == name)
+            .collect()
+    }
+
+    pub fn event_graph(&self) -> EventGraph {
+        EventGraph::build(self.files.iter().map(|(p, f)| (p.as_path(), &f.events)))
+    }
+
+    fn parse(&self, source: String) -> IndexedFile {
+        let parsed = Parser::new(&source).parse_program();
+        let symbols = match &parsed {
+            Ok(p) => symbols::analyze(p),
+            Err(_) => SymbolTable::default(),
+        };
+        let native = |n: &str| {
+            self.options
+                .decls
+                .as_ref()
+                .is_some_and(|d| d.natives.contains_key(n))
+        };
+        let free = symbols
+            .refs
+            .iter()
+            .filter(|r| r.symbol.is_none())
+            .filter(|r| !BUILTINS.contains(&r.name.as_str()) && !native(&r.name))
+            .map(|r| r.name.clone())
+            .collect();
+        IndexedFile {
+            source,
+            parsed,
+            symbols,
+            types: None,
+            free,
+            events: FileEvents::default(),
+        }
+    }
+
+    // Scripts are checked in path order, so a second pass gives each script the types of
+    // dependencies that come after it
+    fn recheck_all(&mut self) {
+        let all: Vec<PathBuf> = self.files.keys().cloned().collect();
+        for path in &all {
+            self.recheck(path);
+        }
+        for path in &all {
+            if !self.dependencies(path).is_empty() {
+                self.recheck(path);
+            }
+        }
+    }
+
+    // Re-check a script and, transitively, the scripts depending on it (plus `also`)
+    fn recheck_from(&mut self, path: &Path, also: BTreeSet<PathBuf>) -> Vec<PathBuf> {
+        let mut queue: VecDeque<PathBuf> = VecDeque::from([path.to_path_buf()]);
+        queue.extend(also);
+        let mut checked = Vec::new();
+        while let Some(p) = queue.pop_front() {
+            if checked.contains(&p) || !self.files.contains_key(&p) {
+                continue;
+            }
+            self.recheck(&p);
+            queue.extend(self.dependents(&p));
+            checked.push(p);
+        }
+        checked
+    }
+
+    fn recheck(&mut self, path: &Path) {
+        let globals = self.globals_for(path);
+        let Some(file) = self.files.get_mut(path) else {
+            return;
+        };
+        let Ok(program) = &file.parsed else {
+            file.types = None;
+            return;
+        };
+        let mut opts = self.options.clone();
+        opts.strict |= typecheck::has_strict_pragma(&file.source);
+        opts.globals = Some(Arc::new(globals));
+        let types = typecheck::check_program_with_options(program, &opts);
+        file.events = events::analyze(program, Some(&types));
+        file.types = Some(types);
+    }
+
+    // Types of the names a script takes from the scripts it depends on
+    pub fn globals_for(&self, path: &Path) -> BTreeMap<String, Type> {
+        let mut globals = BTreeMap::new();
+        let Some(file) = self.files.get(path) else {
+            return globals;
+        };
+        for (p, other) in &self.files {
+            if p.as_path() == path {
+                continue;
+            }
+            let Some(tc) = &other.types else {
+                continue;
+            };
+            for g in other.globals().filter(|g| file.free.contains(&g.name)) {
+                if let Some(t) = tc.env.vars.get(&g.name) {
+                    globals.entry(g.name.clone()).or_insert_with(|| t.clone());
+                }
+            }
+        }
+        globals
+    }
+}
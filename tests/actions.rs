@@ -0,0 +1,98 @@
+use questicle::actions::{apply, code_actions, ActionKind, CodeAction};
+use questicle::ast::Span;
+use questicle::typecheck::CheckOptions;
+use questicle::Parser;
+
+fn actions_at(src: &str, range: Span) -> Vec<CodeAction> {
+    code_actions(src, range, &CheckOptions::default())
+}
+
+fn cursor(line: usize, col: usize) -> Span {
+    Span::new(line, col, line, col)
+}
+
+// The source after applying the action with the given title
+fn fixed(src: &str, range: Span, title: &str) -> String {
+    let actions = actions_at(src, range);
+    let action = actions
+        .iter()
+        .find(|a| a.title == title)
+        .unwrap_or_else(|| panic!("no action {:?} in {:?}", title, actions));
+    let out = apply(src, &action.edits).expect("edits apply");
+    Parser::new(&out)
+        .parse_program()
+        .expect("fixed source parses");
+    out
+}
+
+#[test]
+fn annotations_take_the_inferred_type() {
+    let src = "let n: string = 5;\n";
+    assert_eq!(
+        fixed(src, cursor(1, 5), "Change the annotation to `number`"),
+        "let n: number = 5;\n"
+    );
+    assert!(actions_at(src, cursor(1, 5))[0].preferred);
+
+    let src = "let inc: any = fn(x: number) {\n  return x + 1;\n};\n";
+    assert_eq!(
+        fixed(src, cursor(1, 6), "Add return type `-> number`"),
+        "let inc: any = fn(x: number) -> number {\n  return x + 1;\n};\n"
+    );
+    assert_eq!(
+        fixed(
+            src,
+            cursor(1, 6),
+            "Change the annotation to `fn(number) -> number`"
+        ),
+        "let inc: fn(number) -> number = fn(x: number) {\n  return x + 1;\n};\n"
+    );
+    // Nothing to offer away from the declaration head
+    assert!(actions_at(src, cursor(2, 5)).is_empty());
+}
+
+#[test]
+fn conditions_are_compared_explicitly() {
+    let src = "let n: number = 3;\nlet s: string = \"\";\nif (n) {\n  print(n);\n}\nwhile (s) {\n  s = \"\";\n}\n";
+    let out = fixed(src, cursor(3, 5), "Compare explicitly: `n != 0`");
+    assert!(out.contains("if (n != 0) {"));
+    let out = fixed(src, cursor(6, 8), "Compare explicitly: `s != \"\"`");
+    assert!(out.contains("while (s != \"\") {"));
+}
+
+#[test]
+fn missing_names_can_be_declared() {
+    let src = "fn main() -> null {\n  print(score);\n  heal(score, 3);\n}\n";
+    assert_eq!(
+        fixed(src, cursor(2, 9), "Create variable 'score'"),
+        "fn main() -> null {\n  let score: any = null;\n  print(score);\n  heal(score, 3);\n}\n"
+    );
+    assert_eq!(
+        fixed(src, cursor(3, 4), "Create function 'heal'"),
+        "fn heal(score: any, arg2: any) -> any {\n  return null;\n}\n\nfn main() -> null {\n  print(score);\n  heal(score, 3);\n}\n"
+    );
+    // Builtins are not missing
+    assert!(!actions_at(src, cursor(2, 4))
+        .iter()
+        .any(|a| a.title.contains("print")));
+}
+
+#[test]
+fn expressions_are_extracted_into_lets() {
+    let src = "let price: number = 4;\nlet total: number = price * 2 + 1;\n";
+    let actions = actions_at(src, Span::new(2, 21, 2, 30));
+    let extract = actions
+        .iter()
+        .find(|a| a.kind == ActionKind::Extract)
+        .expect("extract action");
+    assert_eq!(
+        apply(src, &extract.edits).unwrap(),
+        "let price: number = 4;\nlet value: number = price * 2;\nlet total: number = value + 1;\n"
+    );
+
+    // Hoisting a loop condition would evaluate it once
+    let src = "let i: number = 0;\nwhile (i < 3) {\n  i = i + 1;\n}\n";
+    assert!(!actions_at(src, Span::new(2, 8, 2, 13))
+        .iter()
+        .any(|a| a.kind == ActionKind::Extract));
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use questicle::check::{check_source, Severity};
+use questicle::typecheck::CheckOptions;
+
+// A fresh directory under the system temp dir holding the given files
+fn scratch_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qk-check-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    for (file, content) in files {
+        fs::write(dir.join(file), content).expect("write scratch file");
+    }
+    dir
+}
+
+fn qk_check(args: &[&str]) -> (i32, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_qk"))
+        .arg("check")
+        .args(args)
+        .output()
+        .expect("run qk");
+    (
+        out.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&out.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn diagnostics_carry_locations_and_severity() {
+    let src = "let x: number = 1;\nlet y: string = x;\n";
+    let diags = check_source(src, &CheckOptions::default());
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].severity, Severity::Warning);
+    assert_eq!((diags[0].span.line, diags[0].span.col), (2, 17));
+
+    let strict = format!("// qk: strict\n{}", src);
+    let diags = check_source(&strict, &CheckOptions::default());
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert_eq!(diags[0].span.line, 3);
+}
+
+#[test]
+fn parse_errors_are_errors() {
+    let diags = check_source("let x: number = ;\n", &CheckOptions::default());
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert_eq!(diags[0].source, "parse");
+    assert_eq!(diags[0].span.line, 1);
+}
+
+#[test]
+fn exit_code_follows_errors_and_warnings() {
+    let dir = scratch_dir(
+        "exit",
+        &[
+            ("ok.qk", "let x: number = 1;\n"),
+            ("warn.qk", "let x: number = 1;\nlet y: string = x;\n"),
+        ],
+    );
+    let dir_s = dir.to_str().unwrap();
+
+    let (code, out) = qk_check(&[dir_s]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(out.contains("warn.qk:2:17: warning:"), "{}", out);
+    assert!(
+        out.contains("Checked 2 file(s): 0 error(s), 1 warning(s)"),
+        "{}",
+        out
+    );
+
+    assert_eq!(qk_check(&["--deny-warnings", dir_s]).0, 1);
+    assert_eq!(qk_check(&["--strict", dir_s]).0, 1);
+    assert_eq!(qk_check(&[dir.join("ok.qk").to_str().unwrap()]).0, 0);
+    assert_eq!(qk_check(&[dir.join("missing.qk").to_str().unwrap()]).0, 2);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn json_report_lists_every_diagnostic() {
+    let dir = scratch_dir(
+        "json",
+        &[
+            (
+                "engine.qkd",
+                "host spawn(payload: record { kind: string }) -> number;\n",
+            ),
+            (
+                "a.qk",
+                "let id: number = host(\"spawn\", { name: \"x\" });\nlet z: number = ;\n",
+            ),
+            ("b.qk", "let id: number = host(\"despawn\", 1);\n"),
+        ],
+    );
+    let (code, out) = qk_check(&["--json", dir.to_str().unwrap()]);
+    assert_eq!(code, 1, "{}", out);
+    let report: serde_json::Value = serde_json::from_str(&out).expect("valid json");
+    assert_eq!(report["files"], 2);
+    assert_eq!(report["errors"], 1);
+    assert_eq!(report["warnings"], 1);
+    let diags = report["diagnostics"].as_array().unwrap();
+    assert!(diags[0]["path"].as_str().unwrap().ends_with("a.qk"));
+    assert_eq!(diags[0]["source"], "parse");
+    assert_eq!(diags[0]["span"]["line"], 2);
+    assert!(diags[1]["message"]
+        .as_str()
+        .unwrap()
+        .contains("Unknown host op 'despawn'"));
+    let _ = fs::remove_dir_all(&dir);
+}
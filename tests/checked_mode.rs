@@ -0,0 +1,80 @@
+use questicle::eval::RuntimeError;
+use questicle::{Host, Interpreter, Parser};
+
+fn run_checked(src: &str) -> Result<(), RuntimeError> {
+    let program = Parser::new(src).parse_program().expect("parse");
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.checked = true;
+    interp.eval(program).map(|_| ())
+}
+
+fn type_error(src: &str) -> (String, usize, usize) {
+    match run_checked(src) {
+        Err(RuntimeError::Type { message, span }) => (message, span.line, span.col),
+        other => panic!(
+            "expected type error, got {:?}",
+            other.map_err(|e| e.to_string())
+        ),
+    }
+}
+
+#[test]
+fn host_result_checked_against_let() {
+    // The default host answers with a map, not a number
+    let (msg, line, col) = type_error("let ok: bool = true;\nlet hp: number = host(\"x\", null);");
+    assert!(
+        msg.contains("variable 'hp' is annotated as number but got map"),
+        "{}",
+        msg
+    );
+    // The error points at the annotation
+    assert_eq!((line, col), (2, 9));
+}
+
+#[test]
+fn assignments_respect_declared_type() {
+    let (msg, line, _) = type_error("let n: number = 1;\nn = \"two\";");
+    assert!(msg.contains("variable 'n'"), "{}", msg);
+    assert_eq!(line, 1);
+}
+
+#[test]
+fn arguments_and_returns_checked() {
+    let (msg, _, col) = type_error("fn f(a: any, b: number) -> number { return b; }\nf(1, \"x\");");
+    assert!(msg.contains("parameter 'b'"), "{}", msg);
+    assert_eq!(col, 17);
+    let (msg, line, col) = type_error("let x: any = 0;\nfn g() -> string { return 1; }\ng();");
+    assert!(
+        msg.contains("return value is annotated as string"),
+        "{}",
+        msg
+    );
+    assert_eq!((line, col), (2, 11));
+    let err = run_checked("let hp: list<number> = \"full\";")
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().ends_with("(annotation at line 1, col 9)"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn records_and_lists_are_structural() {
+    assert!(run_checked("let r: record { ok: bool, op: string } = host(\"x\", null);").is_ok());
+    let (msg, _, _) = type_error("let r: record { id: number } = host(\"x\", null);");
+    assert!(msg.contains("record{id:number}"), "{}", msg);
+    let (msg, _, _) = type_error("let xs: list<number> = [1, \"a\"];");
+    assert!(msg.contains("list<number>"), "{}", msg);
+    assert!(run_checked("let x: number = null; let m: map<string> = { a: \"b\" };").is_ok());
+}
+
+#[test]
+fn unchecked_mode_ignores_annotations() {
+    let program = Parser::new("let hp: number = \"full\";")
+        .parse_program()
+        .expect("parse");
+    let mut interp = Interpreter::with_host(Host::default());
+    assert!(interp.eval(program).is_ok());
+}
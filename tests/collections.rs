@@ -0,0 +1,56 @@
+use questicle::output::Capture;
+use questicle::value::{List, Value};
+use questicle::{Host, Interpreter, Parser};
+
+fn run(src: &str) -> String {
+    let capture = Capture::default();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.set_output(capture.clone());
+    interp
+        .eval(Parser::new(src).parse_program().unwrap())
+        .unwrap();
+    capture.text()
+}
+
+#[test]
+fn updates_leave_other_copies_alone() {
+    let out = run(r#"let bag: list<string> = ["potion"];
+let kept: list<string> = bag;
+bag = push(bag, "herb");
+let hero: map<number> = { hp: 10 };
+let before: map<number> = hero;
+hero = set(hero, "hp", 7);
+hero = set(hero, "mp", 3);
+print(kept, bag, pop(bag), bag);
+print(before, hero, set(bag, 0, "elixir"), bag);
+"#);
+    assert_eq!(
+        out,
+        "[\"potion\"] [\"potion\", \"herb\"] herb [\"potion\", \"herb\"]\n{hp: 10} {hp: 7, mp: 3} [\"elixir\", \"herb\"] [\"potion\", \"herb\"]\n"
+    );
+}
+
+#[test]
+fn long_lists_grow_by_sharing() {
+    let out = run(r#"let log: list<number> = [];
+let snapshots: list<list<number>> = [];
+let i: number = 0;
+while (i < 20000) {
+  log = push(log, i);
+  if (i % 5000 == 0) { snapshots = push(snapshots, log); }
+  i = i + 1;
+}
+print(len(log), log[19999], len(snapshots), len(snapshots[1]), len(snapshots[3]));
+"#);
+    assert_eq!(out, "20000 19999 4 5001 15001\n");
+
+    let list: List = (0..1000).map(|n| Value::Number(n as f64)).collect();
+    let copy = Value::List(list.clone());
+    let mut grown = list;
+    grown.push_back(Value::Null);
+    match copy {
+        Value::List(items) => assert_eq!(items.len(), 1000),
+        _ => unreachable!(),
+    }
+    assert_eq!(grown.len(), 1001);
+}
@@ -0,0 +1,73 @@
+use questicle::compiled::CompileError;
+use questicle::host::MockHost;
+use questicle::value::Value;
+use questicle::{CompiledScript, Host};
+
+const GUARD: &str = r#"let hp: number = 20;
+let name: string = "guard";
+fn hurt(amount: number) -> number {
+  hp = hp - amount;
+  return hp;
+}
+fn shout() -> any {
+  return host("say", name + " has " + hp);
+}
+"#;
+
+#[test]
+fn instances_keep_their_own_globals_and_host() {
+    let script = CompiledScript::compile(GUARD).unwrap();
+    let first_host = MockHost::default();
+    let second_host = MockHost::default();
+    let mut first = script.instantiate(first_host.host()).unwrap();
+    let mut second = script.instantiate(second_host.host()).unwrap();
+    assert!(std::ptr::eq(
+        first.script().program(),
+        second.script().program()
+    ));
+
+    first.call("hurt", vec![Value::Number(5.0)]).unwrap();
+    first.call("hurt", vec![Value::Number(5.0)]).unwrap();
+    second.call("shout", Vec::new()).unwrap();
+    first.call("shout", Vec::new()).unwrap();
+    assert_eq!(first.get("hp").unwrap().to_string(), "10");
+    assert_eq!(second.get("hp").unwrap().to_string(), "20");
+    let said = |mock: &MockHost| -> Vec<String> {
+        mock.calls("say").iter().map(|p| p.to_string()).collect()
+    };
+    assert_eq!(said(&first_host), vec!["\"guard has 10\""]);
+    assert_eq!(said(&second_host), vec!["\"guard has 20\""]);
+}
+
+#[test]
+fn calls_to_missing_functions_fail() {
+    let script = CompiledScript::compile(GUARD).unwrap();
+    let mut npc = script.instantiate(Host::default()).unwrap();
+    let err = npc.call("patrol", Vec::new()).err().unwrap();
+    assert_eq!(err.to_string(), "Undefined function 'patrol'");
+    let err = npc.call("name", Vec::new()).err().unwrap();
+    assert_eq!(err.to_string(), "Undefined function 'name'");
+    // A failed call leaves the instance usable
+    assert_eq!(
+        npc.call("hurt", vec![Value::Number(1.0)])
+            .unwrap()
+            .to_string(),
+        "19"
+    );
+}
+
+#[test]
+fn compiling_checks_the_script_once() {
+    let err = CompiledScript::compile("let x: number = ;").unwrap_err();
+    assert!(matches!(err, CompileError::Parse(_)));
+
+    let sloppy = "let hp: number = \"full\";\n";
+    let script = CompiledScript::compile(sloppy).unwrap();
+    assert_eq!(script.warnings().len(), 1);
+    let err = CompiledScript::compile(&format!("// qk: strict\n{}", sloppy)).unwrap_err();
+    let CompileError::Type(errors) = &err else {
+        panic!("type errors");
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(err.to_string().starts_with("Type error: Type mismatch"));
+}
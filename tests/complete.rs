@@ -0,0 +1,109 @@
+use questicle::complete::{complete, Completion, CompletionKind, Completions};
+use questicle::typecheck::CheckOptions;
+
+// Completions where `|` marks the cursor
+fn at_cursor(src: &str) -> Completions {
+    let (line, col) = src
+        .lines()
+        .enumerate()
+        .find_map(|(i, l)| l.find('|').map(|c| (i + 1, l[..c].chars().count() + 1)))
+        .expect("cursor");
+    complete(&src.replace('|', ""), line, col, &CheckOptions::default())
+}
+
+fn labels(items: &[Completion]) -> Vec<(&str, &str)> {
+    items
+        .iter()
+        .map(|c| (c.label.as_str(), c.detail.as_str()))
+        .collect()
+}
+
+const HERO: &str = r#"let hero: record { name: string, hp: number, bag: list<record { item: string }> } = { name: "Ayla", hp: 10, bag: [] };
+"#;
+
+#[test]
+fn fields_after_a_dot() {
+    let src = format!("{}print(hero.h|", HERO);
+    let Completions::Fields(items) = at_cursor(&src) else {
+        panic!("expected fields");
+    };
+    assert_eq!(
+        labels(&items),
+        vec![
+            ("bag", "list<record{item:string}>"),
+            ("hp", "number"),
+            ("name", "string")
+        ]
+    );
+
+    let src = format!("{}fn f() -> null {{\n  print(hero.bag[0].|\n", HERO);
+    let Completions::Fields(items) = at_cursor(&src) else {
+        panic!("expected fields");
+    };
+    assert_eq!(labels(&items), vec![("item", "string")]);
+
+    let Completions::Fields(items) = at_cursor("let n: number = 1;\nprint(n.|") else {
+        panic!("expected fields");
+    };
+    assert!(items.is_empty());
+}
+
+#[test]
+fn missing_fields_of_record_literals() {
+    let src = r#"let pos: record { x: number, y: number } = { x: 1, |"#;
+    let Completions::MissingFields(items) = at_cursor(src) else {
+        panic!("expected missing fields");
+    };
+    assert_eq!(labels(&items), vec![("y", "number")]);
+    assert_eq!(items[0].insert_text.as_deref(), Some("y: "));
+
+    // Nested literal, and an assignment to a variable of record type
+    let src = format!("{}hero = {{ name: \"B\", bag: [], |", HERO);
+    let Completions::MissingFields(items) = at_cursor(&src) else {
+        panic!("expected missing fields");
+    };
+    assert_eq!(labels(&items), vec![("hp", "number")]);
+    let src = r#"let e: record { at: record { x: number, y: number } } = { at: { |"#;
+    let Completions::MissingFields(items) = at_cursor(src) else {
+        panic!("expected missing fields");
+    };
+    assert_eq!(labels(&items), vec![("x", "number"), ("y", "number")]);
+
+    // A value position is not a key position
+    let src = r#"let pos: record { x: number, y: number } = { x: |"#;
+    assert!(matches!(at_cursor(src), Completions::Names(_)));
+}
+
+#[test]
+fn names_in_scope_with_signatures() {
+    let src = r#"let gold: number = 5;
+fn heal(target: string, amount: number) -> bool {
+  let left: number = amount;
+  |
+  let later: number = 1;
+  return true;
+}
+"#;
+    let Completions::Names(items) = at_cursor(src) else {
+        panic!("expected names");
+    };
+    let mut found: Vec<(&str, CompletionKind, &str)> = items
+        .iter()
+        .map(|c| (c.label.as_str(), c.kind, c.detail.as_str()))
+        .collect();
+    found.sort_by_key(|f| f.0);
+    assert_eq!(
+        found,
+        vec![
+            ("amount", CompletionKind::Variable, "number"),
+            ("gold", CompletionKind::Variable, "number"),
+            (
+                "heal",
+                CompletionKind::Function,
+                "fn heal(target: string, amount: number) -> bool"
+            ),
+            ("left", CompletionKind::Variable, "number"),
+            ("target", CompletionKind::Variable, "string"),
+        ]
+    );
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use questicle::coverage::{self, Branch, Coverage};
+use questicle::testing::{self, TestOptions};
+use questicle::{Host, Interpreter, Parser};
+
+const QUEST: &str = r#"fn reward(level: number) -> number {
+  if (level < 0) {
+    return 0;
+  }
+  let gold: number = 0;
+  for (i in [1, 2]) {
+    gold = gold + level;
+  }
+  return gold;
+}
+fn unused() -> null {
+  while (false) {}
+  return null;
+}
+
+test "rewards" {
+  assert_eq(reward(2), 4);
+}
+"#;
+
+#[test]
+fn tests_cover_statements_and_branches() {
+    let opts = TestOptions {
+        coverage: true,
+        ..Default::default()
+    };
+    let report = testing::run_source(QUEST, &opts);
+    let cov = report.coverage.expect("coverage was asked for");
+    let lines: Vec<(usize, u64)> = cov.lines.iter().map(|(l, n)| (*l, *n)).collect();
+    assert_eq!(
+        lines,
+        vec![
+            (1, 1),
+            (2, 1),
+            (3, 0),
+            (5, 1),
+            (6, 1),
+            (7, 2),
+            (9, 1),
+            (11, 1),
+            (12, 0),
+            (13, 0)
+        ]
+    );
+    let branch = |line, taken| Branch {
+        line,
+        block: 0,
+        taken,
+    };
+    assert_eq!(
+        cov.branches,
+        vec![
+            branch(2, Some([0, 1])),
+            branch(6, Some([2, 1])),
+            branch(12, None)
+        ]
+    );
+    assert_eq!(cov.line_counts(), (7, 10));
+    assert_eq!(cov.branch_counts(), (3, 6));
+
+    let lcov = coverage::to_lcov(&[(PathBuf::from("quest.qk"), cov)]);
+    assert!(lcov.starts_with("TN:\nSF:quest.qk\nBRDA:2,0,0,0\nBRDA:2,0,1,1\n"));
+    assert!(lcov.contains("BRDA:12,0,0,-\nBRDA:12,0,1,-\nBRF:6\nBRH:3\n"));
+    assert!(lcov.ends_with("DA:13,0\nLF:10\nLH:7\nend_of_record\n"));
+}
+
+#[test]
+fn coverage_hooks_into_any_interpreter() {
+    let program = Parser::new("let n: number = 0;\nwhile (n < 3) {\n  n = n + 1;\n}\n")
+        .parse_program()
+        .unwrap();
+    let cov = Coverage::default();
+    for _ in 0..2 {
+        let mut interp = Interpreter::with_host(Host::default());
+        interp.hooks = Some(Box::new(cov.clone()));
+        interp.eval(program.clone()).unwrap();
+    }
+    let report = cov.report(&program);
+    assert_eq!(report.lines.get(&3), Some(&6));
+    assert_eq!(report.branches[0].taken, Some([6, 2]));
+}
+
+#[test]
+fn qk_test_writes_lcov_and_a_summary() {
+    let dir = std::env::temp_dir().join(format!("qk-coverage-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("quest.qk"), QUEST).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_qk"))
+        .args(["test", "--coverage=cov/lcov.info", "quest.qk"])
+        .current_dir(&dir)
+        .output()
+        .expect("run qk");
+    let text = String::from_utf8_lossy(&out.stdout).into_owned();
+    assert_eq!(out.status.code(), Some(0), "{}", text);
+    assert!(text.contains("File                  Lines           Branches\n"));
+    assert!(text.contains("quest.qk       7/10   70.0%        3/6   50.0%\n"));
+    let lcov = fs::read_to_string(dir.join("cov/lcov.info")).unwrap();
+    assert!(lcov.starts_with("TN:\nSF:quest.qk\n"));
+}
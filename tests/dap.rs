@@ -0,0 +1,273 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use serde_json::{json, Value as Json};
+
+use questicle::ast::Span;
+use questicle::debug::{Breakpoint, Frontend, Paused, Resume, Session, StopReason};
+use questicle::{Host, Interpreter, Parser};
+
+// Answers each stop with the next scripted resume, noting where it stopped
+struct Scripted {
+    resumes: VecDeque<Resume>,
+    // Evaluated in the innermost frame at each stop
+    watch: &'static str,
+    stops: Rc<RefCell<Vec<String>>>,
+}
+
+impl Frontend for Scripted {
+    fn poll(&mut self, _: &mut BTreeMap<usize, Breakpoint>) -> bool {
+        false
+    }
+
+    fn stopped(&mut self, reason: &StopReason, mut paused: Paused<'_>) -> Resume {
+        let frames: Vec<&str> = paused.frames.iter().map(|f| f.name.as_str()).collect();
+        let line = paused.frames.last().unwrap().span.line;
+        let top = paused.frames.len() - 1;
+        let watch = match paused.evaluate(top, self.watch) {
+            Ok(v) => v.to_string(),
+            Err(e) => e,
+        };
+        self.stops.borrow_mut().push(format!(
+            "{:?} {} line {}: {}",
+            reason,
+            frames.join(">"),
+            line,
+            watch
+        ));
+        self.resumes.pop_front().unwrap_or(Resume::Continue)
+    }
+
+    fn log(&mut self, message: &str, span: Span) {
+        self.stops
+            .borrow_mut()
+            .push(format!("log line {}: {}", span.line, message));
+    }
+}
+
+fn debug_run(
+    src: &str,
+    breakpoints: &[Breakpoint],
+    resumes: &[Resume],
+    watch: &'static str,
+) -> Vec<String> {
+    let stops = Rc::new(RefCell::new(Vec::new()));
+    let mut session = Session::new(Scripted {
+        resumes: resumes.iter().copied().collect(),
+        watch,
+        stops: stops.clone(),
+    });
+    for bp in breakpoints {
+        session.breakpoints.insert(bp.line, bp.clone());
+    }
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.hooks = Some(Box::new(session));
+    let _ = interp.eval(Parser::new(src).parse_program().unwrap());
+    let stops = stops.borrow().clone();
+    stops
+}
+
+const SCRIPT: &str = r#"fn heal(hp: number) -> number {
+  let more: number = hp + 10;
+  return more;
+}
+let total: number = 0;
+for (i in [1, 2, 3]) {
+  total = total + heal(i);
+}
+print(total);
+"#;
+
+#[test]
+fn breakpoints_conditions_and_logpoints() {
+    let at = |line: usize| Breakpoint {
+        line,
+        ..Default::default()
+    };
+    let stops = debug_run(
+        SCRIPT,
+        &[
+            Breakpoint {
+                condition: Some("i == 2".into()),
+                ..at(7)
+            },
+            Breakpoint {
+                log_message: Some("healing {hp}, total {total}".into()),
+                ..at(2)
+            },
+            at(9),
+        ],
+        &[],
+        "total",
+    );
+    assert_eq!(
+        stops,
+        vec![
+            "log line 2: healing 1, total 0",
+            "Breakpoint <script> line 7: 11",
+            "log line 2: healing 2, total 11",
+            "log line 2: healing 3, total 23",
+            "Breakpoint <script> line 9: 36",
+        ]
+    );
+}
+
+#[test]
+fn stepping_and_errors() {
+    let stops = debug_run(
+        SCRIPT,
+        &[Breakpoint {
+            line: 6,
+            ..Default::default()
+        }],
+        &[
+            Resume::StepOver,
+            Resume::StepIn,
+            Resume::StepOver,
+            Resume::StepOut,
+            Resume::Abort,
+        ],
+        "total",
+    );
+    assert_eq!(
+        stops,
+        vec![
+            "Breakpoint <script> line 6: 0",
+            "Step <script> line 7: 0",
+            "Step <script>>heal line 2: 0",
+            "Step <script>>heal line 3: 0",
+            "Step <script> line 7: 11",
+        ]
+    );
+
+    // Runtime errors pause with the failing frame still on the stack
+    let src = "fn boom(n: number) -> number {\n  return n + nope;\n}\nboom(4);\n";
+    let stops = debug_run(src, &[], &[], "n");
+    assert_eq!(
+        stops,
+        vec!["Error(\"Undefined variable 'nope'\") <script>>boom line 2: 4"]
+    );
+}
+
+// A DAP client talking to qk-dap over its stdio
+struct Dap {
+    child: std::process::Child,
+    out: BufReader<std::process::ChildStdout>,
+    seq: i64,
+}
+
+impl Dap {
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        self.seq += 1;
+        let body = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments })
+            .to_string();
+        let stdin = self.child.stdin.as_mut().unwrap();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        stdin.flush().unwrap();
+        let seq = self.seq;
+        self.until(|m| m["type"] == "response" && m["request_seq"] == seq)
+    }
+
+    // Messages are read until one matches
+    fn until(&mut self, want: impl Fn(&Json) -> bool) -> Json {
+        loop {
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                assert!(self.out.read_line(&mut line).unwrap() > 0, "qk-dap closed");
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(n) = line.strip_prefix("Content-Length:") {
+                    length = n.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            self.out.read_exact(&mut body).unwrap();
+            let msg: Json = serde_json::from_slice(&body).unwrap();
+            if want(&msg) {
+                return msg;
+            }
+        }
+    }
+}
+
+#[test]
+fn qk_dap_speaks_the_protocol() {
+    let path = std::env::temp_dir().join(format!("qk-dap-{}.qk", std::process::id()));
+    std::fs::write(&path, SCRIPT).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_qk-dap"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run qk-dap");
+    let out = BufReader::new(child.stdout.take().unwrap());
+    let mut dap = Dap { child, out, seq: 0 };
+
+    let init = dap.request("initialize", json!({ "adapterID": "questicle" }));
+    assert_eq!(init["body"]["supportsConditionalBreakpoints"], true);
+    dap.request("launch", json!({ "program": path }));
+    // Line 3 has a statement; line 8 does not, so it moves to line 9
+    let set = dap.request(
+        "setBreakpoints",
+        json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }, { "line": 8 }] }),
+    );
+    let lines: Vec<&Json> = set["body"]["breakpoints"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| &b["line"])
+        .collect();
+    assert_eq!(lines, vec![&json!(3), &json!(9)]);
+    dap.request("configurationDone", json!({}));
+
+    let stopped = dap.until(|m| m["event"] == "stopped");
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+    let trace = dap.request("stackTrace", json!({ "threadId": 1 }));
+    let frames = trace["body"]["stackFrames"].as_array().unwrap();
+    assert_eq!(
+        (frames[0]["name"].clone(), frames[0]["line"].clone()),
+        (json!("heal"), json!(3))
+    );
+    assert_eq!(frames[1]["line"], 7);
+
+    let scopes = dap.request("scopes", json!({ "frameId": frames[0]["id"] }));
+    let locals = &scopes["body"]["scopes"][0]["variablesReference"];
+    let vars = dap.request("variables", json!({ "variablesReference": locals }));
+    let names: Vec<(&Json, &Json)> = vars["body"]["variables"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| (&v["name"], &v["value"]))
+        .collect();
+    assert_eq!(
+        names,
+        vec![(&json!("hp"), &json!("1")), (&json!("more"), &json!("11"))]
+    );
+    let eval = dap.request(
+        "evaluate",
+        json!({ "expression": "[more, hp * 2]", "frameId": frames[0]["id"] }),
+    );
+    assert_eq!(eval["body"]["result"], "[11, 2]");
+
+    // Clear the breakpoint in heal and run to line 9; print goes to the debug console
+    dap.request(
+        "setBreakpoints",
+        json!({ "source": { "path": path }, "breakpoints": [{ "line": 9 }] }),
+    );
+    dap.request("continue", json!({ "threadId": 1 }));
+    let stopped = dap.until(|m| m["event"] == "stopped");
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+    dap.request("continue", json!({ "threadId": 1 }));
+    let printed = dap.until(|m| m["event"] == "output" && m["body"]["category"] == "stdout");
+    assert_eq!(printed["body"]["output"], "36\n");
+    let exited = dap.until(|m| m["event"] == "exited");
+    assert_eq!(exited["body"]["exitCode"], 0);
+    dap.until(|m| m["event"] == "terminated");
+    dap.request("disconnect", json!({}));
+    dap.child.wait().unwrap();
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use questicle::ast::DeclKind;
+use questicle::decl::Declarations;
+use questicle::typecheck::{check_program_with_options, CheckOptions, Type};
+use questicle::Parser;
+
+const DECLS: &str = r#"
+/// Spawn an entity in the world.
+/// Returns the new entity id.
+host spawn(payload: record { kind: string, name: string }) -> record { id: number };
+
+host "ui.toast"(payload: record { text: string }) -> null;
+
+/// Straight-line distance between two points.
+fn distance(a: record { x: number, y: number }, b: record { x: number, y: number }) -> number;
+"#;
+
+fn decls() -> Declarations {
+    let mut d = Declarations::default();
+    d.add_source(DECLS, None).expect("declarations parse");
+    d
+}
+
+fn errors(src: &str) -> Vec<String> {
+    let program = Parser::new(src).parse_program().expect("parse");
+    let opts = CheckOptions {
+        decls: Some(Arc::new(decls())),
+        ..Default::default()
+    };
+    check_program_with_options(&program, &opts)
+        .errors
+        .into_iter()
+        .map(|e| e.message)
+        .collect()
+}
+
+#[test]
+fn parses_ops_natives_and_docs() {
+    let d = decls();
+    let spawn = &d.host_ops["spawn"];
+    assert_eq!(
+        spawn.doc.as_deref(),
+        Some("Spawn an entity in the world.\nReturns the new entity id.")
+    );
+    assert_eq!(
+        spawn.signature(DeclKind::HostOp),
+        "host spawn(payload: record{kind:string, name:string}) -> record{id:number}"
+    );
+    assert!(d.host_ops.contains_key("ui.toast"));
+    assert!(d.host_ops["ui.toast"].doc.is_none());
+    assert_eq!(d.natives["distance"].ret_type(), Type::Number);
+}
+
+#[test]
+fn host_payload_and_result_are_checked() {
+    let ok = r#"
+let e: record { id: number } = host("spawn", { kind: "npc", name: "Elder" });
+host("ui.toast", { text: "hi" });
+"#;
+    assert!(errors(ok).is_empty(), "{:?}", errors(ok));
+
+    let missing = errors(r#"let e: any = host("spawn", { kind: "npc" });"#);
+    assert_eq!(missing.len(), 1);
+    assert!(missing[0].contains("expects payload"), "{:?}", missing);
+
+    let wrong_result = errors(r#"let e: string = host("spawn", { kind: "npc", name: "A" });"#);
+    assert_eq!(wrong_result.len(), 1, "{:?}", wrong_result);
+}
+
+#[test]
+fn unknown_host_op_is_reported() {
+    let errs = errors(r#"let e: any = host("despawn", {});"#);
+    assert_eq!(errs, vec!["Unknown host op 'despawn'".to_string()]);
+}
+
+#[test]
+fn declared_natives_are_typed() {
+    let ok = r#"let d: number = distance({ x: 0, y: 0 }, { x: 3, y: 4 });"#;
+    assert!(errors(ok).is_empty(), "{:?}", errors(ok));
+    assert!(!errors(r#"let d: string = distance({ x: 0, y: 0 }, { x: 3, y: 4 });"#).is_empty());
+}
+
+#[test]
+fn without_declarations_host_stays_untyped() {
+    let program = Parser::new(r#"let e: string = host("anything", 1);"#)
+        .parse_program()
+        .expect("parse");
+    assert!(
+        check_program_with_options(&program, &CheckOptions::default())
+            .errors
+            .is_empty()
+    );
+}
+
+#[test]
+fn example_declarations_load() {
+    let d = Declarations::load(&["examples".into()]).expect("load examples");
+    assert!(d.host_ops.contains_key("spawn"));
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use questicle::events::{self, SiteKind};
+use questicle::typecheck::{self, Type};
+use questicle::workspace::WorkspaceIndex;
+use questicle::Parser;
+
+// A fresh directory under the system temp dir holding the given files
+fn scratch_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qk-events-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    for (file, content) in files {
+        fs::write(dir.join(file), content).expect("write scratch file");
+    }
+    dir
+}
+
+const QUEST: &str = r#"fn finish(id: number) -> null {
+  emit("quest_completed", { id: id, name: "Find the cat" });
+  return null;
+}
+finish(1);
+"#;
+
+const REWARDS: &str = r#"fn grant(q: record { id: number, name: string }) -> null {
+  emit("gold_awarded", q.id * 10);
+  return null;
+}
+on("quest_completed", grant);
+on("level_up", fn(level: number) {
+  emit("fanfare");
+});
+"#;
+
+#[test]
+fn sites_carry_payload_types_and_functions() {
+    let program = Parser::new(REWARDS).parse_program().unwrap();
+    let tc = typecheck::check_program(&program);
+    let found = events::analyze(&program, Some(&tc));
+    let sites: Vec<_> = found
+        .sites
+        .iter()
+        .map(|s| {
+            (
+                s.event.as_str(),
+                s.kind,
+                s.payload.clone(),
+                s.function.as_deref(),
+                s.handler.as_deref(),
+            )
+        })
+        .collect();
+    let quest = Type::Record(
+        [("id".into(), Type::Number), ("name".into(), Type::String)]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(
+        sites,
+        vec![
+            (
+                "gold_awarded",
+                SiteKind::Emitter,
+                Some(Type::Number),
+                Some("grant"),
+                None
+            ),
+            (
+                "quest_completed",
+                SiteKind::Handler,
+                Some(quest),
+                None,
+                Some("grant")
+            ),
+            (
+                "level_up",
+                SiteKind::Handler,
+                Some(Type::Number),
+                None,
+                Some("on(\"level_up\")")
+            ),
+            (
+                "fanfare",
+                SiteKind::Emitter,
+                Some(Type::Null),
+                Some("on(\"level_up\")"),
+                None
+            ),
+        ]
+    );
+    // The event name literal locates the site
+    let site = found.site_at(6, 6).expect("site on the event name");
+    assert_eq!(site.event, "level_up");
+    assert!(found.site_at(6, 1).is_none());
+}
+
+#[test]
+fn graph_spans_scripts() {
+    let dir = scratch_dir("graph", &[("quest.qk", QUEST), ("rewards.qk", REWARDS)]);
+    let mut index = WorkspaceIndex::default();
+    index.scan(std::slice::from_ref(&dir));
+    let graph = index.event_graph();
+
+    let completed = &graph.events["quest_completed"];
+    assert_eq!(completed.emitters.len(), 1);
+    assert_eq!(completed.emitters[0].file, dir.join("quest.qk"));
+    assert_eq!(completed.emitters[0].function.as_deref(), Some("finish"));
+    assert_eq!(
+        completed.emitters[0].payload.as_deref(),
+        Some("record{id:number, name:string}")
+    );
+    assert_eq!(completed.handlers[0].file, dir.join("rewards.qk"));
+    assert!(graph.events["level_up"].emitters.is_empty());
+
+    let callers: Vec<_> = index
+        .calls_to("finish")
+        .into_iter()
+        .map(|(p, c)| (p.to_path_buf(), c.caller.clone(), c.span.line))
+        .collect();
+    assert_eq!(callers, vec![(dir.join("quest.qk"), None, 5)]);
+}
+
+#[test]
+fn qk_events_prints_dot_and_json() {
+    let dir = scratch_dir("cli", &[("quest.qk", QUEST), ("rewards.qk", REWARDS)]);
+    let run = |json: bool| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_qk"));
+        cmd.arg("events").current_dir(&dir);
+        if json {
+            cmd.arg("--json");
+        }
+        let out = cmd.output().expect("run qk");
+        assert_eq!(out.status.code(), Some(0));
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+
+    let dot = run(false);
+    assert!(dot.starts_with("digraph events {"));
+    assert!(dot.contains(
+        "\"./quest.qk\" -> \"event:quest_completed\" [label=\"record{id:number, name:string}\"];"
+    ));
+    assert!(dot.contains("\"event:quest_completed\" -> \"./rewards.qk\";"));
+    assert!(dot.contains("\"event:fanfare\" [label=\"fanfare\", shape=ellipse, style=dashed];"));
+
+    let report: serde_json::Value = serde_json::from_str(&run(true)).unwrap();
+    let handler = &report["events"]["level_up"]["handlers"][0];
+    assert_eq!(handler["payload"], "number");
+    assert_eq!(handler["line"], 6);
+}
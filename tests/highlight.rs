@@ -0,0 +1,62 @@
+use questicle::decl::Declarations;
+use questicle::highlight::{
+    semantic_tokens, TokenType, MOD_DECLARATION, MOD_DEFAULT_LIBRARY, MOD_HOST, MOD_MODIFICATION,
+};
+
+// (text, type, modifiers) of every token, in order
+fn classify(src: &str, decls: Option<&Declarations>) -> Vec<(String, TokenType, u32)> {
+    let lines: Vec<&str> = src.lines().collect();
+    semantic_tokens(src, decls)
+        .expect("parses")
+        .into_iter()
+        .map(|t| {
+            let text: String = lines[t.line - 1]
+                .chars()
+                .skip(t.col - 1)
+                .take(t.len)
+                .collect();
+            (text, t.ty, t.modifiers)
+        })
+        .collect()
+}
+
+#[test]
+fn names_are_classified_by_what_they_bind() {
+    let src = r#"let hero: record { hp: number } = { hp: 3 };
+fn heal(amount: number) -> null {
+  hero = { hp: hero.hp + amount };
+}
+print(distance(1, 2));
+"#;
+    let mut decls = Declarations::default();
+    decls
+        .add_source("fn distance(a: number, b: number) -> number;", None)
+        .unwrap();
+    use TokenType::*;
+    let t = |s: &str, ty, m| (s.to_string(), ty, m);
+    assert_eq!(
+        classify(src, Some(&decls)),
+        vec![
+            t("hero", Variable, MOD_DECLARATION),
+            t("record", Type, 0),
+            t("hp", Property, 0),
+            t("number", Type, 0),
+            t("hp", Property, 0),
+            t("heal", Function, MOD_DECLARATION),
+            t("amount", Parameter, MOD_DECLARATION),
+            t("number", Type, 0),
+            t("hero", Variable, MOD_MODIFICATION),
+            t("hp", Property, 0),
+            t("hero", Variable, 0),
+            t("hp", Property, 0),
+            t("amount", Parameter, 0),
+            t("print", Function, MOD_DEFAULT_LIBRARY),
+            t("distance", Function, MOD_HOST),
+        ]
+    );
+}
+
+#[test]
+fn unparseable_source_has_no_tokens() {
+    assert!(semantic_tokens("let x: number = ;", None).is_none());
+}
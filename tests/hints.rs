@@ -0,0 +1,100 @@
+use questicle::decl::Declarations;
+use questicle::hints::{inlay_hints, HintKind, HintOptions};
+use questicle::typecheck::CheckOptions;
+use std::sync::Arc;
+
+// (line, col, label) of every hint of one kind
+fn hints(src: &str, opts: HintOptions, kind: HintKind) -> Vec<(usize, usize, String)> {
+    inlay_hints(src, &opts, &CheckOptions::default())
+        .expect("parses")
+        .into_iter()
+        .filter(|h| h.kind == kind)
+        .map(|h| (h.line, h.col, h.label))
+        .collect()
+}
+
+#[test]
+fn inferred_types_of_variables_and_returns() {
+    let src = r#"let scores: any = [1, 2, 3];
+for (s in [1, 2]) {
+  print(s);
+}
+let greet: any = fn(name: string) {
+  return "hi " + name;
+};
+let annotated: any = fn(n: number) -> number {
+  return n;
+};
+"#;
+    let found = hints(src, HintOptions::default(), HintKind::Type);
+    assert!(
+        found.contains(&(1, 16, "≈ list<number>".to_string())),
+        "{:?}",
+        found
+    );
+    assert!(
+        found.contains(&(2, 7, ": number".to_string())),
+        "{:?}",
+        found
+    );
+    assert!(
+        found.contains(&(5, 34, "-> string".to_string())),
+        "{:?}",
+        found
+    );
+    // Annotated returns get no hint
+    assert!(
+        !found
+            .iter()
+            .any(|(line, _, label)| *line == 8 && label.starts_with("->")),
+        "{:?}",
+        found
+    );
+
+    let off = HintOptions {
+        variable_types: false,
+        return_types: false,
+        ..HintOptions::default()
+    };
+    assert!(hints(src, off, HintKind::Type).is_empty());
+}
+
+#[test]
+fn parameter_names_at_call_sites() {
+    let src = r#"fn damage(target: string, amount: number) -> null {
+  print(target);
+}
+let amount: number = 3;
+damage("orc", amount);
+print(distance(1, 2));
+"#;
+    let mut decls = Declarations::default();
+    decls
+        .add_source("fn distance(a: number, b: number) -> number;", None)
+        .unwrap();
+    let check = CheckOptions {
+        decls: Some(Arc::new(decls)),
+        ..Default::default()
+    };
+    let found: Vec<(usize, usize, String)> = inlay_hints(src, &HintOptions::default(), &check)
+        .unwrap()
+        .into_iter()
+        .filter(|h| h.kind == HintKind::Parameter)
+        .map(|h| (h.line, h.col, h.label))
+        .collect();
+    // `amount` already names its parameter
+    assert_eq!(
+        found,
+        vec![
+            (5, 8, "target:".to_string()),
+            (6, 16, "a:".to_string()),
+            (6, 19, "b:".to_string()),
+        ]
+    );
+
+    let off = HintOptions {
+        parameter_names: false,
+        ..HintOptions::default()
+    };
+    assert!(hints(src, off, HintKind::Parameter).is_empty());
+}
@@ -0,0 +1,143 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use questicle::ast::Span;
+use questicle::eval::RuntimeError;
+use questicle::hooks::{Flow, Hooks};
+use questicle::value::{Function, Value};
+use questicle::{Host, Interpreter, Parser};
+
+// Writes down every hook call except statements
+struct Recorder(Rc<RefCell<Vec<String>>>);
+
+impl Hooks for Recorder {
+    fn enter(&mut self, _: &mut Interpreter, name: &str, call: Span, args: &[Value]) -> Flow {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        self.0.borrow_mut().push(format!(
+            "enter {}({}) line {}",
+            name,
+            args.join(", "),
+            call.line
+        ));
+        Flow::Continue
+    }
+
+    fn exit(&mut self, _: &mut Interpreter, name: &str, result: &Value) -> Flow {
+        self.0
+            .borrow_mut()
+            .push(format!("exit {} = {}", name, result));
+        Flow::Continue
+    }
+
+    fn emit(&mut self, _: &mut Interpreter, event: &str, payload: &Value) -> Flow {
+        self.0
+            .borrow_mut()
+            .push(format!("emit {} {}", event, payload));
+        Flow::Continue
+    }
+
+    fn dispatch(&mut self, _: &mut Interpreter, event: &str, handler: &Rc<Function>) -> Flow {
+        let line = match handler.as_ref() {
+            Function::User { span, .. } => span.line,
+            Function::Native { .. } => 0,
+        };
+        self.0
+            .borrow_mut()
+            .push(format!("dispatch {} to line {}", event, line));
+        Flow::Continue
+    }
+
+    fn host_call(
+        &mut self,
+        _: &mut Interpreter,
+        op: &str,
+        payload: &Value,
+        result: &Result<Value, String>,
+    ) -> Flow {
+        let ok = match result {
+            Ok(Value::Map(m)) => m.get("ok").map(|v| v.to_string()),
+            _ => None,
+        };
+        self.0
+            .borrow_mut()
+            .push(format!("host {} {} -> {:?}", op, payload, ok));
+        Flow::Continue
+    }
+
+    fn error(&mut self, _: &mut Interpreter, error: &RuntimeError) {
+        self.0.borrow_mut().push(format!("error {}", error));
+    }
+}
+
+const SCRIPT: &str = r#"fn heal(hp: number) -> number {
+  return hp + 10;
+}
+let gained: number = 0;
+on("healed", fn(amount: number) {
+  gained = gained + amount;
+});
+emit("healed", heal(5));
+host("save", gained);
+print(nope);
+"#;
+
+#[test]
+fn hooks_see_calls_events_host_calls_and_errors() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.hooks = Some(Box::new(Recorder(seen.clone())));
+    let result = interp.eval(Parser::new(SCRIPT).parse_program().unwrap());
+    assert!(result.is_err());
+    assert_eq!(
+        *seen.borrow(),
+        vec![
+            "enter heal(5) line 8",
+            "exit heal = 15",
+            "emit healed 15",
+            "dispatch healed to line 5",
+            "enter on(\"healed\")(15) line 5",
+            "exit on(\"healed\") = 15",
+            "host save 15 -> Some(\"true\")",
+            "error Undefined variable 'nope'",
+        ]
+    );
+    // Script handlers run even without hooks
+    let mut plain = Interpreter::with_host(Host::default());
+    let _ = plain.eval(Parser::new(SCRIPT).parse_program().unwrap());
+    assert_eq!(
+        plain.env.borrow().get("gained").map(|v| v.to_string()),
+        Some("15".into())
+    );
+}
+
+// Stops a script after a number of statements
+struct Budget(usize);
+
+impl Hooks for Budget {
+    fn statement(&mut self, _: &mut Interpreter, _: Span) -> Flow {
+        match self.0.checked_sub(1) {
+            Some(left) => {
+                self.0 = left;
+                Flow::Continue
+            }
+            None => Flow::Abort("Statement budget exhausted".into()),
+        }
+    }
+}
+
+#[test]
+fn hooks_can_abort() {
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.hooks = Some(Box::new(Budget(100)));
+    let src = "let n: number = 0;\nwhile (true) {\n  n = n + 1;\n}\n";
+    let err = interp
+        .eval(Parser::new(src).parse_program().unwrap())
+        .err()
+        .expect("the budget stops the loop");
+    assert!(matches!(&err, RuntimeError::Aborted(m) if m == "Statement budget exhausted"));
+    // The let and the while take two statements of the budget, each pass one more
+    assert_eq!(
+        interp.env.borrow().get("n").map(|v| v.to_string()),
+        Some("98".into())
+    );
+}
@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use questicle::isolate::{Isolate, IsolateError, IsolatePool, SendValue};
+use questicle::{CompiledScript, Host, Interpreter};
+
+const WANDERER: &str = r#"let steps: number = 0;
+let trail: list<any> = [];
+fn tick(dx: number) -> number {
+  steps = steps + 1;
+  trail = push(trail, dx);
+  return steps * dx;
+}
+fn echo(v: any) -> any {
+  return v;
+}
+fn maker() -> any {
+  return fn() { return 1; };
+}
+"#;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn isolates_tick_on_other_threads() {
+    assert_send_sync::<Isolate>();
+    let script = CompiledScript::compile(WANDERER).unwrap();
+    let isolates: Vec<Isolate> = (0..4).map(|_| Isolate::spawn(&script).unwrap()).collect();
+    // Job-system threads each drive some of the isolates
+    std::thread::scope(|s| {
+        for (i, isolate) in isolates.iter().enumerate() {
+            s.spawn(move || {
+                for _ in 0..=i {
+                    isolate
+                        .call("tick", vec![SendValue::Number(i as f64)])
+                        .unwrap();
+                }
+            });
+        }
+    });
+    // Posted calls run at the same time and are waited for together
+    let pending: Vec<_> = isolates
+        .iter()
+        .map(|isolate| isolate.post("tick", vec![SendValue::Number(10.0)]))
+        .collect();
+    let results: Vec<String> = pending
+        .into_iter()
+        .map(|p| p.wait().unwrap().to_string())
+        .collect();
+    assert_eq!(results, vec!["20", "30", "40", "50"]);
+    assert_eq!(
+        isolates[2].get("trail").unwrap().to_string(),
+        "[2, 2, 2, 10]"
+    );
+}
+
+#[test]
+fn values_cross_between_isolates_but_functions_do_not() {
+    let script = CompiledScript::compile(WANDERER).unwrap();
+    let a = Isolate::spawn(&script).unwrap();
+    let b = Isolate::spawn(&script).unwrap();
+    let hero = SendValue::Map(BTreeMap::from([
+        ("name".to_string(), SendValue::String("Ada".into())),
+        (
+            "bag".to_string(),
+            SendValue::List(vec![SendValue::Bool(true), SendValue::Null]),
+        ),
+    ]));
+    let from_a = a.call("echo", vec![hero.clone()]).unwrap();
+    let from_b = b.call("echo", vec![from_a]).unwrap();
+    assert_eq!(from_b, hero);
+    assert_eq!(from_b.to_string(), "{bag: [true, null], name: \"Ada\"}");
+
+    let err = a.call("maker", Vec::new()).err().unwrap();
+    assert!(matches!(err, IsolateError::NotSendable(_)));
+    assert_eq!(err.to_string(), "<fn> cannot be sent to another isolate");
+    // The isolate carries on after an error
+    let err = a.call("wander", Vec::new()).err().unwrap();
+    assert_eq!(err.to_string(), "Undefined function 'wander'");
+    assert_eq!(
+        a.call("tick", vec![SendValue::Number(3.0)])
+            .unwrap()
+            .to_string(),
+        "3"
+    );
+}
+
+#[test]
+fn setup_runs_on_the_isolate_thread() {
+    let script =
+        CompiledScript::compile("print(\"ready\");\nfn shout(s: string) { print(s); }\n").unwrap();
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let isolate = Isolate::spawn_with(&script, {
+        let lines = lines.clone();
+        move || {
+            let mut interp = Interpreter::with_host(Host::default());
+            interp.set_output(move |line: &str| lines.lock().unwrap().push(line.to_string()));
+            interp
+        }
+    })
+    .unwrap();
+    isolate
+        .call("shout", vec![SendValue::String("hi".into())])
+        .unwrap();
+    drop(isolate);
+    assert_eq!(*lines.lock().unwrap(), vec!["ready", "hi"]);
+
+    let broken = CompiledScript::compile("let x: any = nope;\n").unwrap();
+    let err = Isolate::spawn(&broken).err().unwrap();
+    assert_eq!(err.to_string(), "Undefined variable 'nope'");
+}
+
+#[test]
+fn many_isolates_share_a_few_worker_threads() {
+    let script = CompiledScript::compile(WANDERER).unwrap();
+    let pool = IsolatePool::new(3);
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let isolates: Vec<Isolate> = (0..100)
+        .map(|_| {
+            let threads = threads.clone();
+            pool.spawn_with(&script, move || {
+                threads.lock().unwrap().insert(std::thread::current().id());
+                Interpreter::with_host(Host::default())
+            })
+            .unwrap()
+        })
+        .collect();
+    assert_eq!(pool.threads(), 3);
+    assert_eq!(threads.lock().unwrap().len(), 3);
+    let pending: Vec<_> = isolates
+        .iter()
+        .enumerate()
+        .map(|(i, isolate)| isolate.post("tick", vec![SendValue::Number(i as f64)]))
+        .collect();
+    for (i, p) in pending.into_iter().enumerate() {
+        assert_eq!(p.wait().unwrap(), SendValue::Number(i as f64));
+    }
+    // Isolates outlive the pool handle; its workers stay up until they are dropped
+    drop(pool);
+    assert_eq!(isolates[7].get("steps").unwrap(), SendValue::Number(1.0));
+}
@@ -0,0 +1,184 @@
+use std::path::Path;
+
+use questicle::check::Severity;
+use questicle::events::{self, EventGraph};
+use questicle::lint::{lint_source, lint_source_in, Level, LintConfig, LintConfigError};
+use questicle::Parser;
+
+// (rule, line) of every finding with the default configuration
+fn findings(src: &str) -> Vec<(&'static str, usize)> {
+    lint_source(src, &LintConfig::default())
+        .into_iter()
+        .map(|d| (d.code.expect("lint code"), d.span.line))
+        .collect()
+}
+
+#[test]
+fn unused_variables() {
+    let src = r#"
+let used: number = 1;
+let unused: number = 2;
+let _ignored: number = 3;
+fn helper() -> number {
+  let inner: number = used;
+  return later();
+}
+fn later() -> number {
+  return 1;
+}
+print(helper);
+"#;
+    assert_eq!(
+        findings(src),
+        vec![("unused-variable", 3), ("unused-variable", 6)]
+    );
+}
+
+#[test]
+fn recursion_alone_is_not_a_use() {
+    let src = r#"
+let run: fn() -> null = fn() {
+  let loop_fn: any = fn(n: number) {
+    return loop_fn(n - 1);
+  };
+};
+run();
+"#;
+    assert_eq!(findings(src), vec![("unused-variable", 3)]);
+}
+
+#[test]
+fn unreachable_and_misplaced_break() {
+    let src = r#"
+fn f(x: number) -> number {
+  if (x > 1) {
+    return 1;
+  } else {
+    return 2;
+  }
+  print("never");
+}
+while (clock() < 0) {
+  break;
+  print("never");
+}
+f(1);
+break;
+"#;
+    assert_eq!(
+        findings(src),
+        vec![
+            ("unreachable-code", 8),
+            ("unreachable-code", 12),
+            ("break-outside-loop", 15)
+        ]
+    );
+    let d = lint_source(src, &LintConfig::default());
+    assert_eq!(d[2].severity, Severity::Error);
+}
+
+#[test]
+fn constant_conditions() {
+    let src = r#"
+if (true) { print(1); }
+if ("") { print(2); }
+while (true) {
+  break;
+}
+while (1) { print(3); }
+if (clock() > 1) { print(4); }
+"#;
+    assert_eq!(
+        findings(src),
+        vec![
+            ("constant-condition", 2),
+            ("constant-condition", 3),
+            ("constant-condition", 7)
+        ]
+    );
+}
+
+#[test]
+fn events_assignments_and_keys() {
+    let src = r#"
+on("hit", fn(d) { print(d); });
+emit("hit", 1);
+emit("miss", 2);
+let hp: number = 1;
+hp = hp;
+let m: any = { a: 1, b: 2, a: 3 };
+print(m);
+"#;
+    assert_eq!(
+        findings(src),
+        vec![
+            ("unhandled-event", 4),
+            ("self-assignment", 6),
+            ("duplicate-key", 7)
+        ]
+    );
+}
+
+#[test]
+fn events_handled_by_another_linted_script_count() {
+    let quest = "emit(\"quest_done\", 1);\nemit(\"typo_done\", 1);\n";
+    let rewards = "on(\"quest_done\", fn(id) { print(id); });\n";
+    let analyzed: Vec<_> = [quest, rewards]
+        .iter()
+        .map(|src| events::analyze(&Parser::new(src).parse_program().unwrap(), None))
+        .collect();
+    let graph = EventGraph::build([
+        (Path::new("quest.qk"), &analyzed[0]),
+        (Path::new("rewards.qk"), &analyzed[1]),
+    ]);
+    let d = lint_source_in(quest, &LintConfig::default(), &graph);
+    assert_eq!(d.len(), 1);
+    assert_eq!(d[0].span.line, 2);
+    assert_eq!(
+        d[0].message,
+        "No `on` handler for event 'typo_done' in any linted script"
+    );
+    assert_eq!(findings(quest).len(), 2);
+}
+
+#[test]
+fn suppression_comments() {
+    let src = r#"
+// qk: allow-file(unhandled-event)
+// qk: allow(unused-variable)
+let a: number = 1;
+let b: number = 2; // qk: allow(all)
+let c: number = 3;
+emit("nobody", 1);
+"#;
+    assert_eq!(findings(src), vec![("unused-variable", 6)]);
+}
+
+#[test]
+fn suppressions_are_only_read_from_comments() {
+    let src = r#"let url: string = "http://x"; // qk: allow(unused-variable)
+let marker: string = "// qk: allow(unused-variable)";
+"#;
+    assert_eq!(findings(src), vec![("unused-variable", 2)]);
+}
+
+#[test]
+fn config_changes_levels() {
+    let config: LintConfig = serde_json::from_str(
+        r#"{ "rules": { "unused-variable": "off", "self-assignment": "error" } }"#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.level("unused-variable"), Level::Off);
+    let d = lint_source("let x: number = 1;\nx = x;\n", &config);
+    assert_eq!(d.len(), 1);
+    assert_eq!(d[0].code, Some("self-assignment"));
+    assert_eq!(d[0].severity, Severity::Error);
+
+    let bad: LintConfig =
+        serde_json::from_str(r#"{ "rules": { "no-such-rule": "warn" } }"#).unwrap();
+    assert!(matches!(
+        bad.validate(),
+        Err(LintConfigError::UnknownRule(r)) if r == "no-such-rule"
+    ));
+}
@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use questicle::host::HostApi;
+use questicle::output::{Capture, Level, LogCrate, Output, Record};
+use questicle::value::Value;
+use questicle::{Host, Interpreter, Parser};
+
+const SCRIPT: &str = r#"let gold: number = 3;
+log.debug("checking gold");
+log.info("quest started", { quest: "q1", gold: gold });
+print("gold", gold);
+log.warn("low on gold");
+log.error(404);
+"#;
+
+fn run(interp: &mut Interpreter) {
+    interp
+        .eval(Parser::new(SCRIPT).parse_program().unwrap())
+        .unwrap();
+}
+
+#[derive(Clone, Default)]
+struct Lines(Rc<RefCell<Vec<String>>>);
+
+impl Output for Lines {
+    fn print(&mut self, line: &str) {
+        self.0.borrow_mut().push(format!("print {}", line));
+    }
+
+    fn log(&mut self, record: &Record) {
+        let fields: Vec<String> = record.fields.keys().cloned().collect();
+        self.0.borrow_mut().push(format!(
+            "{} {} [{}]",
+            record.level.name(),
+            record.message,
+            fields.join(",")
+        ));
+    }
+}
+
+#[test]
+fn log_records_are_filtered_by_level() {
+    let lines = Lines::default();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.set_output(lines.clone());
+    run(&mut interp);
+    assert_eq!(
+        *lines.0.borrow(),
+        vec![
+            "info quest started [gold,quest]",
+            "print gold 3",
+            "warn low on gold []",
+            "error 404 []"
+        ]
+    );
+
+    // Another script can log at its own level; outputs without a `log` method print records
+    let capture = Capture::default();
+    let mut debug = Interpreter::with_host(Host::default());
+    debug.set_output(capture.clone());
+    debug.set_log_level(Level::Debug);
+    run(&mut debug);
+    assert_eq!(
+        capture.text(),
+        "debug: checking gold\ninfo: quest started gold=3 quest=\"q1\"\ngold 3\nwarn: low on gold\nerror: 404\n"
+    );
+
+    let mut quiet = Interpreter::with_host(Host::default());
+    quiet.set_output(lines.clone());
+    quiet.set_log_level(Level::Error);
+    lines.0.borrow_mut().clear();
+    run(&mut quiet);
+    assert_eq!(*lines.0.borrow(), vec!["print gold 3", "error 404 []"]);
+}
+
+#[derive(Default)]
+struct Engine {
+    console: RefCell<Vec<String>>,
+}
+
+impl HostApi for Engine {
+    fn call(&self, _op: &str, payload: Value) -> Result<Value, String> {
+        Ok(payload)
+    }
+
+    fn print(&self, line: &str) {
+        self.console.borrow_mut().push(line.to_string());
+    }
+
+    fn log(&self, record: &Record) {
+        self.console.borrow_mut().push(record.text());
+    }
+}
+
+#[test]
+fn output_can_go_to_the_host() {
+    let engine = Rc::new(Engine::default());
+    let host = Host::with_api(engine.clone());
+    let mut interp = Interpreter::with_host(host.clone());
+    interp.set_output(host);
+    interp.set_log_level(Level::Warn);
+    run(&mut interp);
+    assert_eq!(
+        *engine.console.borrow(),
+        vec!["gold 3", "warn: low on gold", "error: 404"]
+    );
+}
+
+static LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct TestLogger;
+
+impl log::Log for TestLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOGGED.lock().unwrap().push(format!(
+            "{} {} {}",
+            record.target(),
+            record.level(),
+            record.args()
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn output_can_go_to_the_log_crate() {
+    log::set_logger(&TestLogger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.set_output(LogCrate);
+    run(&mut interp);
+    assert_eq!(
+        *LOGGED.lock().unwrap(),
+        vec![
+            "questicle::script INFO quest started gold=3 quest=\"q1\"",
+            "questicle::print INFO gold 3",
+            "questicle::script WARN low on gold",
+            "questicle::script ERROR 404"
+        ]
+    );
+}
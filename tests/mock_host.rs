@@ -0,0 +1,104 @@
+use std::rc::Rc;
+
+use questicle::host::{MockHost, Recorded, Reply};
+use questicle::testing::{self, Outcome, TestOptions};
+use questicle::value::Value;
+use questicle::{Interpreter, Parser};
+
+const SPAWNER: &str = r#"fn spawn_guard(x: number) -> any {
+  let guard: any = host("spawn", { kind: "npc", x: x });
+  emit("spawned", guard.id);
+  return guard;
+}
+on("spawned", fn(id: any) {
+  host("log", id);
+});
+"#;
+
+#[test]
+fn mock_host_answers_and_records() {
+    let mock = MockHost::default();
+    mock.reply(
+        "spawn",
+        Reply::Native(Rc::new(|payload| match payload {
+            Value::Map(m) => Ok(Value::Map(
+                [("id".to_string(), m["x"].clone())].into_iter().collect(),
+            )),
+            _ => Err("spawn expects a map".into()),
+        })),
+    );
+    mock.reply("log", Reply::Error("log is down".into()));
+    let mut interp = Interpreter::with_host(mock.host());
+    let src = format!("{}spawn_guard(7);\n", SPAWNER);
+    let err = interp
+        .eval(Parser::new(&src).parse_program().unwrap())
+        .err()
+        .expect("the log op fails");
+    assert_eq!(err.to_string(), "log is down");
+
+    let spawns: Vec<String> = mock.calls("spawn").iter().map(|p| p.to_string()).collect();
+    assert_eq!(spawns, vec!["{kind: \"npc\", x: 7}"]);
+    assert_eq!(mock.events(), vec!["spawned"]);
+    // The handler's host call comes after the event that ran it
+    let order: Vec<String> = mock
+        .recorded()
+        .iter()
+        .map(|r| match r {
+            Recorded::Call { op, payload } => format!("call {} {}", op, payload),
+            Recorded::Event { event, payload } => format!("event {} {}", event, payload),
+        })
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            "call spawn {kind: \"npc\", x: 7}",
+            "event spawned 7",
+            "call log 7"
+        ]
+    );
+}
+
+#[test]
+fn scripts_mock_the_host_in_tests() {
+    let src = format!(
+        "{}{}",
+        SPAWNER,
+        r#"
+test "spawns an npc" {
+  mock_host("spawn", fn(p: any) { return { id: p.x + 1 }; });
+  mock_host("log", "logged");
+  assert_eq(spawn_guard(3), { id: 4 });
+  assert_eq(host("log", "hi"), "logged");
+  assert_eq(host_calls("spawn"), [{ op: "spawn", payload: { kind: "npc", x: 3 } }]);
+  assert_eq(len(host_calls()), 3);
+  emit("done", null);
+  assert_eq(emitted(), [{ event: "spawned", payload: 4 }, { event: "done", payload: null }]);
+}
+
+test "unmocked ops echo" {
+  assert_eq(host("heal", 5), { ok: true, op: "heal", payload: 5 });
+  assert_eq(host_calls("spawn"), []);
+}
+
+test "mocks can fail" {
+  mock_host("spawn", fn(p: any) { return nope; });
+  assert_throws(fn() { spawn_guard(1); });
+  assert_eq(len(host_calls("spawn")), 1);
+}
+"#
+    );
+    let report = testing::run_source(&src, &TestOptions::default());
+    let results: Vec<_> = report
+        .results
+        .into_iter()
+        .map(|r| (r.name, r.outcome))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            ("spawns an npc".into(), Outcome::Passed),
+            ("unmocked ops echo".into(), Outcome::Passed),
+            ("mocks can fail".into(), Outcome::Passed),
+        ]
+    );
+}
@@ -0,0 +1,139 @@
+use std::process::Command;
+
+use questicle::profile::Profiler;
+use questicle::{Host, Interpreter, Parser};
+
+const SCRIPT: &str = r#"fn fib(n: number) -> number {
+  if (n < 2) { return n; }
+  return fib(n - 1) + fib(n - 2);
+}
+fn bag(n: number) -> list<number> {
+  let items: list<number> = [];
+  for (i in [1, 2, 3]) {
+    items = push(items, i + n);
+  }
+  return items;
+}
+fn quest() -> number {
+  bag(1);
+  return fib(5);
+}
+quest();
+"#;
+
+fn profile(src: &str) -> Profiler {
+    let profiler = Profiler::new();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.hooks = Some(Box::new(profiler.clone()));
+    let _ = interp.eval(Parser::new(src).parse_program().unwrap());
+    profiler
+}
+
+#[test]
+fn counts_calls_time_and_collection_clones() {
+    let profiler = profile(SCRIPT);
+    let functions = profiler.functions();
+    let get = |name: &str| functions.iter().find(|f| f.name == name).unwrap();
+
+    assert_eq!(get("fib").calls, 15);
+    assert_eq!(get("quest").calls, 1);
+    assert_eq!(get("<script>").calls, 1);
+    // Each read of `items` and each assignment to it clones the list
+    assert_eq!(get("bag").collection_clones, 7);
+    assert_eq!(get("quest").collection_clones, 0);
+    // Recursive calls count once towards inclusive time
+    assert!(get("fib").inclusive <= get("quest").inclusive);
+    assert!(get("quest").exclusive <= get("quest").inclusive);
+    assert!(get("quest").inclusive <= get("<script>").inclusive);
+    // Most exclusive time first
+    assert!(functions
+        .windows(2)
+        .all(|w| w[0].exclusive >= w[1].exclusive));
+
+    let folded = profiler.to_folded();
+    let stacks: Vec<&str> = folded
+        .lines()
+        .map(|l| l.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(
+        stacks,
+        vec![
+            "<script>",
+            "<script>;quest",
+            "<script>;quest;bag",
+            "<script>;quest;fib",
+            "<script>;quest;fib;fib",
+            "<script>;quest;fib;fib;fib",
+            "<script>;quest;fib;fib;fib;fib",
+            "<script>;quest;fib;fib;fib;fib;fib",
+        ]
+    );
+}
+
+#[test]
+fn errors_close_open_functions() {
+    let src = "fn inner() -> null {\n  return nope;\n}\nfn outer() -> null {\n  return inner();\n}\nouter();\n";
+    let profiler = profile(src);
+    let table = profiler.to_table();
+    assert!(
+        table.starts_with("Function     Calls       Incl ms       Excl ms  Collection clones\n")
+    );
+    let names: Vec<&str> = table
+        .lines()
+        .skip(1)
+        .map(|l| l.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(names.len(), 3);
+    assert!(names.contains(&"outer") && names.contains(&"inner"));
+    assert!(profiler.to_folded().contains("<script>;outer;inner "));
+}
+
+#[test]
+fn failed_calls_close_their_functions() {
+    let src = "fn broken() -> null {\n  return nope;\n}\nfn fails() -> null {\n  return broken();\n}\nfn ok() -> number {\n  return 1;\n}\n";
+    let profiler = Profiler::new();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.hooks = Some(Box::new(profiler.clone()));
+    interp
+        .run(&Parser::new(src).parse_program().unwrap())
+        .unwrap();
+    assert!(interp.call("fails", Vec::new()).is_err());
+    interp.call("ok", Vec::new()).unwrap();
+    let folded = profiler.to_folded();
+    let stacks: Vec<&str> = folded
+        .lines()
+        .map(|l| l.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(
+        stacks,
+        vec![
+            "<script>",
+            "<script>;fails",
+            "<script>;fails;broken",
+            "<script>;ok"
+        ]
+    );
+}
+
+#[test]
+fn qk_run_profile() {
+    let dir = std::env::temp_dir().join(format!("qk-profile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("quest.qk");
+    std::fs::write(&script, SCRIPT).unwrap();
+    let folded = dir.join("quest.folded");
+    let out = Command::new(env!("CARGO_BIN_EXE_qk"))
+        .arg("run")
+        .arg(format!("--profile={}", folded.display()))
+        .arg(&script)
+        .output()
+        .expect("run qk");
+    assert_eq!(out.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Function     Calls"));
+    assert!(stderr
+        .lines()
+        .any(|l| l.starts_with("fib ") && l.contains(" 15 ")));
+    let stacks = std::fs::read_to_string(folded).unwrap();
+    assert!(stacks.lines().any(|l| l.starts_with("<script>;quest;bag ")));
+}
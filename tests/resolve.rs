@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use questicle::ast::{ExprKind, Frame, Slot, SlotNames, StmtKind};
+use questicle::output::Capture;
+use questicle::{Host, Interpreter, Parser};
+
+fn run(src: &str) -> String {
+    let capture = Capture::default();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.set_output(capture.clone());
+    if let Err(e) = interp.eval(Parser::new(src).parse_program().unwrap()) {
+        return format!("{}error: {}\n", capture.text(), e);
+    }
+    capture.text()
+}
+
+#[test]
+fn uses_get_slots_only_when_the_binding_is_certain() {
+    // Parsing resolves the program
+    let program = Parser::new(
+        "let g: any = 1;\nfn f(a) {\n  let b: any = a + g;\n  let h: any = fn() { return b + c; };\n  let c: any = 2;\n  return h();\n}\n",
+    )
+    .parse_program()
+    .unwrap();
+    let StmtKind::Let { init, .. } = &program.statements[1].kind else {
+        panic!("fn declaration");
+    };
+    let ExprKind::Fn(f) = &init.kind else {
+        panic!("fn literal");
+    };
+    let StmtKind::Let { init, slot, .. } = &f.body[0].kind else {
+        panic!("let b");
+    };
+    assert_eq!(*slot, Slot::Local { depth: 0, index: 1 });
+    let ExprKind::Binary { left, right, .. } = &init.kind else {
+        panic!("a + g");
+    };
+    assert!(matches!(
+        left.kind,
+        ExprKind::Var(_, Slot::Local { depth: 0, index: 0 })
+    ));
+    assert!(matches!(right.kind, ExprKind::Var(_, Slot::Global)));
+    // `b` is bound when the closure is made; `c` only later
+    let StmtKind::Let { init, .. } = &f.body[1].kind else {
+        panic!("let h");
+    };
+    let ExprKind::Fn(f) = &init.kind else {
+        panic!("closure");
+    };
+    let StmtKind::Return(Some(sum)) = &f.body[0].kind else {
+        panic!("return");
+    };
+    let ExprKind::Binary { left, right, .. } = &sum.kind else {
+        panic!("b + c");
+    };
+    assert!(matches!(
+        left.kind,
+        ExprKind::Var(_, Slot::Local { depth: 1, index: 1 })
+    ));
+    assert!(matches!(right.kind, ExprKind::Var(_, Slot::Dynamic)));
+}
+
+#[test]
+fn scoping_is_unchanged() {
+    let out = run(r#"let x: any = 1;
+fn f() {
+  let h: any = fn() { return later; };
+  let later: any = "later";
+  let fact: any = fn(n) { if (n < 2) { return 1; } return n * fact(n - 1); };
+  print(h(), fact(5));
+}
+f();
+{
+  print(x);
+  let x: any = x + 10;
+  print(x);
+  let x: any = x + 100;
+  print(x);
+}
+print(x);
+fn shadow() {
+  let before: any = y;
+  let y: any = "local";
+  return before + " " + y;
+}
+let y: any = "global";
+print(shadow());
+fn dup(a, a) { return a; }
+print(dup(1, 2));
+let fns: any = [];
+for (i in [1, 2, 3]) { fns = push(fns, fn() { return i * 10; }); }
+print(fns[0](), fns[2]());
+fn count() {
+  let n: any = 0;
+  return fn() { n = n + 1; return n; };
+}
+let c: any = count();
+c();
+print(c(), x);
+"#);
+    assert_eq!(
+        out,
+        "later 120\n1\n11\n111\n1\nglobal local\n2\n10 30\n2 1\n"
+    );
+}
+
+#[test]
+fn undefined_names_still_fail_at_runtime() {
+    assert_eq!(
+        run("fn f() {\n  print(1);\n  missing = 2;\n}\nf();\n"),
+        "1\nerror: Undefined variable 'missing'\n"
+    );
+    assert_eq!(
+        run("{ let a: any = 1; }\nprint(a);\n"),
+        "error: Undefined variable 'a'\n"
+    );
+}
+
+#[test]
+fn blocks_without_lets_are_marked_to_share_their_frame() {
+    let program =
+        Parser::new("{ print(1); }\n{ let x: any = 2; print(x); }\nwhile (false) { print(3); }\n")
+            .parse_program()
+            .unwrap();
+    let frames: Vec<Frame> = program
+        .statements
+        .iter()
+        .map(|s| match &s.kind {
+            StmtKind::Block(_, frame) => frame.clone(),
+            StmtKind::While { body, .. } => match &body.kind {
+                StmtKind::Block(_, frame) => frame.clone(),
+                _ => panic!("loop body"),
+            },
+            _ => panic!("block"),
+        })
+        .collect();
+    let x: SlotNames = [Arc::from("x")].into();
+    assert_eq!(
+        frames,
+        vec![Frame::Enclosing, Frame::Own(x), Frame::Enclosing]
+    );
+}
+
+#[test]
+fn frames_are_laid_out_with_their_slot_names() {
+    let names = |names: &[&str]| -> SlotNames { names.iter().map(|&n| Arc::from(n)).collect() };
+    // Parameters come first, then the lets in order; a repeated `let` reuses its slot
+    let program = Parser::new(
+        "fn f(a, b) {\n  let c: any = a;\n  for (i in [b]) { let d: any = i; }\n  let c: any = b;\n}\n",
+    )
+    .parse_program()
+    .unwrap();
+    let StmtKind::Let { init, .. } = &program.statements[0].kind else {
+        panic!("fn");
+    };
+    let ExprKind::Fn(f) = &init.kind else {
+        panic!("fn literal");
+    };
+    assert_eq!(f.slots, names(&["a", "b", "c"]));
+    let StmtKind::For { slots, .. } = &f.body[1].kind else {
+        panic!("for loop");
+    };
+    assert_eq!(*slots, names(&["i"]));
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use questicle::ast::{ExprKind, StmtKind};
+use questicle::output::Capture;
+use questicle::value::{Function, Value};
+use questicle::{Host, Interpreter, Parser};
+
+#[test]
+fn closures_share_their_body_with_the_program() {
+    let program = Parser::new(
+        r#"let makers: list<any> = [];
+for (i in [1, 2, 3]) {
+  makers = push(makers, fn() { return i * 10; });
+}
+makers;
+"#,
+    )
+    .parse_program()
+    .unwrap();
+    let mut interp = Interpreter::with_host(Host::default());
+    let Some(Value::List(makers)) = interp.run(&program).unwrap() else {
+        panic!("list of closures");
+    };
+    let StmtKind::For { body, .. } = &program.statements[1].kind else {
+        panic!("for loop");
+    };
+    let StmtKind::Block(body, _) = &body.kind else {
+        panic!("loop body");
+    };
+    let StmtKind::Expr(e) = &body[0].kind else {
+        panic!("push");
+    };
+    let ExprKind::Assign { value, .. } = &e.kind else {
+        panic!("assignment");
+    };
+    let ExprKind::Call { args, .. } = &value.kind else {
+        panic!("push call");
+    };
+    let ExprKind::Fn(literal) = &args[1].kind else {
+        panic!("fn literal");
+    };
+    assert_eq!(makers.len(), 3);
+    for maker in &makers {
+        let Value::Function(f) = maker else {
+            panic!("closure");
+        };
+        let Function::User {
+            literal: shared, ..
+        } = f.as_ref()
+        else {
+            panic!("script function");
+        };
+        assert!(Arc::ptr_eq(literal, shared));
+    }
+    assert_eq!(Arc::strong_count(literal), 4);
+}
+
+#[test]
+fn one_parsed_program_runs_in_many_interpreters() {
+    let program = Parser::new(
+        r#"let visits: number = 0;
+fn visit(n: number) -> number {
+  let total: number = 0;
+  for (i in [1, 2, 3]) { total = total + i * n; }
+  visits = visits + 1;
+  return total;
+}
+print(visit(1), visit(2), visits);
+"#,
+    )
+    .parse_program()
+    .unwrap();
+    for _ in 0..3 {
+        let capture = Capture::default();
+        let mut interp = Interpreter::with_host(Host::default());
+        interp.set_output(capture.clone());
+        interp.run(&program).unwrap();
+        assert_eq!(capture.text(), "6 12 2\n");
+    }
+}
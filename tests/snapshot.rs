@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::fs;
+use std::process::Command;
+use std::rc::Rc;
+
+use questicle::output::Capture;
+use questicle::testing::{self, Outcome, TestOptions};
+use questicle::{Host, Interpreter, Parser};
+
+const QUEST: &str =
+    "print(\"quest started\");\nlet gold: number = 3;\nprint(\"gold\", gold * 10);\nmissing();\n";
+
+#[test]
+fn print_goes_to_the_output_sink() {
+    let capture = Capture::default();
+    let lines: Rc<RefCell<Vec<String>>> = Rc::default();
+    let mut interp = Interpreter::with_host(Host::default());
+    interp.set_output(capture.clone());
+    let program = Parser::new("print(\"a\", 1);\nprint([true]);\n")
+        .parse_program()
+        .unwrap();
+    interp.eval(program.clone()).unwrap();
+    assert_eq!(capture.text(), "a 1\n[true]\n");
+
+    let sink = lines.clone();
+    interp.set_output(move |line: &str| sink.borrow_mut().push(line.to_string()));
+    interp.eval(program).unwrap();
+    assert_eq!(*lines.borrow(), vec!["a 1", "[true]"]);
+    assert_eq!(capture.text(), "a 1\n[true]\n");
+}
+
+#[test]
+fn snapshot_compares_and_blesses() {
+    let dir = std::env::temp_dir().join(format!("qk-snapshot-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("quest.qk");
+    fs::write(&script, QUEST).unwrap();
+    let snapshot = TestOptions {
+        snapshot: true,
+        ..Default::default()
+    };
+    // Without a .out file there is nothing to compare
+    assert!(testing::run_file(&script, &snapshot).results.is_empty());
+
+    let bless = TestOptions {
+        bless: true,
+        ..Default::default()
+    };
+    assert!(testing::run_file(&script, &bless).passed());
+    assert_eq!(
+        fs::read_to_string(dir.join("quest.out")).unwrap(),
+        "quest started\ngold 30\nRuntime error: Undefined variable 'missing'\n"
+    );
+    assert!(testing::run_file(&script, &snapshot).passed());
+
+    fs::write(dir.join("quest.out"), "quest started\ngold 20\n").unwrap();
+    let report = testing::run_file(&script, &snapshot);
+    let Outcome::Failed(message) = &report.results[0].outcome else {
+        panic!("expected a failed snapshot");
+    };
+    assert!(message.contains("line 2:\n  - gold 20\n  + gold 30\n"));
+    assert!(message.ends_with("line 3: extra\n  + Runtime error: Undefined variable 'missing'"));
+}
+
+#[test]
+fn examples_match_their_snapshots() {
+    let out = Command::new(env!("CARGO_BIN_EXE_qk"))
+        .args(["test", "--snapshot", "examples"])
+        .output()
+        .expect("run qk");
+    let text = String::from_utf8_lossy(&out.stdout).into_owned();
+    assert_eq!(out.status.code(), Some(0), "{}", text);
+    assert!(text.contains("examples/quests.qk\n  ok    snapshot"));
+}
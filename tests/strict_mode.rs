@@ -0,0 +1,75 @@
+use questicle::typecheck::{check_program_with_options, has_strict_pragma, CheckOptions};
+use questicle::Parser;
+
+fn strict_errors(src: &str) -> Vec<String> {
+    let program = Parser::new(src).parse_program().expect("parse");
+    let tc = check_program_with_options(
+        &program,
+        &CheckOptions {
+            strict: true,
+            ..Default::default()
+        },
+    );
+    assert!(tc.strict);
+    tc.errors.into_iter().map(|e| e.message).collect()
+}
+
+#[test]
+fn fully_typed_script_passes() {
+    let src = r#"
+fn fact(n: number) -> number {
+  if (n <= 1) {
+    return 1;
+  }
+  return n * fact(n - 1);
+}
+let x: number = fact(5);
+"#;
+    assert!(strict_errors(src).is_empty(), "{:?}", strict_errors(src));
+}
+
+#[test]
+fn top_level_functions_need_annotations() {
+    let errs = strict_errors("fn f(a) { return a; }");
+    assert!(
+        errs.iter().any(|m| m.contains("Parameter 'a'")),
+        "{:?}",
+        errs
+    );
+    assert!(errs.iter().any(|m| m.contains("return type")), "{:?}", errs);
+}
+
+#[test]
+fn truthiness_conditions_rejected() {
+    let errs = strict_errors("let n: number = 1; if (n) { print(n); }");
+    assert!(errs.iter().any(|m| m.contains("If condition must be bool")));
+    let errs = strict_errors("let a: any = 1; while (!a) { break; }");
+    assert!(errs.iter().any(|m| m.contains("Unary ! expects bool")));
+}
+
+#[test]
+fn unknown_names_are_implicit_any() {
+    let errs = strict_errors("let y: number = missing + 1;");
+    assert!(
+        errs.iter().any(|m| m.contains("implicit any")),
+        "{:?}",
+        errs
+    );
+}
+
+#[test]
+fn non_strict_stays_permissive() {
+    let program = Parser::new("fn f(a) { if (a) { return a; } }")
+        .parse_program()
+        .expect("parse");
+    let tc = check_program_with_options(&program, &CheckOptions::default());
+    assert!(tc.errors.is_empty());
+    assert!(!tc.strict);
+}
+
+#[test]
+fn pragma_detection() {
+    assert!(has_strict_pragma("// qk: strict\nlet a: number = 1;"));
+    assert!(has_strict_pragma("\n// header\n//qk:strict\n"));
+    assert!(!has_strict_pragma("let a: number = 1;\n// qk: strict"));
+}
@@ -0,0 +1,145 @@
+use questicle::symbols::{analyze, RefKind, SymbolKind, SymbolTable};
+use questicle::Parser;
+
+fn table(src: &str) -> SymbolTable {
+    analyze(&Parser::new(src).parse_program().expect("parse"))
+}
+
+// (line, col) of the definition of the name at a 1-based position
+fn definition_of(t: &SymbolTable, line: usize, col: usize) -> Option<(usize, usize)> {
+    let r = t.reference_at(line, col)?;
+    t.definition(r).map(|s| (s.span.line, s.span.col))
+}
+
+#[test]
+fn shadowed_names_resolve_to_the_innermost_binding() {
+    let src = r#"let x: number = 1;
+fn f(x: number) -> number {
+  return x + 1;
+}
+{
+  let x: string = "inner";
+  print(x);
+}
+print(x);
+"#;
+    let t = table(src);
+    assert_eq!(definition_of(&t, 3, 10), Some((2, 6)));
+    assert_eq!(definition_of(&t, 7, 9), Some((6, 7)));
+    assert_eq!(definition_of(&t, 9, 7), Some((1, 5)));
+    let param = t.definition(t.reference_at(3, 10).unwrap()).unwrap();
+    assert_eq!(param.kind, SymbolKind::Parameter);
+}
+
+#[test]
+fn function_bodies_see_later_declarations_and_themselves() {
+    let src = r#"fn a() -> number {
+  return b() + a();
+}
+fn b() -> number {
+  return 1;
+}
+"#;
+    let t = table(src);
+    assert_eq!(definition_of(&t, 2, 10), Some((4, 4)));
+    assert_eq!(definition_of(&t, 2, 16), Some((1, 4)));
+}
+
+#[test]
+fn references_and_write_kinds() {
+    let src = r#"let hp: number = 10;
+hp = hp - 1;
+for (i in [1, 2]) {
+  print(i + hp);
+}
+print(len([1]));
+"#;
+    let t = table(src);
+    let r = t.reference_at(1, 5).unwrap();
+    let kinds: Vec<(usize, RefKind)> = t
+        .references_to(r)
+        .iter()
+        .map(|o| (o.span.line, o.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (1, RefKind::Decl),
+            (2, RefKind::Write),
+            (2, RefKind::Read),
+            (4, RefKind::Read)
+        ]
+    );
+    // Builtins are unresolved and grouped by name
+    let print = t.reference_at(4, 3).unwrap();
+    assert!(print.symbol.is_none());
+    assert_eq!(t.references_to(print).len(), 2);
+    // A cursor right after a name still finds it
+    assert_eq!(t.reference_at(1, 7).map(|r| r.name.as_str()), Some("hp"));
+}
+
+#[test]
+fn rename_conflicts_are_detected() {
+    let src = r#"let count: number = 0;
+let total: number = 1;
+fn bump(step: number) -> number {
+  let inner: number = step;
+  return count + inner + total;
+}
+"#;
+    let t = table(src);
+    let count = t.reference_at(1, 5).unwrap().symbol.unwrap();
+    let inner = t.reference_at(4, 7).unwrap().symbol.unwrap();
+    let step = t.reference_at(3, 9).unwrap().symbol.unwrap();
+
+    assert!(t
+        .rename_conflict(count, "while")
+        .unwrap()
+        .contains("keyword"));
+    assert!(t
+        .rename_conflict(count, "print")
+        .unwrap()
+        .contains("builtin"));
+    assert!(t.rename_conflict(count, "9lives").is_some());
+    assert!(t
+        .rename_conflict(count, "total")
+        .unwrap()
+        .contains("already declared"));
+    // The use of count inside bump would be captured by the local `inner`
+    assert!(t
+        .rename_conflict(count, "inner")
+        .unwrap()
+        .contains("shadowed"));
+    // Renaming the local to `total` would hijack the use of the global total
+    assert!(t
+        .rename_conflict(inner, "total")
+        .unwrap()
+        .contains("would refer"));
+    assert_eq!(t.rename_conflict(step, "amount"), None);
+    assert_eq!(t.rename_conflict(count, "score"), None);
+}
+
+#[test]
+fn renaming_a_shared_name_checks_scripts_that_use_it() {
+    // A script using `heal`, declared by another script
+    let t = table(
+        r#"let hp: number = 3;
+fn rest(mend: number) -> number {
+  return heal(mend) + bonus;
+}
+"#,
+    );
+    assert!(t
+        .global_rename_conflict("heal", "hp")
+        .unwrap()
+        .contains("already declared"));
+    assert!(t
+        .global_rename_conflict("heal", "mend")
+        .unwrap()
+        .contains("shadowed"));
+    assert!(t
+        .global_rename_conflict("heal", "bonus")
+        .unwrap()
+        .contains("would refer"));
+    assert_eq!(t.global_rename_conflict("heal", "cure"), None);
+}
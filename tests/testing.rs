@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use questicle::testing::{self, Outcome, TestOptions};
+use questicle::{Host, Interpreter, Parser};
+
+const QUEST: &str = r#"fn reward(level: number) -> record { gold: number, items: list<string> } {
+  if (level < 0) {
+    return nope;
+  }
+  return { gold: level * 10, items: ["potion"] };
+}
+let runs: number = 0;
+runs = runs + 1;
+
+test "rewards scale with level" {
+  assert_eq(reward(2), { gold: 20, items: ["potion"] });
+  assert(reward(1).gold > 0, "some gold");
+}
+
+test "rewards differ" {
+  assert_eq(reward(3), { gold: 20, items: ["potion", "sword"] });
+}
+
+test "negative levels throw" {
+  let msg: string = assert_throws(fn() { reward(-1); }, "Undefined");
+  assert_eq(msg, "Undefined variable 'nope'");
+  assert_throws(fn() { level; }, "Undefined variable 'level'");
+  assert_throws(fn() { reward(1); });
+}
+
+test "each test starts fresh" {
+  assert_eq(runs, 1);
+  runs = 5;
+  missing();
+}
+"#;
+
+fn outcomes(opts: &TestOptions) -> Vec<(String, Outcome)> {
+    let report = testing::run_source(QUEST, opts);
+    assert!(report.error.is_none());
+    report
+        .results
+        .into_iter()
+        .map(|r| (r.name, r.outcome))
+        .collect()
+}
+
+#[test]
+fn assertions_report_failures_and_errors() {
+    let results = outcomes(&TestOptions::default());
+    assert_eq!(
+        results,
+        vec![
+            ("rewards scale with level".into(), Outcome::Passed),
+            (
+                "rewards differ".into(),
+                Outcome::Failed(
+                    "Assertion failed: values differ\n  .gold: expected 20, got 30\n  .items[1]: missing, expected \"sword\"".into()
+                )
+            ),
+            (
+                "negative levels throw".into(),
+                Outcome::Failed(
+                    "Assertion failed: expected an error, got {gold: 10, items: [\"potion\"]}"
+                        .into()
+                )
+            ),
+            (
+                "each test starts fresh".into(),
+                Outcome::Error("Undefined variable 'missing'".into())
+            ),
+        ]
+    );
+
+    let filtered = outcomes(&TestOptions {
+        filter: Some("scale".into()),
+        ..Default::default()
+    });
+    assert_eq!(filtered.len(), 1);
+
+    // Running the script skips its tests
+    let mut interp = Interpreter::with_host(Host::default());
+    interp
+        .eval(Parser::new(QUEST).parse_program().unwrap())
+        .expect("tests are skipped");
+}
+
+#[test]
+fn junit_report() {
+    let mut report = testing::run_source(QUEST, &TestOptions::default());
+    report.path = PathBuf::from("quest.qk");
+    let xml = testing::to_junit(&[report]);
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites tests=\"4\" failures=\"2\" errors=\"1\""));
+    assert!(xml.contains("<testcase name=\"rewards differ\" classname=\"quest.qk\""));
+    assert!(xml.contains(
+        "<failure message=\"Assertion failed: values differ\">Assertion failed: values differ\n  .gold: expected 20, got 30\n  .items[1]: missing, expected &quot;sword&quot;</failure>"
+    ));
+    assert!(xml.contains("<error message=\"Undefined variable 'missing'\">"));
+}
+
+#[test]
+fn qk_test_runs_scripts_under_a_path() {
+    let dir = std::env::temp_dir().join(format!("qk-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("quest.qk"), QUEST).unwrap();
+    fs::write(dir.join("plain.qk"), "let x: number = 1;\n").unwrap();
+    let run = |args: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_qk"))
+            .arg("test")
+            .args(args)
+            .arg(&dir)
+            .output()
+            .expect("run qk");
+        (
+            out.status.code(),
+            String::from_utf8_lossy(&out.stdout).into_owned(),
+        )
+    };
+
+    let junit = dir.join("results.xml");
+    let (code, text) = run(&[&format!("--junit={}", junit.display())]);
+    assert_eq!(code, Some(1));
+    assert!(text.contains("  FAIL  rewards differ"));
+    assert!(text.contains("          .items[1]: missing, expected \"sword\""));
+    assert!(text.ends_with("\n1 passed, 2 failed, 1 errors in 1 files\n"));
+    assert!(fs::read_to_string(junit)
+        .unwrap()
+        .contains("<testsuites tests=\"4\""));
+
+    let (code, text) = run(&["--filter=scale"]);
+    assert_eq!(code, Some(0));
+    assert!(text.contains("  ok    rewards scale with level"));
+}
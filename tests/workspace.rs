@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use questicle::lint::{self, LintConfig};
+use questicle::symbols::RefKind;
+use questicle::workspace::WorkspaceIndex;
+
+// A fresh directory under the system temp dir holding the given files
+fn scratch_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qk-index-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (file, content) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).expect("create scratch dir");
+        fs::write(path, content).expect("write scratch file");
+    }
+    dir
+}
+
+const HEALING: &str = "fn heal(amount: number) -> number {\n  return amount + 1;\n}\n";
+const QUEST: &str = "// qk: strict\nlet hp: number = heal(3);\nprint(heal(hp));\n";
+
+#[test]
+fn scripts_are_indexed_with_cross_file_names() {
+    let dir = scratch_dir(
+        "names",
+        &[
+            ("lib/healing.qk", HEALING),
+            ("quest.qk", QUEST),
+            (".hidden/skip.qk", "let x: number = 1;\n"),
+        ],
+    );
+    let mut index = WorkspaceIndex::default();
+    assert_eq!(index.scan(std::slice::from_ref(&dir)), 2);
+    let (healing, quest) = (dir.join("lib/healing.qk"), dir.join("quest.qk"));
+
+    assert_eq!(
+        index.dependencies(&quest),
+        BTreeSet::from([healing.clone()])
+    );
+    assert_eq!(index.dependents(&healing), BTreeSet::from([quest.clone()]));
+    // The strict script sees heal's type from the other script
+    assert!(index.file(&quest).unwrap().diagnostics().is_empty());
+
+    let found = index.symbols("HEA");
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        (found[0].0, found[0].1.name.as_str()),
+        (healing.as_path(), "heal")
+    );
+
+    let defs = index.definitions("heal");
+    assert_eq!((defs[0].0, defs[0].1.span.line), (healing.as_path(), 1));
+
+    let refs: Vec<(PathBuf, usize, RefKind)> = index
+        .references("heal")
+        .into_iter()
+        .map(|(p, r)| (p.to_path_buf(), r.span.line, r.kind))
+        .collect();
+    assert_eq!(
+        refs,
+        vec![
+            (healing.clone(), 1, RefKind::Decl),
+            (quest.clone(), 2, RefKind::Read),
+            (quest.clone(), 3, RefKind::Read),
+        ]
+    );
+}
+
+#[test]
+fn edits_recheck_dependent_scripts() {
+    let dir = scratch_dir("edits", &[("healing.qk", HEALING), ("quest.qk", QUEST)]);
+    let mut index = WorkspaceIndex::default();
+    index.scan(std::slice::from_ref(&dir));
+    let (healing, quest) = (dir.join("healing.qk"), dir.join("quest.qk"));
+
+    // Unchanged text re-checks nothing
+    assert!(index.update(&healing, HEALING.to_string()).is_empty());
+
+    // Renaming heal leaves quest.qk with an unknown name
+    let renamed = HEALING.replace("heal", "mend");
+    let checked = index.update(&healing, renamed);
+    assert_eq!(checked, vec![healing.clone(), quest.clone()]);
+    let diags = index.file(&quest).unwrap().diagnostics();
+    assert!(diags.iter().any(|d| d.message.contains("'heal'")));
+    assert!(index.dependencies(&quest).is_empty());
+
+    // Deleting the file on disk drops it from the index
+    fs::remove_file(&healing).unwrap();
+    index.reload(&healing);
+    assert!(index.file(&healing).is_none());
+}
+
+#[test]
+fn handler_edits_republish_emitters() {
+    let dir = scratch_dir(
+        "events",
+        &[
+            ("quest.qk", "emit(\"quest_done\", 1);\n"),
+            ("rewards.qk", "print(\"ready\");\n"),
+        ],
+    );
+    let mut index = WorkspaceIndex::default();
+    index.scan(std::slice::from_ref(&dir));
+    let (quest, rewards) = (dir.join("quest.qk"), dir.join("rewards.qk"));
+    let unhandled = |index: &WorkspaceIndex| {
+        let file = index.file(&quest).unwrap();
+        lint::lint_program_in(
+            file.program().unwrap(),
+            &file.source,
+            &LintConfig::default(),
+            &index.event_graph(),
+        )
+        .iter()
+        .filter(|d| d.code == Some("unhandled-event"))
+        .count()
+    };
+    assert_eq!(unhandled(&index), 1);
+
+    // Adding a handler in rewards.qk changes what quest.qk reports, so both come back
+    let handled = "on(\"quest_done\", fn(id) { print(id); });\n";
+    let checked = index.update(&rewards, handled.to_string());
+    assert_eq!(checked, vec![quest.clone(), rewards.clone()]);
+    assert_eq!(unhandled(&index), 0);
+
+    // Other edits to rewards.qk leave quest.qk alone; removing the handler does not
+    let checked = index.update(&rewards, format!("{}print(1);\n", handled));
+    assert_eq!(checked, vec![rewards.clone()]);
+    let checked = index.update(&rewards, "print(2);\n".to_string());
+    assert_eq!(checked, vec![quest.clone(), rewards.clone()]);
+    assert_eq!(unhandled(&index), 1);
+}